@@ -1,8 +1,10 @@
 mod init_flags;
 mod device_flags;
+mod stream_flags;
 
 pub use init_flags::*;
 pub use device_flags::*;
+pub use stream_flags::*;
 
 
 pub trait ToBassFlags<T> {
@@ -0,0 +1,20 @@
+use crate::prelude::*;
+
+/// Flags used when creating a [`StreamChannel`]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StreamFlags {
+    /// Scan the whole file for accurate seeking and length
+    Prescan,
+    /// Don't play the stream, only decode it with [`Channel::read_samples`]
+    Decode,
+    /// Decode as 32-bit floating-point
+    Float,
+    /// Decode to mono
+    Mono,
+}
+crate::__impl_BassFlags!(StreamFlags, [
+    (BASS_STREAM_PRESCAN, StreamFlags::Prescan),
+    (BASS_STREAM_DECODE, StreamFlags::Decode),
+    (BASS_SAMPLE_FLOAT, StreamFlags::Float),
+    (BASS_SAMPLE_MONO, StreamFlags::Mono)
+]);
@@ -0,0 +1,62 @@
+//! Bindings to the BASS_FX add-on
+//!
+//! The library is loaded from the directory of the executable, same as bass itself
+//! (`bass_fx.dll`, `libbass_fx.so` or `libbass_fx.dylib`)
+#![allow(non_snake_case)]
+use libloading::{Library, Symbol};
+use once_cell::sync::Lazy;
+
+use crate::prelude::*;
+
+// tempo attributes
+pub const BASS_ATTRIB_TEMPO: u32 = 0x10000;
+pub const BASS_ATTRIB_TEMPO_PITCH: u32 = 0x10001;
+pub const BASS_ATTRIB_TEMPO_FREQ: u32 = 0x10002;
+pub const BASS_ATTRIB_TEMPO_OPTION_USE_AA_FILTER: u32 = 0x10010;
+pub const BASS_ATTRIB_TEMPO_OPTION_AA_FILTER_LENGTH: u32 = 0x10011;
+pub const BASS_ATTRIB_TEMPO_OPTION_USE_QUICKALGO: u32 = 0x10012;
+pub const BASS_ATTRIB_TEMPO_OPTION_SEQUENCE_MS: u32 = 0x10013;
+pub const BASS_ATTRIB_TEMPO_OPTION_SEEKWINDOW_MS: u32 = 0x10014;
+pub const BASS_ATTRIB_TEMPO_OPTION_OVERLAP_MS: u32 = 0x10015;
+pub const BASS_ATTRIB_REVERSE_DIR: u32 = 0x11000;
+
+/// free the source channel when the fx channel is freed
+pub const BASS_FX_FREESOURCE: u32 = 0x10000;
+
+static BASS_FX_LIBRARY: Lazy<Option<Library>> = Lazy::new(|| {
+    let mut path = std::env::current_exe().ok()?;
+    path.pop();
+    path.push(format!("{}bass_fx{}", std::env::consts::DLL_PREFIX, std::env::consts::DLL_SUFFIX));
+
+    // safety: bass_fx has no initialization which could misbehave when loaded
+    unsafe { Library::new(path) }.ok()
+});
+
+/// Returns an error if the library or the function couldn't be loaded
+fn symbol<T>(name: &[u8]) -> BassResult<Symbol<'static, T>> {
+    let library = BASS_FX_LIBRARY.as_ref().ok_or(BassError::Notavail)?;
+
+    // safety: callers name the function with its signature from bass_fx.h
+    unsafe { library.get(name) }.map_err(|_| BassError::Notavail)
+}
+
+/// Check if the bass_fx library is available
+pub fn is_bass_fx_available() -> bool {
+    BASS_FX_LIBRARY.is_some()
+}
+
+/// Version of the loaded bass_fx library
+///
+/// Returns an error if the library is not available
+pub fn BASS_FX_GetVersion() -> BassResult<u32> {
+    let get_version = symbol::<extern "C" fn() -> u32>(b"BASS_FX_GetVersion\0")?;
+    Ok(get_version())
+}
+
+/// Create a tempo channel from a decoding channel, returns 0 on error like bass functions
+///
+/// Returns an error if the library is not available
+pub fn BASS_FX_TempoCreate(chan: u32, flags: u32) -> BassResult<u32> {
+    let create = symbol::<extern "C" fn(u32, u32) -> u32>(b"BASS_FX_TempoCreate\0")?;
+    Ok(create(chan, flags))
+}
@@ -1,6 +1,6 @@
 #![allow(unused_variables, dead_code)]
 use once_cell::sync::Lazy;
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, sync::{Arc, Mutex}};
 
 use crate::prelude::*;
 
@@ -15,11 +15,21 @@ use crate::prelude::*;
 /// This object is clone-able, and the underlying channel will be preserved.
 /// 
 /// Once the final reference has been dropped, the underlying channel will be freed
-#[derive(Clone, PartialEq)]
+#[derive(Clone)]
 pub struct Channel {
     // probably shouldnt be pub but whatever
     pub handle: Arc<u32>,
     pub default_frequency: f32,
+
+    /// sync callbacks set on this channel, kept alive until the final reference is dropped
+    pub(crate) syncs: Arc<Mutex<SyncList>>,
+    /// dsp callbacks set on this channel, kept alive until the final reference is dropped
+    pub(crate) dsps: Arc<Mutex<DspList>>,
+}
+impl PartialEq for Channel {
+    fn eq(&self, other: &Self) -> bool {
+        self.handle == other.handle && self.default_frequency == other.default_frequency
+    }
 }
 impl Channel {
     /// Create a new channel, where `handle` is the handle of the underlying bass channel
@@ -34,7 +44,9 @@ impl Channel {
 
         Self {
             handle: Arc::new(handle),
-            default_frequency
+            default_frequency,
+            syncs: Arc::new(Mutex::new(SyncList::new(handle))),
+            dsps: Arc::new(Mutex::new(DspList::new(handle))),
         }
     }
 
@@ -54,6 +66,30 @@ impl Channel {
         Ok(())
     }
 
+    /// Slide a channel attribute from its current value to `value` over `time` ms, in the background
+    ///
+    /// Sliding the attribute again replaces the running slide
+    ///
+    /// Returns an error if there was a problem starting the slide (ie value is out of bounds for attribute)
+    pub fn slide_attribute(&self, attrib: ChannelAttribute, value: f32, time: u32) -> BassResult<()> {
+        check_bass_err!(BASS_ChannelSlideAttribute(*self.handle, attrib.into(), value, time));
+        Ok(())
+    }
+
+    /// Check if the attribute is sliding, started with [`Channel::slide_attribute`]
+    pub fn is_sliding(&self, attrib: ChannelAttribute) -> bool {
+        BASS_ChannelIsSliding(*self.handle, attrib.into()) != 0
+    }
+
+    /// Get the number of audio channels, ie 2 for stereo
+    /// 
+    /// Returns an error if the channel is not valid
+    pub fn get_channel_count(&self) -> BassResult<u32> {
+        let mut info = BassChannelInfo::new(0, 0, 0, 0, 0, 0, 0, std::ptr::null());
+        check_bass_err!(BASS_ChannelGetInfo(*self.handle, &mut info));
+        Ok(info.channels)
+    }
+
     /// Get the length of the channel in bytes
     /// 
     /// Returns an error if the length is not avaiable
@@ -161,6 +197,15 @@ impl Channel {
         Ok(data)
     }
 
+    /// Decode sample data from a decoding channel (ie one created with [`StreamFlags::Decode`]) into `buffer`, as floats
+    /// 
+    /// Returns the number of samples written, or an error if the end of the channel was reached
+    pub fn read_samples(&self, buffer: &mut [f32]) -> BassResult<usize> {
+        let length = std::mem::size_of_val(buffer) as u32;
+        let read = check_bass_err_val!(BASS_ChannelGetData(*self.handle, buffer.as_mut_ptr() as *mut c_void, length | BASS_DATA_FLOAT), u32::MAX);
+        Ok(read as usize / std::mem::size_of::<f32>())
+    }
+
 
     // convenience functions
 
@@ -184,6 +229,16 @@ impl Channel {
         self.set_attribute(ChannelAttribute::Volume, vol)
     }
 
+    /// Slide the volume for this channel over `time` ms
+    /// 
+    /// Alias for 
+    /// ```ignore
+    /// self.slide_attribute(Volume, vol, time)
+    /// ```
+    pub fn slide_volume(&self, vol: f32, time: u32) -> BassResult<()> {
+        self.slide_attribute(ChannelAttribute::Volume, vol, time)
+    }
+
 
     /// Get the volume for this channel
     /// 
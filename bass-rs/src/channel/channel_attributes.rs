@@ -34,6 +34,7 @@ impl Into<u32> for ChannelAttribute {
 }
 
 #[cfg(feature="bass_fx")] 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum TempoAttribute {
     /// The tempo of a channel, [-95%...0...+5000%] percents.
     Tempo,
@@ -56,3 +57,22 @@ pub enum TempoAttribute {
     /// Sets the playback direction of a reverse channel (-1=reverse, 1=forward, or use one of the BASSFXReverse flags).
     ReverseDir,
 }
+#[cfg(feature="bass_fx")] 
+impl Into<u32> for TempoAttribute {
+    fn into(self) -> u32 {
+        use TempoAttribute::*;
+
+        match self {
+            Tempo => BASS_ATTRIB_TEMPO,
+            TempoPitch => BASS_ATTRIB_TEMPO_PITCH,
+            TempoFrequency => BASS_ATTRIB_TEMPO_FREQ,
+            TempoUseAAFilter => BASS_ATTRIB_TEMPO_OPTION_USE_AA_FILTER,
+            TempoAAFilterLength => BASS_ATTRIB_TEMPO_OPTION_AA_FILTER_LENGTH,
+            TempoUseQuickAlgorithm => BASS_ATTRIB_TEMPO_OPTION_USE_QUICKALGO,
+            TempoSequenceMs => BASS_ATTRIB_TEMPO_OPTION_SEQUENCE_MS,
+            TempoSeekWindowMs => BASS_ATTRIB_TEMPO_OPTION_SEEKWINDOW_MS,
+            TempoOverlapMs => BASS_ATTRIB_TEMPO_OPTION_OVERLAP_MS,
+            ReverseDir => BASS_ATTRIB_REVERSE_DIR,
+        }
+    }
+}
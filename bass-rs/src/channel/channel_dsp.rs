@@ -0,0 +1,107 @@
+use std::sync::Mutex;
+
+use crate::prelude::*;
+
+pub(crate) type DspCallback = Mutex<Box<dyn FnMut(&mut [f32]) + Send>>;
+
+/// DSP function registered with [`Channel::set_dsp`]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DspId(pub(crate) u32);
+
+/// a registered dsp, the callback lives as long as this does
+struct DspEntry {
+    id: DspId,
+    callback: Box<DspCallback>,
+}
+
+/// All DSP functions set on a channel
+///
+/// Removes remaining DSP functions from bass when dropped, before their callbacks are freed
+pub(crate) struct DspList {
+    channel: u32,
+    entries: Vec<DspEntry>,
+    /// Callbacks of removed functions, bass may still be running them until the channel is freed
+    removed: Vec<Box<DspCallback>>,
+}
+impl DspList {
+    pub(crate) fn new(channel: u32) -> Self {
+        Self {
+            channel,
+            entries: Vec::new(),
+            removed: Vec::new(),
+        }
+    }
+}
+impl Drop for DspList {
+    fn drop(&mut self) {
+        for entry in self.entries.iter() {
+            // the channel may be already freed, and the dsp with it
+            BASS_ChannelRemoveDSP(self.channel, entry.id.0);
+        }
+    }
+}
+
+extern "C" fn dsp_proc(_handle: u32, _channel: u32, buffer: *mut c_void, length: u32, user: *mut c_void) {
+    if buffer.is_null() || length == 0 {
+        return;
+    }
+
+    // safety: float dsp is enabled before any dsp is set, so the buffer holds `length` bytes of floats
+    let samples = unsafe { std::slice::from_raw_parts_mut(buffer as *mut f32, length as usize / std::mem::size_of::<f32>()) };
+    // safety: user is a pointer to the boxed callback owned by the channel
+    let callback = unsafe { &*(user as *const DspCallback) };
+    // a callback which is still running is skipped instead of waited for
+    if let Ok(mut callback) = callback.try_lock() {
+        callback(samples)
+    }
+}
+
+impl Channel {
+    /// Set a DSP function on this channel, which can modify the sample data before it is played (or decoded)
+    ///
+    /// The callback gets interleaved float samples. This enables float DSP (`BASS_CONFIG_FLOATDSP`) for all channels,
+    /// which the built-in effects support as well.
+    /// Functions with higher `priority` are called first, effects set with [`Channel::set_fx`] share the same chain
+    ///
+    /// The callback is called from a bass thread, and is kept alive as long as the channel is, even if it is removed
+    ///
+    /// Returns an error if the function couldn't be set
+    /// ```ignore
+    /// // swap left and right
+    /// stream.set_dsp(0, |samples| samples.chunks_exact_mut(2).for_each(|frame| frame.swap(0, 1)))?;
+    /// ```
+    pub fn set_dsp<F: FnMut(&mut [f32]) + Send + 'static>(&self, priority: i32, callback: F) -> BassResult<DspId> {
+        check_bass_err!(BASS_SetConfig(BASS_CONFIG_FLOATDSP, 1));
+        let callback: Box<DspCallback> = Box::new(Mutex::new(Box::new(callback)));
+
+        let handle = check_bass_err!(BASS_ChannelSetDSP(
+            *self.handle,
+            dsp_proc as *mut DSPPROC,
+            callback.as_ref() as *const DspCallback as *mut c_void,
+            priority
+        ));
+
+        let id = DspId(handle);
+        self.dsps.lock().unwrap().entries.push(DspEntry {
+            id,
+            callback
+        });
+        Ok(id)
+    }
+
+    /// Remove a DSP function set with [`Channel::set_dsp`]
+    ///
+    /// Bass may be running the callback on another thread, so it is freed along with the channel
+    ///
+    /// Returns an error if the function does not exist on this channel
+    pub fn remove_dsp(&self, id: DspId) -> BassResult<()> {
+        let mut dsps = self.dsps.lock().unwrap();
+        check_bass_err!(BASS_ChannelRemoveDSP(*self.handle, id.0));
+
+        if let Some(index) = dsps.entries.iter().position(|entry| entry.id == id) {
+            let entry = dsps.entries.remove(index);
+            dsps.removed.push(entry.callback);
+        }
+        Ok(())
+    }
+}
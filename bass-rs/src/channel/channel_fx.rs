@@ -0,0 +1,237 @@
+use std::marker::PhantomData;
+
+use crate::prelude::*;
+
+/// Parameters of an effect type, see [`Channel::set_fx`]
+///
+/// Implemented for the parameter structs of the built-in effects, which have the same layout as the bass structs
+pub trait FxParams: Copy + Default {
+    /// `BASS_FX_xxx` type of the effect
+    const KIND: u32;
+}
+
+/// Parametric equalizer band (`BASS_DX8_PARAMEQ`)
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ParamEqFx {
+    /// Center frequency, 80 to 16000 Hz (but below a third of the channel's sample rate)
+    pub center: f32,
+    /// Bandwidth, 1 to 36 semitones
+    pub bandwidth: f32,
+    /// Gain, -15 to +15 dB
+    pub gain: f32,
+}
+impl Default for ParamEqFx {
+    fn default() -> Self {
+        Self { center: 1000.0, bandwidth: 12.0, gain: 0.0 }
+    }
+}
+impl ParamEqFx {
+    /// Lowest center frequency bass accepts, in Hz
+    pub const MIN_CENTER: f32 = 80.0;
+}
+impl FxParams for ParamEqFx {
+    const KIND: u32 = BASS_FX_DX8_PARAMEQ;
+}
+
+/// Dynamic range compressor (`BASS_DX8_COMPRESSOR`)
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CompressorFx {
+    /// Output gain after compression, -60 to +60 dB
+    pub gain: f32,
+    /// Time before compression reaches its full value, 0.01 to 500 ms
+    pub attack: f32,
+    /// Time before compression stops after input drops below threshold, 50 to 3000 ms
+    pub release: f32,
+    /// Point at which compression begins, -60 to 0 dB
+    pub threshold: f32,
+    /// Compression ratio, 1 to 100
+    pub ratio: f32,
+    /// Time after threshold is reached before attack phase is started, 0 to 4 ms
+    pub predelay: f32,
+}
+impl Default for CompressorFx {
+    fn default() -> Self {
+        Self { gain: 0.0, attack: 10.0, release: 200.0, threshold: -20.0, ratio: 3.0, predelay: 4.0 }
+    }
+}
+impl FxParams for CompressorFx {
+    const KIND: u32 = BASS_FX_DX8_COMPRESSOR;
+}
+
+/// Music reverb (`BASS_DX8_REVERB`)
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ReverbFx {
+    /// Input gain, -96 to 0 dB
+    pub in_gain: f32,
+    /// Reverb mix, -96 to 0 dB
+    pub reverb_mix: f32,
+    /// Reverb time, 0.001 to 3000 ms
+    pub reverb_time: f32,
+    /// High frequency reverb time ratio, 0.001 to 0.999
+    pub high_freq_rt_ratio: f32,
+}
+impl Default for ReverbFx {
+    fn default() -> Self {
+        Self { in_gain: 0.0, reverb_mix: 0.0, reverb_time: 1000.0, high_freq_rt_ratio: 0.001 }
+    }
+}
+impl FxParams for ReverbFx {
+    const KIND: u32 = BASS_FX_DX8_REVERB;
+}
+
+/// Echo (`BASS_DX8_ECHO`)
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct EchoFx {
+    /// Ratio of wet (processed) signal to dry (unprocessed) signal, 0 to 100
+    pub wet_dry_mix: f32,
+    /// Percentage of output fed back into input, 0 to 100
+    pub feedback: f32,
+    /// Delay for left channel, 1 to 2000 ms
+    pub left_delay: f32,
+    /// Delay for right channel, 1 to 2000 ms
+    pub right_delay: f32,
+    /// Swap left and right delays with each successive echo, 0 or 1
+    pub pan_delay: i32,
+}
+impl Default for EchoFx {
+    fn default() -> Self {
+        Self { wet_dry_mix: 50.0, feedback: 50.0, left_delay: 500.0, right_delay: 500.0, pan_delay: 0 }
+    }
+}
+impl FxParams for EchoFx {
+    const KIND: u32 = BASS_FX_DX8_ECHO;
+}
+
+/// Chorus (`BASS_DX8_CHORUS`)
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ChorusFx {
+    /// Ratio of wet (processed) signal to dry (unprocessed) signal, 0 to 100
+    pub wet_dry_mix: f32,
+    /// Percentage by which the delay time is modulated by the low-frequency oscillator, 0 to 100
+    pub depth: f32,
+    /// Percentage of output signal to feed back into the effect's input, -99 to 99
+    pub feedback: f32,
+    /// Frequency of the low-frequency oscillator, 0 to 10 Hz
+    pub frequency: f32,
+    /// Waveform of the low-frequency oscillator, 0 = triangle, 1 = sine
+    pub waveform: u32,
+    /// Number of milliseconds the input is delayed before it is played back, 0 to 20 ms
+    pub delay: f32,
+    /// Phase differential between left and right oscillators, `BASS_DX8_PHASE_xxx` (0 to 4)
+    pub phase: u32,
+}
+impl Default for ChorusFx {
+    fn default() -> Self {
+        Self { wet_dry_mix: 50.0, depth: 10.0, feedback: 25.0, frequency: 1.1, waveform: 1, delay: 16.0, phase: 3 }
+    }
+}
+impl FxParams for ChorusFx {
+    const KIND: u32 = BASS_FX_DX8_CHORUS;
+}
+
+/// Volume level (`BASS_FX_VOLUME_PARAM`), applied in the effect chain instead of the channel volume
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct VolumeFx {
+    /// New volume to reach, 0 = silent, 1 = normal, above 1 = amplification
+    pub target: f32,
+    /// Volume to start a slide from, -1 = current
+    pub current: f32,
+    /// Time to slide to the new volume in seconds, 0 = no slide
+    pub time: f32,
+    /// Curve to use for the slide, 0 = linear, 1 = logarithmic
+    pub curve: u32,
+}
+impl Default for VolumeFx {
+    fn default() -> Self {
+        Self { target: 1.0, current: -1.0, time: 0.0, curve: 0 }
+    }
+}
+impl FxParams for VolumeFx {
+    const KIND: u32 = BASS_FX_VOLUME;
+}
+
+/// Effect set on a channel with [`Channel::set_fx`]
+///
+/// The effect is freed along with its channel
+#[derive(PartialEq, Eq, Debug)]
+pub struct Fx<P: FxParams> {
+    handle: u32,
+    _params: PhantomData<P>,
+}
+impl<P: FxParams> Clone for Fx<P> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<P: FxParams> Copy for Fx<P> {}
+impl<P: FxParams> Fx<P> {
+    /// Set the parameters of the effect
+    ///
+    /// Returns an error if a parameter is out of range
+    pub fn set_parameters(&self, params: &P) -> BassResult<()> {
+        check_bass_err!(BASS_FxSetParameters(self.handle, params as *const P as *const c_void));
+        Ok(())
+    }
+
+    /// Get the current parameters of the effect
+    ///
+    /// Returns an error if the effect is not valid anymore
+    pub fn get_parameters(&self) -> BassResult<P> {
+        let mut params = P::default();
+        check_bass_err!(BASS_FXGetParameters(self.handle, &mut params as *mut P as *mut c_void));
+        Ok(params)
+    }
+
+    /// Change the position of the effect in the chain, effects with higher priority are applied first
+    ///
+    /// Returns an error if the effect is not valid anymore
+    pub fn set_priority(&self, priority: i32) -> BassResult<()> {
+        check_bass_err!(BASS_FXSetPriority(self.handle, priority));
+        Ok(())
+    }
+
+    /// Clear the effect state (ie reverb tail), for example after seeking
+    pub fn reset(&self) {
+        BASS_FXReset(self.handle);
+    }
+}
+
+impl Channel {
+    /// Add an effect to the channel, with default parameters of the effect
+    ///
+    /// Effects with higher `priority` are applied first, DSP functions set with [`Channel::set_dsp`] share the same chain
+    ///
+    /// Returns an error if the effect is not available (ie bass was built without it)
+    /// ```ignore
+    /// let eq = stream.set_fx::<ParamEqFx>(0)?;
+    /// eq.set_parameters(&ParamEqFx { center: 125.0, bandwidth: 12.0, gain: 3.0 })?;
+    /// ```
+    pub fn set_fx<P: FxParams>(&self, priority: i32) -> BassResult<Fx<P>> {
+        let handle = check_bass_err!(BASS_ChannelSetFX(*self.handle, P::KIND, priority));
+        Ok(Fx {
+            handle,
+            _params: PhantomData,
+        })
+    }
+
+    /// Same as [`Channel::set_fx`], and sets the effect parameters
+    pub fn set_fx_with<P: FxParams>(&self, params: &P, priority: i32) -> BassResult<Fx<P>> {
+        let fx = self.set_fx::<P>(priority)?;
+        fx.set_parameters(params)?;
+        Ok(fx)
+    }
+
+    /// Remove an effect set with [`Channel::set_fx`]
+    ///
+    /// Returns an error if the effect does not belong to this channel
+    pub fn remove_fx<P: FxParams>(&self, fx: Fx<P>) -> BassResult<()> {
+        check_bass_err!(BASS_ChannelRemoveFX(*self.handle, fx.handle));
+        Ok(())
+    }
+}
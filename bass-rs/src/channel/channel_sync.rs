@@ -0,0 +1,184 @@
+use std::sync::{Mutex, atomic::{AtomicBool, Ordering}};
+
+use crate::prelude::*;
+
+/// Callback of a sync, bass gets a pointer to it
+pub(crate) struct SyncCallback {
+    callback: Mutex<Box<dyn FnMut(u32) + Send>>,
+    one_time: bool,
+    /// Set once a one-time sync has run, bass doesn't call it anymore
+    fired: AtomicBool,
+}
+
+/// Event which triggers a sync callback
+///
+/// See [`here`](https://www.un4seen.com/doc/#bass/BASS_ChannelSetSync.html) for more info
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SyncType {
+    /// The channel has reached the end
+    End,
+    /// Playback (or decoding) has reached the position, in ms
+    Position(f64),
+    /// Playback of a stream has stalled (data is `0`) or resumed (data is `1`)
+    Stall,
+    /// Download of an internet stream has finished
+    Download,
+    /// Metadata of a Shoutcast/Icecast stream has been received
+    Meta,
+    /// A slide of an attribute has ended (data is the attribute), see [`Channel::slide_attribute`]
+    Slide,
+    /// The channel has been freed
+    Free,
+}
+impl SyncType {
+    fn kind(&self) -> u32 {
+        match self {
+            SyncType::End => BASS_SYNC_END,
+            SyncType::Position(_) => BASS_SYNC_POS,
+            SyncType::Stall => BASS_SYNC_STALL,
+            SyncType::Download => BASS_SYNC_DOWNLOAD,
+            SyncType::Meta => BASS_SYNC_META,
+            SyncType::Slide => BASS_SYNC_SLIDE,
+            SyncType::Free => BASS_SYNC_FREE,
+        }
+    }
+
+    fn param(&self, channel: &Channel) -> BassResult<u64> {
+        match self {
+            SyncType::Position(ms) => Ok(channel.seconds2bytes(ms / 1000.0)?.into_len()),
+            _ => Ok(0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SyncFlags {
+    /// Call the callback as soon as the event is processed (mixed), instead of when it is heard
+    MixTime,
+    /// Remove the sync after it has been triggered once
+    OneTime,
+    /// Call the callback in a separate thread, only useful with [`SyncFlags::MixTime`]
+    Thread,
+}
+crate::__impl_BassFlags!(SyncFlags, [
+    (BASS_SYNC_MIXTIME, SyncFlags::MixTime),
+    (BASS_SYNC_ONETIME, SyncFlags::OneTime),
+    (BASS_SYNC_THREAD, SyncFlags::Thread)
+]);
+
+/// Sync registered with [`Channel::set_sync`]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SyncId(pub(crate) u32);
+
+/// a registered sync, the callback lives as long as this does
+struct SyncEntry {
+    id: SyncId,
+    callback: Box<SyncCallback>,
+}
+
+/// All syncs set on a channel
+///
+/// Removes remaining syncs from bass when dropped, before their callbacks are freed
+pub(crate) struct SyncList {
+    channel: u32,
+    entries: Vec<SyncEntry>,
+    /// Callbacks of removed syncs, bass may still be running them until the channel is freed
+    removed: Vec<Box<SyncCallback>>,
+}
+impl SyncList {
+    pub(crate) fn new(channel: u32) -> Self {
+        Self {
+            channel,
+            entries: Vec::new(),
+            removed: Vec::new(),
+        }
+    }
+
+    /// Frees callbacks of one-time syncs which have run
+    fn prune(&mut self) {
+        self.entries.retain(|entry| !entry.callback.fired.load(Ordering::Acquire));
+    }
+}
+impl Drop for SyncList {
+    fn drop(&mut self) {
+        for entry in self.entries.iter() {
+            // the channel may be already freed, and the syncs with it
+            BASS_ChannelRemoveSync(self.channel, entry.id.0);
+        }
+    }
+}
+
+extern "C" fn sync_proc(_handle: u32, _channel: u32, data: u32, user: *mut c_void) {
+    // safety: user is a pointer to the boxed callback owned by the channel
+    let sync = unsafe { &*(user as *const SyncCallback) };
+    // the sync fired again while its callback runs (ie it set the position of its own channel), skip it
+    if let Ok(mut callback) = sync.callback.try_lock() {
+        callback(data)
+    }
+    // nothing touches the callback after this, so it can be freed
+    if sync.one_time {
+        sync.fired.store(true, Ordering::Release);
+    }
+}
+
+impl Channel {
+    /// Set a sync callback on this channel, the callback gets the sync data as the argument
+    ///
+    /// The callback is called from a bass thread, and is kept alive as long as the channel is.
+    /// Once the final reference to the channel is dropped, the sync is removed.
+    /// Callbacks of [`SyncFlags::OneTime`] syncs are freed with the next change to the channel syncs after they ran.
+    /// A sync which triggers while its callback still runs is skipped
+    ///
+    /// Returns an error if the sync couldn't be set
+    /// ```ignore
+    /// let (tx, rx) = std::sync::mpsc::channel();
+    /// stream.set_sync(SyncType::End, move |_| tx.send(()).unwrap())?;
+    /// ```
+    pub fn set_sync<F: FnMut(u32) + Send + 'static>(&self, sync: SyncType, callback: F) -> BassResult<SyncId> {
+        self.set_sync_with_flags(sync, Vec::new(), callback)
+    }
+
+    /// Same as [`Channel::set_sync`], with additional [`SyncFlags`]
+    pub fn set_sync_with_flags<F: FnMut(u32) + Send + 'static>(&self, sync: SyncType, flags: Vec<SyncFlags>, callback: F) -> BassResult<SyncId> {
+        let param = sync.param(self)?;
+        let callback = Box::new(SyncCallback {
+            callback: Mutex::new(Box::new(callback)),
+            one_time: flags.contains(&SyncFlags::OneTime),
+            fired: AtomicBool::new(false),
+        });
+
+        let handle = check_bass_err!(BASS_ChannelSetSync(
+            *self.handle,
+            sync.kind() | flags.to_num(),
+            param,
+            sync_proc as *mut SYNCPROC,
+            callback.as_ref() as *const SyncCallback as *mut c_void
+        ));
+
+        let id = SyncId(handle);
+        let mut syncs = self.syncs.lock().unwrap();
+        syncs.prune();
+        syncs.entries.push(SyncEntry {
+            id,
+            callback
+        });
+        Ok(id)
+    }
+
+    /// Remove a sync set with [`Channel::set_sync`]
+    ///
+    /// Bass may be running the callback on another thread, so it is freed along with the channel
+    ///
+    /// Returns an error if the sync does not exist (ie it was a [`SyncFlags::OneTime`] sync which was already triggered)
+    pub fn remove_sync(&self, id: SyncId) -> BassResult<()> {
+        let mut syncs = self.syncs.lock().unwrap();
+        syncs.prune();
+        check_bass_err!(BASS_ChannelRemoveSync(*self.handle, id.0));
+
+        if let Some(index) = syncs.entries.iter().position(|entry| entry.id == id) {
+            let entry = syncs.entries.remove(index);
+            syncs.removed.push(entry.callback);
+        }
+        Ok(())
+    }
+}
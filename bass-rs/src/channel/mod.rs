@@ -1,11 +1,23 @@
 mod channel;
 mod music_channel;
 mod stream_channel;
+mod stream_reader;
 mod sample_channel;
 mod channel_attributes;
+mod channel_sync;
+mod channel_fx;
+mod channel_dsp;
+#[cfg(feature="bass_fx")]
+mod tempo_channel;
 
 pub use channel::*;
 pub use music_channel::*;
 pub use stream_channel::*;
+pub(crate) use stream_reader::*;
 pub use sample_channel::*;
-pub use channel_attributes::*;
\ No newline at end of file
+pub use channel_attributes::*;
+pub use channel_sync::*;
+pub use channel_fx::*;
+pub use channel_dsp::*;
+#[cfg(feature="bass_fx")]
+pub use tempo_channel::*;
\ No newline at end of file
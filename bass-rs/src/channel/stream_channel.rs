@@ -12,7 +12,8 @@ use crate::prelude::*;
 /// 
 /// # Usage
 /// 
-/// Create a new stream channel with [`StreamChannel::create_from_memory`](#method.create_from_memory)
+/// Create a new stream channel with [`StreamChannel::create_from_memory`](#method.create_from_memory),
+/// or [`StreamChannel::from_reader`] to read the data through any [`std::io::Read`]
 /// 
 /// See [`Channel`] for further documentation
 /// 
@@ -23,7 +24,9 @@ pub struct StreamChannel {
     pub channel: Channel,
 
     /// needed so the data stays in memory while its needed by bass
-    _data: Arc<Vec<u8>>
+    _data: Arc<Vec<u8>>,
+    /// reader of streams created with [`StreamChannel::from_reader`], bass reads through it as long as the stream exists
+    _reader: Option<Arc<ReaderSlot>>,
 }
 impl StreamChannel {
     /// Create a StreamChannel from bytes in memory
@@ -33,13 +36,22 @@ impl StreamChannel {
     /// channel.play().expect("error playing channel");
     /// ```
     pub fn load_from_memory(bytes: Vec<u8>, offset: impl IntoLen) -> BassResult<Self> {
+        Self::load_from_memory_with_flags(bytes, offset, vec![StreamFlags::Prescan])
+    }
+
+    /// Create a StreamChannel from bytes in memory, with custom [`StreamFlags`]
+    /// ```ignore
+    /// let bytes = std::fs::read(path.as_ref())?;
+    /// let channel = StreamChannel::load_from_memory_with_flags(bytes, 0i32, vec![StreamFlags::Decode])?;
+    /// ```
+    pub fn load_from_memory_with_flags(bytes: Vec<u8>, offset: impl IntoLen, flags: Vec<StreamFlags>) -> BassResult<Self> {
         // create the stream
         let handle = bass_sys::BASS_StreamCreateFile(
             true.ibool(),
             bytes.as_ptr() as *const c_void,
             offset.into_len(),
             bytes.len() as u64,
-            BASS_STREAM_PRESCAN
+            flags.to_num()
         );
         // check for an error when creating the stream
         check_bass_err!(handle);
@@ -52,7 +64,8 @@ impl StreamChannel {
         println!("created stream channel id: {}", handle);
         Ok(Self {
             channel: Channel::new(handle),
-            _data: Arc::new(bytes)
+            _data: Arc::new(bytes),
+            _reader: None,
         })
     }
 
@@ -81,18 +94,28 @@ impl StreamChannel {
         // should be good to go from here
         Ok(Self {
             channel: Channel::new(handle),
-            _data: Arc::new(Vec::new())
+            _data: Arc::new(Vec::new()),
+            _reader: None,
         })
     }
 
     /// Create a StreamChannel from a URL
     pub fn load_from_url(url: impl AsRef<str>, offset: impl IntoLen) -> BassResult<Self> {
+        Self::load_from_url_with_flags(url, offset, vec![StreamFlags::Prescan])
+    }
+
+    /// Create a StreamChannel from a URL, with custom [`StreamFlags`]
+    /// ```ignore
+    /// // source for a TempoChannel
+    /// let channel = StreamChannel::load_from_url_with_flags(url, 0i32, vec![StreamFlags::Prescan, StreamFlags::Decode])?;
+    /// ```
+    pub fn load_from_url_with_flags(url: impl AsRef<str>, offset: impl IntoLen, flags: Vec<StreamFlags>) -> BassResult<Self> {
         let url = url.as_ref();
         // create the stream
         let handle = bass_sys::BASS_StreamCreateURL(
             url.as_ptr() as *const i8,
             offset.into_len() as u32,
-            BASS_STREAM_PRESCAN,
+            flags.to_num(),
             null::<DOWNLOADPROC>() as _,
             null_mut() as *mut c_void,
         );
@@ -105,10 +128,20 @@ impl StreamChannel {
         // should be good to go from here
         Ok(Self {
             channel: Channel::new(handle),
-            _data: Arc::new(Vec::new())
+            _data: Arc::new(Vec::new()),
+            _reader: None,
         })
     }
 
+    /// Wraps a stream reading through `reader`
+    pub(crate) fn with_reader(handle: u32, reader: Arc<ReaderSlot>) -> Self {
+        Self {
+            channel: Channel::new(handle),
+            _data: Arc::new(Vec::new()),
+            _reader: Some(reader),
+        }
+    }
+
     // pub fn create(freq: u64, ) -> BassResult<Self> {
     //     BASS_StreamCreate(freq, channels, flags, )
     // }
@@ -0,0 +1,152 @@
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::{Arc, Mutex};
+
+use crate::prelude::*;
+
+/// Source of file data for [`StreamChannel::from_reader`] and [`StreamChannel::from_buffered_reader`]
+pub(crate) trait FileSource: Send {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize>;
+    /// `false` if the position can't be reached
+    fn seek(&mut self, offset: u64) -> bool;
+    /// Length of the file, 0 if unknown
+    fn length(&self) -> u64;
+}
+
+struct Seekable<R> {
+    reader: R,
+    /// position of the reader when the stream was created, bass offsets are relative to it
+    start: u64,
+    length: u64,
+}
+impl<R: Read + Seek + Send> FileSource for Seekable<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        self.reader.read(buffer)
+    }
+    fn seek(&mut self, offset: u64) -> bool {
+        self.reader.seek(SeekFrom::Start(self.start + offset)).is_ok()
+    }
+    fn length(&self) -> u64 {
+        self.length
+    }
+}
+
+struct Sequential<R> {
+    reader: R,
+    length: Option<u64>,
+}
+impl<R: Read + Send> FileSource for Sequential<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        self.reader.read(buffer)
+    }
+    fn seek(&mut self, _offset: u64) -> bool {
+        // bass seeks within the data it has buffered
+        false
+    }
+    fn length(&self) -> u64 {
+        self.length.unwrap_or(0)
+    }
+}
+
+/// Reader owned by a stream, bass calls the file procs with a pointer to it from its own threads
+pub(crate) type ReaderSlot = Mutex<Box<dyn FileSource>>;
+
+/// # Safety
+/// `user` has to be a pointer to the [`ReaderSlot`] of a live stream
+unsafe fn reader_slot<'a>(user: *mut c_void) -> &'a ReaderSlot {
+    &*(user as *const ReaderSlot)
+}
+
+extern "C" fn file_close(_user: *mut c_void) {
+    // the reader is dropped along with the stream
+}
+
+extern "C" fn file_length(user: *mut c_void) -> u64 {
+    // safety: user is the reader of the stream being read
+    let slot = unsafe { reader_slot(user) };
+    slot.lock().map(|reader| reader.length()).unwrap_or(0)
+}
+
+extern "C" fn file_read(buffer: *mut c_void, length: u32, user: *mut c_void) -> u32 {
+    // safety: user is the reader of the stream being read, and buffer holds `length` bytes
+    let slot = unsafe { reader_slot(user) };
+    let buffer = unsafe { std::slice::from_raw_parts_mut(buffer as *mut u8, length as usize) };
+    let Ok(mut reader) = slot.lock() else {
+        return u32::MAX;
+    };
+
+    // bass takes a short read as the end of the file, so fill as much as the reader has
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(_) if filled > 0 => break,
+            Err(_) => return u32::MAX,
+        }
+    }
+    filled as u32
+}
+
+extern "C" fn file_seek(offset: u64, user: *mut c_void) -> i32 {
+    // safety: user is the reader of the stream being read
+    let slot = unsafe { reader_slot(user) };
+    slot.lock().map(|mut reader| reader.seek(offset)).unwrap_or(false).ibool()
+}
+
+impl StreamChannel {
+    /// Create a StreamChannel reading from `reader`, with custom [`StreamFlags`]
+    ///
+    /// The data is read on demand from the thread which decodes the stream, so slow readers cause playback to stall.
+    /// The reader is kept alive as long as the stream is
+    ///
+    /// Returns an error if the data is not in a supported format, or the reader can't report its length
+    /// ```ignore
+    /// let file = std::fs::File::open("test.mp3")?;
+    /// let channel = StreamChannel::from_reader(file, vec![StreamFlags::Prescan])?;
+    /// ```
+    pub fn from_reader<R: Read + Seek + Send + 'static>(mut reader: R, flags: Vec<StreamFlags>) -> BassResult<Self> {
+        let start = reader.stream_position().map_err(|_| BassError::FileOpen)?;
+        let length = reader.seek(SeekFrom::End(0)).map_err(|_| BassError::FileOpen)? - start;
+        reader.seek(SeekFrom::Start(start)).map_err(|_| BassError::FileOpen)?;
+
+        Self::from_file_source(Seekable { reader, start, length }, STREAMFILE_NOBUFFER, flags)
+    }
+
+    /// Create a StreamChannel reading from a reader which can't seek (ie a network response), with custom [`StreamFlags`]
+    ///
+    /// Bass reads ahead from `reader` in a background thread, and keeps the data in a buffer, so seeking is possible within the downloaded data.
+    /// `length` is the total size of the data if known (ie from `Content-Length`), needed for the length of the stream
+    ///
+    /// Returns an error if the data is not in a supported format
+    /// ```ignore
+    /// let response = agent.get(url).call()?;
+    /// let length = response.header("Content-Length").and_then(|len| len.parse().ok());
+    /// let channel = StreamChannel::from_buffered_reader(response.into_reader(), length, vec![])?;
+    /// ```
+    pub fn from_buffered_reader<R: Read + Send + 'static>(reader: R, length: Option<u64>, flags: Vec<StreamFlags>) -> BassResult<Self> {
+        Self::from_file_source(Sequential { reader, length }, STREAMFILE_BUFFER, flags)
+    }
+
+    fn from_file_source<S: FileSource + 'static>(source: S, system: u32, flags: Vec<StreamFlags>) -> BassResult<Self> {
+        let reader: Arc<ReaderSlot> = Arc::new(Mutex::new(Box::new(source)));
+        let mut procs = BassFileProcs::new(
+            file_close as *mut FILECLOSEPROC,
+            file_length as *mut FILELENPROC,
+            file_read as *mut FILEREADPROC,
+            file_seek as *mut FILESEEKPROC,
+        );
+
+        // create the stream, bass copies the procs
+        let handle = check_bass_err!(BASS_StreamCreateFileUser(
+            system,
+            flags.to_num(),
+            &mut procs,
+            Arc::as_ptr(&reader) as *mut c_void
+        ));
+
+        #[cfg(feature="drop_debug")]
+        println!("created reader stream channel id: {}", handle);
+        Ok(Self::with_reader(handle, reader))
+    }
+}
@@ -0,0 +1,108 @@
+use std::ops::Deref;
+use std::sync::Arc;
+
+use crate::prelude::*;
+
+/// ## Tempo channel
+///
+/// Plays a decoding [`StreamChannel`] with changed tempo and pitch, independently of each other.
+/// Needs the `bass_fx` feature, and the bass_fx library next to the executable
+///
+/// See [`here`](https://www.un4seen.com/doc/#bass_fx/BASS_FX_TempoCreate.html) for more information
+///
+/// # Usage
+/// ```ignore
+/// let source = StreamChannel::load_from_url_with_flags(url, 0, vec![StreamFlags::Decode])?;
+/// let channel = TempoChannel::new(source)?;
+/// channel.set_speed(1.5)?;
+/// channel.play(true)?;
+/// ```
+///
+/// See [`Channel`] for further documentation
+///
+/// # Dropping
+/// See [`Channel`] for drop behaviour, the source stream is freed after the tempo channel
+#[derive(Clone)]
+pub struct TempoChannel {
+    pub channel: Channel,
+
+    /// the decoding stream the tempo channel reads from
+    source: StreamChannel,
+}
+impl TempoChannel {
+    /// Create a tempo channel playing `source`, which has to be created with [`StreamFlags::Decode`]
+    ///
+    /// Returns an error if bass_fx is not available, or the source is not a decoding channel
+    pub fn new(source: StreamChannel) -> BassResult<Self> {
+        Self::new_with_flags(source, Vec::new())
+    }
+
+    /// Create a tempo channel playing `source`, with custom [`StreamFlags`] (ie [`StreamFlags::Decode`] to decode the tempo channel too)
+    ///
+    /// Returns an error if bass_fx is not available, or the source is not a decoding channel
+    pub fn new_with_flags(source: StreamChannel, flags: Vec<StreamFlags>) -> BassResult<Self> {
+        // the source is freed by its own drop, not with BASS_FX_FREESOURCE
+        let handle = check_bass_err!(BASS_FX_TempoCreate(*source.handle, flags.to_num())?);
+
+        #[cfg(feature="drop_debug")]
+        println!("created tempo channel id: {}", handle);
+        Ok(Self {
+            channel: Channel::new(handle),
+            source,
+        })
+    }
+
+    /// The decoding stream this channel plays
+    pub fn source(&self) -> &StreamChannel {
+        &self.source
+    }
+
+    /// Set playback speed without changing pitch, 1 = normal, 0.5 = half speed, 2 = double speed
+    ///
+    /// Returns an error if the speed is out of range (0.05 to 51)
+    pub fn set_speed(&self, speed: f32) -> BassResult<()> {
+        self.set_attribute(ChannelAttribute::Tempo(TempoAttribute::Tempo), (speed - 1.0) * 100.0)
+    }
+
+    /// Get playback speed, 1 = normal
+    ///
+    /// Returns an error if the channel is not valid anymore
+    pub fn get_speed(&self) -> BassResult<f32> {
+        Ok(self.get_attribute(ChannelAttribute::Tempo(TempoAttribute::Tempo))? / 100.0 + 1.0)
+    }
+
+    /// Set pitch in semitones without changing speed, 0 = original pitch
+    ///
+    /// Returns an error if the pitch is out of range (-60 to 60)
+    pub fn set_pitch(&self, semitones: f32) -> BassResult<()> {
+        self.set_attribute(ChannelAttribute::Tempo(TempoAttribute::TempoPitch), semitones)
+    }
+
+    /// Get pitch in semitones, 0 = original pitch
+    ///
+    /// Returns an error if the channel is not valid anymore
+    pub fn get_pitch(&self) -> BassResult<f32> {
+        self.get_attribute(ChannelAttribute::Tempo(TempoAttribute::TempoPitch))
+    }
+}
+impl Deref for TempoChannel {
+    type Target = Channel;
+
+    fn deref(&self) -> &Self::Target {
+        &self.channel
+    }
+}
+impl Drop for TempoChannel {
+    fn drop(&mut self) {
+        let count = Arc::strong_count(&self.handle);
+        if count == 1 {
+            #[cfg(feature="drop_debug")]
+            println!("dropping tempo channel id: {}", self.channel.handle);
+
+            // tempo channels are streams, freed before the source which is dropped after this
+            if BASS_StreamFree(*self.channel.handle) == 0 {
+                panic!("error dropping tempo channel: {:?}", BassError::get_last_error())
+            }
+        }
+    }
+}
@@ -1,7 +1,11 @@
 #![allow(unused_variables)]
+use once_cell::sync::Lazy;
 use prelude::*;
+use std::{ffi::CString, sync::Mutex};
 
 // mods
+#[cfg(feature="bass_fx")]
+pub mod bass_fx;
 pub mod bass_error;
 pub mod bass_flags;
 pub mod channel;
@@ -117,10 +121,33 @@ impl BassBuilder {
     }
 }
 
+/// keeps proxy string alive while bass may use it
+static NET_PROXY: Lazy<Mutex<Option<CString>>> = Lazy::new(|| Mutex::new(None));
+
 impl Bass {
     pub fn builder() -> BassBuilder {
         BassBuilder::default()
     }
+
+    /// Set proxy server used by URL streams (`BASS_CONFIG_NET_PROXY`)
+    ///
+    /// Format is `[user:pass@]server:port`, `None` disables proxy, empty string uses the OS proxy settings.
+    ///
+    /// Returns an error if proxy string contains a nul byte
+    pub fn set_net_proxy(proxy: Option<&str>) -> BassResult<()> {
+        let mut stored = NET_PROXY.lock().unwrap();
+        *stored = match proxy {
+            Some(proxy) => Some(CString::new(proxy).map_err(|_| BassError::Illparam)?),
+            None => None,
+        };
+
+        let ptr = stored
+            .as_ref()
+            .map(|proxy| proxy.as_ptr() as *mut c_void)
+            .unwrap_or(std::ptr::null_mut());
+        check_bass_err!(BASS_SetConfigPtr(BASS_CONFIG_NET_PROXY, ptr));
+        Ok(())
+    }
 }
 impl Drop for Bass {
     fn drop(&mut self) {
@@ -8,6 +8,8 @@ pub(crate) use std::ffi::c_void;
 pub use crate::bass_error::*;
 
 pub(crate) use bass_sys::*;
+#[cfg(feature="bass_fx")]
+pub(crate) use crate::bass_fx::*;
 
 pub use crate::channel::*;
 
@@ -0,0 +1,64 @@
+use std::sync::{Arc, atomic::{AtomicU32, Ordering}};
+
+use bass_rs::prelude::*;
+
+pub fn fx_tests() -> BassResult<()> {
+    let bytes = std::fs::read("./test.mp3").expect("Error reading ./test.mp3");
+    let stream = StreamChannel::load_from_memory_with_flags(bytes, 0, vec![StreamFlags::Decode, StreamFlags::Float])?;
+
+    // parameters round trip
+    let band = ParamEqFx { center: 125.0, bandwidth: 12.0, gain: 6.0 };
+    let eq = stream.set_fx_with(&band, 1)?;
+    assert_eq!(eq.get_parameters()?, band);
+
+    // bands of the bc_rs equalizer, the lowest ones are raised to the lowest center the effect takes
+    let low = stream.set_fx::<ParamEqFx>(0)?;
+    assert!(low.set_parameters(&ParamEqFx { center: 31.0, ..Default::default() }).is_err(), "band below the lowest center was set");
+    stream.remove_fx(low)?;
+
+    let max_center = stream.default_frequency / 3.0 - 1.0;
+    for center in [31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0] {
+        let band = ParamEqFx {
+            center: f32::max(center, ParamEqFx::MIN_CENTER).min(max_center),
+            bandwidth: 12.0,
+            gain: -12.0,
+        };
+        let eq = stream.set_fx_with(&band, 0)?;
+        assert_eq!(eq.get_parameters()?, band);
+        stream.remove_fx(eq)?;
+    }
+
+    let compressor = stream.set_fx::<CompressorFx>(0)?;
+    compressor.set_parameters(&CompressorFx { threshold: -30.0, ..Default::default() })?;
+    assert_eq!(compressor.get_parameters()?.threshold, -30.0);
+    compressor.set_priority(2)?;
+
+    stream.remove_fx(compressor)?;
+    assert!(compressor.get_parameters().is_err(), "removed effect is still valid");
+
+    // dsp sees the decoded data, and can change it
+    let called = Arc::new(AtomicU32::new(0));
+    let silence = {
+        let called = called.clone();
+        stream.set_dsp(0, move |samples| {
+            called.fetch_add(1, Ordering::SeqCst);
+            samples.fill(0.0);
+        })?
+    };
+
+    let mut buffer = vec![1.0f32; 4096];
+    stream.read_samples(&mut buffer)?;
+    assert!(called.load(Ordering::SeqCst) > 0, "dsp was not called");
+    assert!(buffer.iter().all(|&sample| sample == 0.0), "dsp did not change the data");
+
+    stream.remove_dsp(silence)?;
+    let count = called.load(Ordering::SeqCst);
+    stream.read_samples(&mut buffer)?;
+    assert_eq!(called.load(Ordering::SeqCst), count, "removed dsp was called");
+
+    // callbacks are freed along with the channel
+    drop(stream);
+    assert_eq!(Arc::strong_count(&called), 1);
+
+    Ok(())
+}
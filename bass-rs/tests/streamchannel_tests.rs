@@ -19,10 +19,41 @@ pub fn stream_channel_tests() -> BassResult<()> {
 
     stream.set_position(20.0)?;
     stream.set_volume(0.1)?;
+
+    stream.slide_attribute(ChannelAttribute::Volume, 0.5, 100)?;
+    assert!(stream.is_sliding(ChannelAttribute::Volume), "volume is not sliding");
+    stream.set_volume(0.1)?;
     stream.set_attribute(ChannelAttribute::Pan, -1.0)?;
 
     let current_freq = stream.get_attribute(ChannelAttribute::Frequency)?;
     stream.set_attribute(ChannelAttribute::Frequency, current_freq * 1.7)?;
 
     Ok(())
-}
\ No newline at end of file
+}
+pub fn reader_stream_tests() -> BassResult<()> {
+    let bytes = std::fs::read("./test.mp3").expect("Error reading ./test.mp3");
+    let memory = StreamChannel::load_from_memory_with_flags(bytes.clone(), 0, vec![StreamFlags::Decode])?;
+
+    // same data through a reader, from a position which is not the start
+    let mut padded = vec![0u8; 16];
+    padded.extend_from_slice(&bytes);
+    let mut cursor = std::io::Cursor::new(padded);
+    cursor.set_position(16);
+    let stream = StreamChannel::from_reader(cursor, vec![StreamFlags::Decode])?;
+    assert_eq!(stream.get_length()?, memory.get_length()?);
+
+    stream.set_position(20.0)?;
+    let mut buffer = vec![0.0f32; 4096];
+    assert!(stream.read_samples(&mut buffer)? > 0, "nothing decoded after seeking");
+
+    // a reader which can't seek, with and without known length
+    let stream = StreamChannel::from_buffered_reader(std::io::Cursor::new(bytes.clone()), Some(bytes.len() as u64), vec![StreamFlags::Decode])?;
+    assert!(stream.read_samples(&mut buffer)? > 0, "nothing decoded from buffered reader");
+    let stream = StreamChannel::from_buffered_reader(std::io::Cursor::new(bytes), None, vec![])?;
+    stream.play(false)?;
+
+    // not audio
+    assert!(StreamChannel::from_reader(std::io::Cursor::new(vec![0u8; 1024]), vec![]).is_err(), "stream created from garbage");
+
+    Ok(())
+}
@@ -0,0 +1,48 @@
+use std::sync::{Arc, atomic::{AtomicU32, Ordering}};
+
+use bass_rs::prelude::*;
+
+pub fn sync_tests() -> BassResult<()> {
+    let bytes = std::fs::read("./test.mp3").expect("Error reading ./test.mp3");
+
+    // decode-only streams trigger syncs while decoding, so no output device is needed
+    let stream = StreamChannel::load_from_memory_with_flags(bytes, 0, vec![StreamFlags::Decode, StreamFlags::Prescan])?;
+
+    let ended = Arc::new(AtomicU32::new(0));
+    let reached = Arc::new(AtomicU32::new(0));
+    let removed = Arc::new(AtomicU32::new(0));
+
+    {
+        let ended = ended.clone();
+        stream.set_sync(SyncType::End, move |_| {ended.fetch_add(1, Ordering::SeqCst);})?;
+    }
+    {
+        let reached = reached.clone();
+        stream.set_sync_with_flags(SyncType::Position(100.0), vec![SyncFlags::OneTime], move |_| {reached.fetch_add(1, Ordering::SeqCst);})?;
+    }
+    let removed_sync = {
+        let removed = removed.clone();
+        stream.set_sync(SyncType::Position(50.0), move |_| {removed.fetch_add(1, Ordering::SeqCst);})?
+    };
+    stream.remove_sync(removed_sync)?;
+
+    // decode the whole stream
+    let mut buffer = vec![0.0f32; 4096];
+    while stream.read_samples(&mut buffer).is_ok() {}
+
+    assert_eq!(ended.load(Ordering::SeqCst), 1, "end sync was not triggered");
+    assert_eq!(reached.load(Ordering::SeqCst), 1, "position sync was not triggered");
+    assert_eq!(removed.load(Ordering::SeqCst), 0, "removed sync was triggered");
+
+    // one-time callbacks which ran are freed with the next change, removed ones live as long as the channel
+    stream.set_sync(SyncType::Free, |_| {})?;
+    assert_eq!(Arc::strong_count(&reached), 1, "one-time callback was not freed");
+    assert_eq!(Arc::strong_count(&removed), 2);
+
+    // callbacks are freed along with the channel
+    drop(stream);
+    assert_eq!(Arc::strong_count(&ended), 1);
+    assert_eq!(Arc::strong_count(&removed), 1);
+
+    Ok(())
+}
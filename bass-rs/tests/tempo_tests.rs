@@ -0,0 +1,30 @@
+#![cfg(feature="bass_fx")]
+
+use bass_rs::prelude::*;
+
+pub fn tempo_tests() -> BassResult<()> {
+    let bytes = std::fs::read("./test.mp3").expect("Error reading ./test.mp3");
+    let source = StreamChannel::load_from_memory_with_flags(bytes, 0, vec![StreamFlags::Decode])?;
+    let channel = TempoChannel::new(source)?;
+
+    channel.set_speed(1.5)?;
+    assert!((channel.get_speed()? - 1.5).abs() < 0.001, "speed was not set");
+    channel.set_pitch(-2.0)?;
+    assert_eq!(channel.get_pitch()?, -2.0);
+    assert!(channel.set_speed(100.0).is_err(), "out of range speed was set");
+
+    // same length as the source
+    assert_eq!(channel.get_length()?, channel.source().get_length()?);
+
+    channel.play(true)?;
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    assert_eq!(channel.get_playback_state()?, PlaybackState::Playing);
+    channel.stop()?;
+
+    // a playing stream is not a decoding channel
+    let bytes = std::fs::read("./test.mp3").expect("Error reading ./test.mp3");
+    let playing = StreamChannel::load_from_memory(bytes, 0)?;
+    assert!(TempoChannel::new(playing).is_err(), "tempo channel created from a playing stream");
+
+    Ok(())
+}
@@ -4,6 +4,10 @@ use bass_rs::{*};
 mod device_tests;
 mod samplechannel_tests;
 mod streamchannel_tests;
+mod sync_tests;
+mod fx_tests;
+#[cfg(feature="bass_fx")]
+mod tempo_tests;
 
 macro_rules! __bass_check {
     ($res:expr) => {
@@ -38,6 +42,15 @@ pub fn test() {
 
     __bass_check!(streamchannel_tests::stream_channel_tests());
 
+    __bass_check!(streamchannel_tests::reader_stream_tests());
+
+    __bass_check!(sync_tests::sync_tests());
+
+    __bass_check!(fx_tests::fx_tests());
+
+    #[cfg(feature="bass_fx")]
+    __bass_check!(tempo_tests::tempo_tests());
+
 
     drop(bass);
 }
@@ -63,4 +76,17 @@ fn move_dll() {
     } else {
         panic!("error with current dir for lib")
     }
+
+    // bass_fx is loaded from the exe dir as well
+    #[cfg(feature="bass_fx")]
+    {
+        let filename = format!("{}bass_fx{}", std::env::consts::DLL_PREFIX, std::env::consts::DLL_SUFFIX);
+        let mut library_path = std::env::current_exe().expect("error with current dir for lib");
+        library_path.pop();
+        library_path.push(&filename);
+
+        if !library_path.exists() {
+            std::fs::copy(filename, library_path).expect("error copying bass_fx lib to exe path");
+        }
+    }
 }
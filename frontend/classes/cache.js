@@ -0,0 +1,67 @@
+/** Cache tab of the settings window and "keep offline" downloads */
+class AudioCache {
+    constructor() {
+        $('#cache-clear').on('click', () => {
+            Window.this.xcall("clear_cache");
+            this.render();
+        });
+
+        $('#cache-albums').on('click', 'button', (e) => {
+            Window.this.xcall("cache_unpin_album", $(e.currentTarget).attr('value'));
+            this.render();
+        });
+    }
+
+    render() {
+        const info = JSON.parse(Window.this.xcall("get_cache_info"));
+        const albums = $('#cache-albums');
+        const recent = $('#cache-recent');
+        albums.empty();
+        recent.empty();
+
+        if (!info) {
+            $('#cache-usage').text("Cache is disabled");
+            return;
+        }
+
+        $('#cache-usage').text(`${info.tracks} tracks, ${this.#megabytes(info.used)} of ${this.#megabytes(info.max_size)} MB used in ${info.dir}`);
+
+        for (const album of info.albums) {
+            albums.append(`<p class="cache-item">${album.name} (${album.tracks} tracks, ${this.#megabytes(album.size)} MB)
+                <button value="${album.name}">Remove</button></p>`);
+        }
+
+        for (const track of info.recent) {
+            const title = track.title ? `${track.artist} - ${track.title}` : "Unknown track";
+            recent.append(`<p class="cache-item">${title} (${this.#megabytes(track.size)} MB)${track.pinned ? " - kept offline" : ""}</p>`);
+        }
+    }
+
+    /** Downloads all tracks of the album at `url` into the cache */
+    keepOffline(url) {
+        const artistPage = url.split("/")[2];
+
+        loading.spawn();
+        httpRequestGet(url, (response) => {
+            const jsonRes = parseAlbumData(response);
+            if (jsonRes) {
+                const tracks = jsonRes.trackinfo.filter(element => element.file != null);
+                tracks.forEach(element => {
+                    element.artist = jsonRes.artist;
+                    element.art_id = jsonRes.art_id;
+                    element.title_link = "https://" + artistPage + element.title_link;
+                });
+
+                const album = `${jsonRes.artist} - ${jsonRes.current.title}`;
+                if (!Window.this.xcall("cache_keep_offline", JSON.stringify(tracks), album)) {
+                    showErrorModal("Enable cache in settings to keep albums offline");
+                }
+            }
+            loading.destroy();
+        }, showErrorModal);
+    }
+
+    #megabytes(bytes) {
+        return (bytes / 1048576).toFixed(1);
+    }
+}
@@ -0,0 +1,91 @@
+/** Equalizer tab of the settings window. Settings live in config, player only applies them */
+class Equalizer {
+    constructor() {
+        this.settings = null;
+        this.bands = JSON.parse(Window.this.xcall("get_equalizer_bands"));
+
+        $('#eq-enabled').on('change', () => {
+            this.settings.enabled = $('#eq-enabled').val();
+            this.#apply();
+        });
+
+        $('#eq-preamp').on('input', () => {
+            this.settings.preamp = Number($('#eq-preamp').val());
+            this.#apply();
+        });
+
+        $('#eq-preset').on('change', () => {
+            const name = $('#eq-preset').val();
+            if (name) {
+                this.#update(Window.this.xcall("load_equalizer_preset", name));
+            }
+        });
+
+        $('#eq-save-preset').on('click', () => {
+            const name = $('#eq-preset-name').val();
+            if (Window.this.xcall("save_equalizer_preset", name)) {
+                $('#eq-status').text("");
+                this.renderPresets(name.trim());
+            } else {
+                $('#eq-status').text("Preset name must not be empty or same as built-in preset");
+            }
+        });
+
+        $('#eq-delete-preset').on('click', () => {
+            Window.this.xcall("delete_equalizer_preset", $('#eq-preset').val());
+            this.renderPresets();
+        });
+    }
+
+    setup() {
+        const sliders = $('#eq-bands');
+        sliders.empty();
+
+        this.bands.frequencies.forEach((freq, index) => {
+            const label = freq >= 1000 ? `${freq / 1000}k` : `${freq}`;
+            sliders.append(`<div class="eq-band">
+                <input type="vslider" min="${-this.bands.max_gain}" max="${this.bands.max_gain}" step="0.5" value="0">
+                <p>${label}</p>
+            </div>`);
+
+            sliders.find('input').eq(index).on('input', (e) => {
+                this.settings.bands[index] = Number($(e.currentTarget).val());
+                this.#apply();
+            });
+        });
+
+        $('#eq-preamp').attr('min', -this.bands.max_gain).attr('max', this.bands.max_gain);
+        this.#update(Window.this.xcall("get_equalizer"));
+        this.renderPresets();
+    }
+
+    renderPresets(selected) {
+        const presets = JSON.parse(Window.this.xcall("get_equalizer_presets"));
+        const select = $('#eq-preset');
+        select.empty();
+        select.append(`<option value="">Custom</option>`);
+
+        for (const preset of presets) {
+            select.append(`<option value="${preset.name}">${preset.name}${preset.builtin ? "" : " (user)"}</option>`);
+        }
+
+        select.val(selected || "");
+    }
+
+    /** Stores settings in config and sends them to the player */
+    #apply() {
+        const settings = Window.this.xcall("update_equalizer", JSON.stringify(this.settings));
+        Window.this.xcall("apply_equalizer", settings);
+    }
+
+    #update(json) {
+        this.settings = JSON.parse(json);
+        Window.this.xcall("apply_equalizer", json);
+
+        $('#eq-enabled').val(this.settings.enabled);
+        $('#eq-preamp').val(this.settings.preamp);
+        $('#eq-bands input').each((index, slider) => {
+            $(slider).val(this.settings.bands[index]);
+        });
+    }
+}
@@ -0,0 +1,36 @@
+/** Recently and most played tracks on the home view */
+class ListeningHistory {
+    constructor() {
+        $('#history-recent, #history-most-played').on('click', '.track-card', (e) => {
+            if (Window.this.xcall("history_enqueue", $(e.currentTarget).attr('value'), false)) {
+                player.refreshQueue();
+                player.renderQueue();
+            }
+        });
+    }
+
+    render() {
+        const recent = Window.this.xcall("history_recent", 20) || [];
+        const mostPlayed = Window.this.xcall("history_most_played", 20) || [];
+
+        $('#history').attr("class", recent.length > 0 ? "history" : "hidden history");
+        this.#renderList($('#history-recent'), recent.map(play => [play.track, play.track.artist]));
+        this.#renderList($('#history-most-played'), mostPlayed.map(count =>
+            [count.track, `${count.track.artist} · ${count.plays} ${count.plays == 1 ? "play" : "plays"}`]));
+    }
+
+    #renderList(list, tracks) {
+        list.empty();
+        for (const [track, description] of tracks) {
+            const node = createElementFromHTML(historyTrackCard(track.title, description, String(track.track_id)));
+
+            $(node).children(function () {
+                if ($(this).prop("className") == "track-img" && track.art_id) {
+                    setImage(track.art_id, $(this)[0]);
+                }
+            });
+
+            list.append(node);
+        }
+    }
+}
@@ -1,9 +1,13 @@
 /** Player and queue managment */
 class Player {
     constructor() {
+        /** Copy of the queue kept by player backend, updated with `refreshQueue` */
         this.queue = [];
         this.queuePosition = 0;
         this.shuffle = false;
+        /** 0 - off, 1 - repeat one, 2 - repeat all */
+        this.repeat = 0;
+        this.shownTrack = null;
 
         $('#back').on("click", () => {
             this.previous();
@@ -26,8 +30,15 @@ class Player {
         });
 
         $('#player-shuffle-toggle').on('click', () => {
-            this.shuffle = !this.shuffle;
-            $('#player-shuffle-toggle').toggleClass("toggle");
+            Window.this.xcall("set_shuffle", !this.shuffle);
+            this.refreshQueue();
+        });
+
+        // off -> all -> one
+        $('#player-repeat-toggle').on('click', () => {
+            const next = { 0: 2, 2: 1, 1: 0 };
+            Window.this.xcall("set_repeat", next[this.repeat]);
+            this.refreshQueue();
         });
 
         $('#volume').on('input', (e) => {
@@ -50,15 +61,18 @@ class Player {
         var me = this;
 
         this.setStateChangeCallback(function () {
+            me.refreshQueue();
+
             if (me.queue.length == 0) {
                 $('#queue-select')[0].classList.add("closed");
                 $('#track-name').text("");
             } else {
                 Window.this.xcall("update_metadata",
                     me.queue[me.queuePosition].title,
-                    "",
+                    me.queue[me.queuePosition].album || "",
                     me.queue[me.queuePosition].artist,
                     genUrlImage(me.queue[me.queuePosition].art_id));
+                me.#showTrack();
             }
 
             $('#controls').each(function () {
@@ -85,110 +99,106 @@ class Player {
 
         this.forceUpdate();
 
-        // restore play queue
-        const file = readFile("queue.json");
+        // restore play queue, saved by player backend
+        this.refreshQueue();
+        this.renderQueue();
 
-        if (file && file.length != 0) {
-            const jsonRes = JSON.parse(file);
-            if (jsonRes.queue.length > 0) {
-                jsonRes.queue.forEach(element => {
-                    this.#addToQueueInternal(element);
-                });
-
-                this.queuePosition = jsonRes.position;
-                this.loadTrack();
-                this.seek(jsonRes.play_position);
-                this.setPaused(true);
-            }
-        } else {
-            log("Unable to find queue cache file");
+        if (this.queue.length > 0 && !Window.this.xcall("restore_queue")) {
+            this.loadTrack();
         }
 
         this.setVolume($("#volume").val());
     }
 
     switchBackend(to) {
-        return Window.this.xcall("switch_backend", to);
+        return Window.this.xcall("switch_backend", to, parseInt($("#audio-device").val()) || 0);
     }
 
     previous() {
-        if (this.queuePosition > 0) {
-            this.queuePosition -= 1;
-            this.loadTrack();
-        } else {
-            seek(0);
+        if (!Window.this.xcall("queue_previous")) {
+            this.#reportUnplayable();
         }
     }
 
     next() {
-        if (this.shuffle && this.queue.length > 0) {
-            this.queuePosition = getRandomArbitrary(0, this.queue.length - 1);
-            this.loadTrack();
-            return true;
-        }
-
-
-        const result = this.queuePosition < this.queue.length - 1;
-        if (result) {
-            this.queuePosition += 1;
-            this.loadTrack();
+        if (!Window.this.xcall("queue_next")) {
+            this.#reportUnplayable();
         }
-
-        return result;
     }
 
-    /** Saves all queue contents and position to disk */
-    saveQueue(filename = "queue.json") {
-        writeFile(filename, JSON.stringify({
-            queue: this.queue,
-            position: this.queuePosition,
-            play_position: this.getTime()
-        }))
+    /** Plays track at queue index */
+    playAt(index) {
+        if (!Window.this.xcall("queue_play", index)) {
+            this.#reportUnplayable();
+        }
     }
 
-    #loadTrackInternal() {
-        const useHttp = Window.this.xcall("request_http");
-        const url = this.queue[this.queuePosition].file["mp3-128"];
+    /** Updates local copy of the queue from player backend */
+    refreshQueue() {
+        const state = Window.this.xcall("get_queue");
+        this.queue = state.tracks;
+        this.queuePosition = state.position;
+        this.shuffle = state.shuffle;
+        this.repeat = state.repeat;
 
-        if (useHttp) {
-            url.replace("https://", "http://");
+        if (this.shuffle) {
+            $('#player-shuffle-toggle').addClass("toggle");
+        } else {
+            $('#player-shuffle-toggle').removeClass("toggle");
         }
 
-        return Window.this.xcall("load_track", url);
+        if (this.repeat != 0) {
+            $('#player-repeat-toggle').addClass("toggle");
+        } else {
+            $('#player-repeat-toggle').removeClass("toggle");
+        }
+        $('#player-repeat-toggle').attr("src", this.repeat == 1 ? "icons/repeat-one.svg" : "icons/repeat.svg");
     }
 
-    revokeAudioUrlForAll() {
-        var me = this;
+    /** Rebuilds queue track list */
+    renderQueue() {
+        $('#queue-select').empty();
+        this.queue.forEach(element => {
+            const node = createElementFromHTML(queuedTrackCard(element.title, element.artist));
 
-        me.queue.forEach(element => {
-            loading.spawn();
-            httpRequestGet(element.title_link, function (response) {
-                const aldata = parseAlbumData(response);
-                if (aldata) {
-                    const jsonRes = JSON.parse(aldata);
-                    element.file["mp3-128"] = jsonRes.trackinfo[0].file["mp3-128"];
+            $(node).children(function () {
+                if ($(this).prop("className") == "track-img") {
+                    setImage(element.art_id, $(this)[0]);
                 }
-                loading.destroy();
             });
+
+            $("#queue-select").append(node);
         });
     }
 
+    /** Saves all queue contents and position to disk */
+    saveQueue() {
+        Window.this.xcall("save_queue");
+    }
+
+    /** Fetches fresh stream URLs of all queue tracks, the player refreshes expired ones by itself */
+    revokeAudioUrlForAll() {
+        Window.this.xcall("queue_refresh_stream_urls");
+    }
+
+    /** Reports current track which couldn't be played even with fresh stream URL */
+    #reportUnplayable() {
+        this.refreshQueue();
+        if (this.queue.length > 0) {
+            showErrorModal(`Unable to play ${this.queue[this.queuePosition].title}`);
+        }
+    }
+
     loadTrack() {
-        var me = this;
+        this.playAt(this.queuePosition);
+    }
 
-        if (!me.#loadTrackInternal()) {
-            // probably needs revoke track URL
-            loading.spawn();
-            httpRequestGet(this.queue[this.queuePosition].title_link, function (response) {
-                const aldata = parseAlbumData(response);
-                if (aldata) {
-                    const jsonRes = JSON.parse(aldata);
-                    me.queue[me.queuePosition].file["mp3-128"] = jsonRes.trackinfo[0].file["mp3-128"];
-                    me.#loadTrackInternal()
-                }
-                loading.destroy();
-            });
+    #showTrack() {
+        const me = this;
+        if (me.shownTrack == me.queue[me.queuePosition].title_link) {
+            return;
         }
+        me.shownTrack = me.queue[me.queuePosition].title_link;
 
         $('#track-name').text(me.queue[me.queuePosition].title);
         $("#artist-name").text(me.queue[me.queuePosition].artist);
@@ -196,6 +206,34 @@ class Player {
         $('#track-info-name').text(me.queue[me.queuePosition].artist + " - " + me.queue[me.queuePosition].title);
 
         setImage(me.queue[me.queuePosition].art_id, $("#now-playing-img")[0], 5);
+        me.#showTrackDetails(me.queue[me.queuePosition]);
+    }
+
+    #showTrackDetails(track) {
+        const me = this;
+        const show = (selector, text) => {
+            $(selector).text(text || "");
+            $(selector + "-heading").attr("hidden", text ? null : true);
+        };
+        show("#track-tags", "");
+        show("#track-lyrics", "");
+        show("#track-about", "");
+        show("#track-credits", "");
+
+        if (!track.title_link || !track.track_id) {
+            return;
+        }
+
+        getTrackDetails(track.title_link, track.track_id, function (details) {
+            // another track could be shown already
+            if (me.shownTrack != track.title_link) {
+                return;
+            }
+            show("#track-tags", details.tags.join(" · "));
+            show("#track-lyrics", details.lyrics);
+            show("#track-about", details.about);
+            show("#track-credits", details.credits);
+        }, logWarn);
     }
 
     setPaused(paused) {
@@ -246,40 +284,22 @@ class Player {
             $('#seekbar').val(this.getTime());
             $('#seekbar').attr('max', Math.floor(this.queue[this.queuePosition].duration));
 
-            // load next track
-            if (this.queue.length > this.queuePosition + 1) {
-                if (this.getTime() >= Math.floor(this.queue[this.queuePosition].duration)) {
-                    this.next();
-                }
+            // player follows the queue and refreshes expired stream URLs by itself
+            if (Window.this.xcall("poll_track_event") == "failed") {
+                this.#reportUnplayable();
             }
         }
     }
 
     clearQueue() {
-        this.stop();
-        this.queue = [];
-        this.queuePosition = 0;
-
-        $('#queue-select').empty();
+        Window.this.xcall("queue_clear");
+        this.shownTrack = null;
+        this.refreshQueue();
+        this.renderQueue();
         this.forceUpdate();
     }
 
-    #addToQueueInternal(element) {
-        this.queue.push(element);
-        //log(element);
-
-        const node = createElementFromHTML(queuedTrackCard(element.title, element.artist));
-
-        $(node).children(function () {
-            if ($(this).prop("className") == "track-img") {
-                setImage(element.art_id, $(this)[0]);
-            }
-        });
-
-        $("#queue-select").append(node);
-    }
-
-    addToQueue(url) {
+    addToQueue(url, done) {
         var me = this;
 
         // find the artist page domain
@@ -287,33 +307,101 @@ class Player {
         //              ^
         //              index is 2
         const artistPage = url.split("/")[2];
+        const path = url.split("?")[0].split("/").slice(3).join("/");
+
+        if (artistPage == "bandcamp.com") {
+            // fan profile, bandcamp.com/<username> or bandcamp.com/<username>/wishlist
+            const [username, tab] = path.split("/");
+            this.addFanCollection(username, tab == "wishlist" ? "wishlist" : "collection", done);
+            return;
+        }
+
+        if (path == "" || path == "music") {
+            this.addDiscography("https://" + artistPage + "/music", done);
+            return;
+        }
 
         loading.spawn();
         httpRequestGet(url, function (response) {
-            const aldata = parseAlbumData(response);
-            if (aldata) {
-                const jsonRes = JSON.parse(aldata);
-                jsonRes.trackinfo.forEach(element => {
-                    element.artist = jsonRes.artist;
-                    element.art_id = jsonRes.art_id;
-                    element.title_link = "https://" + artistPage + element.title_link;
-
-                    if (element.file != null) {
-                        me.#addToQueueInternal(element);
-                    }
-                });
+            me.addAlbumPage(response, artistPage);
+            loading.destroy();
+            if (done) {
+                done();
             }
+        }, showErrorModal);
+    }
+
+    /** Adds all releases of an artist or label, one after another to keep their order */
+    addDiscography(url, done) {
+        var me = this;
 
-            me.forceUpdate();
+        loading.spawn();
+        httpRequestGet(url, function (response) {
+            const discography = parseDiscography(response, url);
             loading.destroy();
+
+            if (!discography) {
+                // artists with a single release are redirected to it
+                me.addAlbumPage(response, url.split("/")[2]);
+                if (done) {
+                    done();
+                }
+                return;
+            }
+
+            me.addReleases(discography.releases.map(release => release.url), done);
         }, showErrorModal);
     }
 
-    removeTrackAt(index) {
-        if (index == this.queuePosition) {
-            this.stop();
+    /** Adds all albums and tracks of a public fan collection or wishlist */
+    addFanCollection(username, kind, done) {
+        var me = this;
+
+        loading.spawn();
+        fanCollection(username, kind, function (collection) {
+            loading.destroy();
+            me.addReleases(collection.items.map(item => item.url), done);
+        }, function (error) {
+            loading.destroy();
+            showErrorModal(error);
+        });
+    }
+
+    /** Adds release pages one after another, to keep their order in the queue */
+    addReleases(urls, done) {
+        const addNext = (index) => {
+            if (index < urls.length) {
+                this.addToQueue(urls[index], () => addNext(index + 1));
+            } else if (done) {
+                done();
+            }
+        };
+        addNext(0);
+    }
+
+    addAlbumPage(response, artistPage) {
+        const jsonRes = parseAlbumData(response);
+        if (jsonRes) {
+            const tracks = jsonRes.trackinfo.filter(element => element.file != null);
+            tracks.forEach(element => {
+                element.artist = jsonRes.artist;
+                element.album = jsonRes.item_type == "album" ? jsonRes.current.title : "";
+                element.album_url = jsonRes.url;
+                element.art_id = jsonRes.art_id;
+                element.title_link = "https://" + artistPage + element.title_link;
+            });
+
+            Window.this.xcall("queue_add", JSON.stringify(tracks));
+            this.refreshQueue();
+            this.renderQueue();
         }
 
+        this.forceUpdate();
+    }
+
+    removeTrackAt(index) {
+        Window.this.xcall("queue_remove", index);
+
         var idx = 0;
         $("#queue-select").children(function () {
             if (idx == index) {
@@ -322,10 +410,6 @@ class Player {
             idx += 1;
         });
 
-        this.queue.splice(index, 1);
-
-        if (index < this.queuePosition) {
-            this.queuePosition -= 1;
-        }
+        this.refreshQueue();
     }
 }
\ No newline at end of file
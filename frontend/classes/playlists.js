@@ -0,0 +1,106 @@
+/** Named playlists modal: saving the queue, loading, import and export */
+class Playlists {
+    constructor() {
+        $('#playlist-save').on('click', () => {
+            const name = $('#playlist-name').val().trim();
+            if (!name) {
+                this.#status("Enter playlist name");
+                return;
+            }
+            this.#status(Window.this.xcall("playlist_save", name) ? `Queue saved as ${name}` : "Unable to save playlist");
+            this.render();
+        });
+
+        $('#playlist-import').on('click', () => {
+            const file = Window.this.selectFile({
+                mode: "open",
+                filter: "Playlists (*.m3u8,*.m3u,*.xspf,*.json)|*.m3u8;*.m3u;*.xspf;*.json",
+            });
+            if (file) {
+                this.import(URL.toPath(file));
+            }
+        });
+
+        $('#playlist-list').on('click', 'button', (e) => {
+            const name = $(e.currentTarget).parent().attr('value');
+            switch ($(e.currentTarget).attr('class')) {
+                case "playlist-play":
+                    this.#load(name, true);
+                    break;
+                case "playlist-add":
+                    this.#load(name, false);
+                    break;
+                case "playlist-export":
+                    this.#export(name);
+                    break;
+                case "playlist-delete":
+                    Window.this.xcall("playlist_delete", name);
+                    this.render();
+                    break;
+            }
+        });
+    }
+
+    render() {
+        const list = $('#playlist-list');
+        list.empty();
+
+        for (const playlist of Window.this.xcall("playlist_list") || []) {
+            const node = createElementFromHTML(`<p class="cache-item"><span></span>
+                <button class="playlist-play">Play</button>
+                <button class="playlist-add">Add to queue</button>
+                <button class="playlist-export">Export</button>
+                <button class="playlist-delete">Delete</button></p>`);
+            // names can have any characters, so they're not put into HTML
+            $(node).attr('value', playlist.name);
+            $(node).children('span').text(`${playlist.name} (${playlist.tracks} tracks)`);
+            list.append(node);
+        }
+    }
+
+    /** Imports playlist file and adds its tracks to the queue */
+    import(path) {
+        this.#status("Importing...");
+        loading.spawn();
+        Window.this.xcall("playlist_import", path, (name, tracks, missing) => {
+            Window.this.xcall("queue_add", tracks);
+            player.refreshQueue();
+            player.renderQueue();
+            loading.destroy();
+
+            this.#status(missing > 0 ? `Imported ${name}, ${missing} tracks are not available anymore` : `Imported ${name}`);
+            this.render();
+        }, (error) => {
+            loading.destroy();
+            this.#status(error);
+        });
+    }
+
+    #load(name, replace) {
+        if (Window.this.xcall("playlist_load", name, replace)) {
+            player.refreshQueue();
+            player.renderQueue();
+            if (replace) {
+                player.loadTrack();
+            }
+        } else {
+            this.#status(`Unable to load ${name}`);
+        }
+    }
+
+    #export(name) {
+        const file = Window.this.selectFile({
+            mode: "save",
+            filter: "M3U8 playlist (*.m3u8)|*.m3u8|XSPF playlist (*.xspf)|*.xspf|JSON playlist (*.json)|*.json",
+            extension: "m3u8",
+        });
+        if (file) {
+            const path = URL.toPath(file);
+            this.#status(Window.this.xcall("playlist_export", name, path) ? `Exported to ${path}` : "Unable to export playlist");
+        }
+    }
+
+    #status(text) {
+        $('#playlist-status').text(text);
+    }
+}
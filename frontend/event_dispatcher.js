@@ -35,6 +35,25 @@ function getTags(done_callback) {
 function setSettings() {
     const settings = document.getElementById("options-modal");
     Window.this.xcall("set_settings", settings);
+
+    const proxy = Window.this.xcall("get_proxy");
+    Window.this.xcall("set_proxy", proxy);
+    Window.this.xcall("set_stream_proxy", proxy);
+    Window.this.xcall("set_crossfade", Window.this.xcall("get_crossfade"));
+    Window.this.xcall("set_pause_fade", Window.this.xcall("get_pause_fade"));
+    Window.this.xcall("set_normalization", Window.this.xcall("get_normalization"));
+    Window.this.xcall("set_tempo", Window.this.xcall("get_tempo"));
+    Window.this.xcall("set_cache", Window.this.xcall("get_cache"));
+    Window.this.xcall("set_artwork_cache", Window.this.xcall("get_cache"));
+    Window.this.xcall("set_scrobbler", Window.this.xcall("get_scrobbler"));
+}
+
+function scrobblerLogin(username, password, done_callback, failed_callback) {
+    Window.this.xcall("scrobbler_login", username, password, done_callback, failed_callback);
+}
+
+function testConnection(done_callback) {
+    Window.this.xcall("test_connection", done_callback);
 }
 
 function genUrlImage(art_id, q) {
@@ -45,13 +64,30 @@ function genUrlImage(art_id, q) {
 }
 
 function setImage(art_id, image, q) {
-    Window.this.xcall("set_image", genUrlImage(art_id, q), image);
+    const quality = q != undefined ? q : Window.this.xcall("get_load_artworks");
+    Window.this.xcall("set_image", String(art_id), quality, image);
+}
+
+function searchBandcamp(query, kind, page, done_callback, failed_callback) {
+    Window.this.xcall("search", query, kind, page, done_callback, failed_callback);
+}
+
+function fanCollection(username, kind, done_callback, failed_callback) {
+    Window.this.xcall("fan_collection", username, kind, done_callback, failed_callback);
+}
+
+function getTrackDetails(url, track_id, done_callback, failed_callback) {
+    Window.this.xcall("get_track_details", url, String(track_id), done_callback, failed_callback);
 }
 
 function parseAlbumData(html) {
     return Window.this.xcall("parse_album_data", html);
 }
 
+function parseDiscography(html, url) {
+    return Window.this.xcall("parse_discography", html, url);
+}
+
 function openInBrowser(url) {
     return Window.this.xcall("open_in_browser", url);
 }
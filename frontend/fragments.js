@@ -8,6 +8,16 @@ function queuedTrackCard(title, artist) {
     </div>`;
 }
 
+function historyTrackCard(title, description, trackId) {
+    return `<div class="track-card" value="${trackId}" title="Add to queue">
+        <img class="track-img" src="icons/audio.jpg">
+        <div class="track-card-desc">
+            <h3>${title}</h3>
+            <p>${description}</p>
+        </div>
+    </div>`;
+}
+
 function discoverAlbumCard(title, artist, genre) {
     return `<div class="album-card">
     <img class=album-image>
@@ -28,11 +38,22 @@ function searchResultCard(title, artist, url) {
 </div>`;
 } 
 
+function searchArtistCard(name, location, url) {
+    return `<div class="track-card" value="${url}">
+    <img class="search-img" src="icons/audio.jpg">
+    <div class="track-card-desc">
+        <h3>${name}</h3>
+        <p>${location} · whole discography</p>
+    </div>
+</div>`;
+}
+
 function DiscoverContextMenu() {
     return <menu.context id="discover-context-menu">
         <li>Add to queue</li>
         <li>Open album page in browser</li>
         <li>Copy album URL</li>
+        <li>Keep album offline</li>
     </menu>;
 }
 
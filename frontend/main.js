@@ -1,5 +1,9 @@
 let selectedTags = [];
 let player = new Player();
+let equalizer = new Equalizer();
+let audioCache = new AudioCache();
+let listeningHistory = new ListeningHistory();
+let playlists = new Playlists();
 let loading = new LoadingIndicator();
 let console = new Console();
 
@@ -13,6 +17,7 @@ loading.spawn();
 const clearQueueModal = new Modal("clear-queue-modal");
 const optionsModal = new Modal("options-modal");
 const albumImportModal = new Modal("album-import-modal");
+const playlistsModal = new Modal("playlists-modal");
 
 // VIEWS
 const homeView = new View("home-window", "home");
@@ -22,6 +27,10 @@ const nowPlayingView = new View("now-playing-window", "now-playing", {
     aid: null,
 });
 
+homeView.view.addEventListener("open", (_) => {
+    listeningHistory.render();
+});
+
 homeView.show();
 
 
@@ -62,6 +71,7 @@ albumImportModal.modalWindow.addEventListener("open", (_) => {
 
 window.addEventListener('load', function () {
     player.setup();
+    equalizer.setup();
 });
 
 function setupSizeVars() {
@@ -77,10 +87,6 @@ function setupSizeVars() {
 Window.this.on("size", setupSizeVars);
 setupSizeVars();
 
-function getRandomArbitrary(min, max) {
-    return Math.floor(Math.random() * (max - min) + min);
-}
-
 function showErrorModal(message) {
     $("body").append(`<div class="error-modal">${message}</div>`);
     setTimeout(function () {
@@ -129,6 +135,7 @@ function closeModals() {
     clearQueueModal.hide();
     optionsModal.hide();
     albumImportModal.hide();
+    playlistsModal.hide();
 }
 
 function closeViews() {
@@ -186,8 +193,7 @@ $(window).on("click", "#discover-queue-menu", "li", function (e) {
 
 $("#queue-select").on("click", ".track-card", function () {
     var idx = $(this).index();
-    player.queuePosition = idx;
-    player.loadTrack();
+    player.playAt(idx);
 });
 
 
@@ -211,10 +217,36 @@ $('#clear-queue').on('click', function () {
     clearQueueModal.show();
 });
 
+$('#playlists').on('click', function () {
+    playlists.render();
+    playlistsModal.show();
+});
+
 $('#close-settings').on('click', function () {
     closeModals();
 });
 
+$('#proxy-test').on('click', function () {
+    setSettings();
+    $('#proxy-test-result').text("Connecting...");
+    testConnection(function (ok, message) {
+        $('#proxy-test-result').text(message);
+    });
+});
+
+$('#scrobbler-login').on('click', function () {
+    setSettings();
+    $('#scrobbler-login-result').text("Logging in...");
+    scrobblerLogin($('#scrobbler-username').val(), $('#scrobbler-password').val(), function (sessionKey) {
+        $('#scrobbler-password').val("");
+        $('#scrobbler-session-key').val(sessionKey);
+        $('#scrobbler-login-result').text("Logged in");
+        setSettings();
+    }, function (error) {
+        $('#scrobbler-login-result').text(error);
+    });
+});
+
 $('#settings').on('click', function () {
     optionsModal.show();
 });
@@ -258,33 +290,36 @@ const searchRequest = debounce(function () {
 
     loading.spawn();
 
-    httpRequestGet("https://bandcamp.com/api/fuzzysearch/1/autocomplete?q=" + text, function (response) {
-        var json = JSON.parse(response);
-        if (json.auto && json.auto.results) {
-            json.auto.results.forEach(element => {
-                if (element.type == "a" || element.type == "t") {
-                    const card = createElementFromHTML(
-                        searchResultCard(element.name, element.band_name, element.url));
-
-                    $(card).children(function () {
-                        if ($(this).prop("className") == "search-img") {
-                            setImage(element.art_id, $(this)[0]);
-                        }
-                    });
-
-                    $('#search-results').append(card);
-                }
-            });
-        }
+    searchBandcamp(text, "all", 1, function (page) {
+        page.results.forEach(element => {
+            if (element.type == "album" || element.type == "track") {
+                const card = createElementFromHTML(
+                    searchResultCard(element.name, element.artist || "", element.url));
+
+                $(card).children(function () {
+                    if ($(this).prop("className") == "search-img" && element.art_id) {
+                        setImage(element.art_id, $(this)[0]);
+                    }
+                });
+
+                $('#search-results').append(card);
+            } else {
+                $('#search-results').append(createElementFromHTML(
+                    searchArtistCard(element.name, element.location || element.type, element.url)));
+            }
+        });
+        loading.destroy();
+    }, function (error) {
         loading.destroy();
-    }, showErrorModal);
+        showErrorModal(error);
+    });
 }, 500);
 
 $('#album-url-input').on('input', function () {
     var text = $('#album-url-input').text();
     if ((text.startsWith("https://") || text.startsWith("http://")) && text.includes("bandcamp.com")) {
         $('#search-results')
-            .html("<h2>Press Enter to load album/track into queue</h2>");
+            .html("<h2>Press Enter to load album/track, artist discography or fan collection into queue</h2>");
     } else {
         searchRequest();
     }
@@ -304,6 +339,10 @@ $('#album-url-input').keyup(function (e) {
     }
 });
 
+$("#options-cache").click(function () {
+    audioCache.render();
+});
+
 $(".option-tab").click(function () {
     var selectedIndex = $(this).index();
     $(".option-tab").each(function (index) {
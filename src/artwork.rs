@@ -0,0 +1,336 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use crate::handlers::config::ArtworkThumbnailQuality;
+
+/// Directory of the artwork cache, inside the audio cache directory
+pub const ARTWORK_DIR: &str = "artwork";
+/// Size limit of thumbnails kept in memory, in bytes of their `data:` URLs
+const MEMORY_LIMIT: usize = 32 * 1024 * 1024;
+
+/// Artwork in the resolution of a [`ArtworkThumbnailQuality`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ArtworkKey {
+    pub art_id: u64,
+    pub quality: ArtworkThumbnailQuality,
+}
+
+impl ArtworkKey {
+    pub fn new(art_id: u64, quality: ArtworkThumbnailQuality) -> Self {
+        Self { art_id, quality }
+    }
+
+    pub fn url(&self, http: bool) -> String {
+        format!(
+            "{}://f4.bcbits.com/img/{}",
+            if http { "http" } else { "https" },
+            self.file_name()
+        )
+    }
+
+    fn file_name(&self) -> String {
+        format!("a{}_{}.jpg", self.art_id, self.quality as i32)
+    }
+}
+
+/// Result of [`ArtworkCache::request`]
+#[derive(Debug, PartialEq)]
+pub enum Lookup {
+    /// `data:` URL of the thumbnail
+    Memory(Arc<str>),
+    /// Already requested, the waiter gets the thumbnail with the first request
+    Pending,
+    /// Has to be loaded from [`ArtworkCache::disk_path`] or downloaded, then passed to [`ArtworkCache::finish`]
+    Load,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct DiskEntry {
+    size: u64,
+    last_used: SystemTime,
+}
+
+#[derive(Debug, Clone)]
+struct Thumbnail {
+    data_url: Arc<str>,
+    /// Lookup counter value of the last use, lowest is evicted first
+    last_used: u64,
+}
+
+/// Counts of lookups, reported to the log
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ArtworkStats {
+    pub memory_hits: u64,
+    pub disk_hits: u64,
+    pub misses: u64,
+}
+
+/// Artworks stored on disk up to `max_size` bytes, and recently shown thumbnails kept in memory.
+/// Both evict least recently used artworks first.
+///
+/// Elements waiting for an artwork are kept by `W`, so the same artwork is loaded once however many times it's requested
+pub struct ArtworkCache<W> {
+    dir: PathBuf,
+    max_size: u64,
+    disk: HashMap<ArtworkKey, DiskEntry>,
+    memory: HashMap<ArtworkKey, Thumbnail>,
+    memory_used: usize,
+    lookups: u64,
+    in_flight: HashMap<ArtworkKey, Vec<W>>,
+    stats: ArtworkStats,
+}
+
+/// Parses artwork file names written by [`ArtworkKey::file_name`]
+fn parse_file_name(name: &str) -> Option<ArtworkKey> {
+    let (art_id, quality) = name.strip_prefix('a')?.strip_suffix(".jpg")?.split_once('_')?;
+    Some(ArtworkKey::new(
+        art_id.parse().ok()?,
+        ArtworkThumbnailQuality::from_id(quality.parse().ok()?)?,
+    ))
+}
+
+impl<W> ArtworkCache<W> {
+    /// Opens the cache in `dir`, creating it if needed
+    pub fn open(dir: impl Into<PathBuf>, max_size: u64) -> Self {
+        let dir = dir.into();
+        if let Err(err) = std::fs::create_dir_all(&dir) {
+            log::error!("Unable to create artwork cache directory `{}`: {}", dir.display(), err);
+        }
+
+        let mut disk = HashMap::new();
+        if let Ok(files) = std::fs::read_dir(&dir) {
+            for file in files.flatten() {
+                let Some(key) = file.file_name().to_str().and_then(parse_file_name) else {
+                    continue;
+                };
+                if let Ok(metadata) = file.metadata() {
+                    let last_used = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                    disk.insert(
+                        key,
+                        DiskEntry {
+                            size: metadata.len(),
+                            last_used,
+                        },
+                    );
+                }
+            }
+        }
+
+        let mut cache = Self {
+            dir,
+            max_size,
+            disk,
+            memory: HashMap::new(),
+            memory_used: 0,
+            lookups: 0,
+            in_flight: HashMap::new(),
+            stats: ArtworkStats::default(),
+        };
+        cache.evict_disk();
+        cache
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn set_max_size(&mut self, max_size: u64) {
+        self.max_size = max_size;
+        self.evict_disk();
+    }
+
+    pub fn stats(&self) -> ArtworkStats {
+        self.stats
+    }
+
+    /// Size of artworks stored on disk
+    pub fn used(&self) -> u64 {
+        self.disk.values().map(|entry| entry.size).sum()
+    }
+
+    /// Looks up the thumbnail in memory, otherwise adds `waiter` to the ones waiting for it
+    pub fn request(&mut self, key: ArtworkKey, waiter: W) -> Lookup {
+        self.lookups += 1;
+        if let Some(thumbnail) = self.memory.get_mut(&key) {
+            thumbnail.last_used = self.lookups;
+            self.stats.memory_hits += 1;
+            return Lookup::Memory(thumbnail.data_url.clone());
+        }
+
+        match self.in_flight.get_mut(&key) {
+            Some(waiters) => {
+                waiters.push(waiter);
+                Lookup::Pending
+            }
+            None => {
+                self.in_flight.insert(key, vec![waiter]);
+                Lookup::Load
+            }
+        }
+    }
+
+    /// Path of the stored artwork, and marks it as recently used. Counts a miss if it's not stored
+    pub fn disk_path(&mut self, key: &ArtworkKey) -> Option<PathBuf> {
+        let Some(entry) = self.disk.get_mut(key) else {
+            self.stats.misses += 1;
+            return None;
+        };
+        self.stats.disk_hits += 1;
+        entry.last_used = SystemTime::now();
+
+        let path = self.dir.join(key.file_name());
+        // modification time keeps the order between runs
+        if let Err(err) = File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(entry.last_used))
+        {
+            log::warn!("Unable to update artwork `{}`: {}", path.display(), err);
+        }
+        Some(path)
+    }
+
+    /// Stores downloaded artwork on disk, and evicts old artworks when over the size limit
+    pub fn store(&mut self, key: ArtworkKey, data: &[u8]) -> std::io::Result<()> {
+        std::fs::write(self.dir.join(key.file_name()), data)?;
+        self.disk.insert(
+            key,
+            DiskEntry {
+                size: data.len() as u64,
+                last_used: SystemTime::now(),
+            },
+        );
+        self.evict_disk();
+        Ok(())
+    }
+
+    /// Keeps the loaded thumbnail in memory, `None` if loading failed.
+    /// Returns the waiters of the request
+    pub fn finish(&mut self, key: ArtworkKey, data_url: Option<Arc<str>>) -> Vec<W> {
+        if let Some(data_url) = data_url {
+            self.memory_used += data_url.len();
+            if let Some(previous) = self.memory.insert(
+                key,
+                Thumbnail {
+                    data_url,
+                    last_used: self.lookups,
+                },
+            ) {
+                self.memory_used -= previous.data_url.len();
+            }
+            self.evict_memory();
+        }
+        self.in_flight.remove(&key).unwrap_or_default()
+    }
+
+    fn evict_memory(&mut self) {
+        while self.memory_used > MEMORY_LIMIT {
+            let Some(key) = self
+                .memory
+                .iter()
+                .min_by_key(|(_, thumbnail)| thumbnail.last_used)
+                .map(|(key, _)| *key)
+            else {
+                break;
+            };
+            if let Some(thumbnail) = self.memory.remove(&key) {
+                self.memory_used -= thumbnail.data_url.len();
+            }
+        }
+    }
+
+    /// Removes least recently used artworks until the cache fits its size limit
+    fn evict_disk(&mut self) {
+        let mut candidates: Vec<(SystemTime, ArtworkKey)> = self
+            .disk
+            .iter()
+            .map(|(key, entry)| (entry.last_used, *key))
+            .collect();
+        candidates.sort_by_key(|(last_used, _)| *last_used);
+
+        let mut used = self.used();
+        for (_, key) in candidates {
+            if used <= self.max_size {
+                break;
+            }
+            if let Some(entry) = self.disk.remove(&key) {
+                used -= entry.size;
+                if let Err(err) = std::fs::remove_file(self.dir.join(key.file_name())) {
+                    log::error!("Unable to remove cached artwork: {}", err);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_cache(name: &str, max_size: u64) -> ArtworkCache<u32> {
+        let dir = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        ArtworkCache::open(dir, max_size)
+    }
+
+    #[test]
+    fn deduplicates_requests() {
+        let mut cache = temp_cache("bc_rs_artwork_test_requests", 1000);
+        let key = ArtworkKey::new(1234567890, ArtworkThumbnailQuality::High);
+        assert_eq!(key.url(false), "https://f4.bcbits.com/img/a1234567890_7.jpg");
+
+        assert_eq!(cache.request(key, 1), Lookup::Load);
+        assert_eq!(cache.request(key, 2), Lookup::Pending);
+        assert_eq!(cache.disk_path(&key), None);
+        cache.store(key, b"jpeg").unwrap();
+        assert_eq!(cache.finish(key, Some(Arc::from("data:"))), vec![1, 2]);
+        assert_eq!(cache.request(key, 3), Lookup::Memory(Arc::from("data:")));
+
+        // other quality of the same artwork is separate
+        let small = ArtworkKey::new(1234567890, ArtworkThumbnailQuality::VeryLow);
+        assert_eq!(cache.request(small, 4), Lookup::Load);
+        assert_eq!(cache.finish(small, None), vec![4]);
+        assert_eq!(cache.request(small, 5), Lookup::Load);
+
+        // stored artwork is found after reopening
+        let mut cache: ArtworkCache<u32> = ArtworkCache::open(cache.dir().to_path_buf(), 1000);
+        let path = cache.disk_path(&key).unwrap();
+        assert_eq!(std::fs::read(path).unwrap(), b"jpeg");
+        assert_eq!(
+            cache.stats(),
+            ArtworkStats {
+                memory_hits: 0,
+                disk_hits: 1,
+                misses: 0
+            }
+        );
+        std::fs::remove_dir_all(cache.dir()).unwrap();
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = temp_cache("bc_rs_artwork_test_lru", 10);
+        let keys: Vec<ArtworkKey> = (1..=3)
+            .map(|art_id| ArtworkKey::new(art_id, ArtworkThumbnailQuality::Medium))
+            .collect();
+
+        cache.store(keys[0], b"1111").unwrap();
+        cache.store(keys[1], b"2222").unwrap();
+        cache.disk.get_mut(&keys[1]).unwrap().last_used = SystemTime::UNIX_EPOCH;
+        cache.store(keys[2], b"3333").unwrap();
+
+        assert!(cache.disk_path(&keys[0]).is_some());
+        assert!(cache.disk_path(&keys[1]).is_none());
+        assert!(cache.disk_path(&keys[2]).is_some());
+        assert_eq!(cache.used(), 8);
+
+        cache.set_max_size(0);
+        assert_eq!(std::fs::read_dir(cache.dir()).unwrap().count(), 0);
+        std::fs::remove_dir_all(cache.dir()).unwrap();
+    }
+}
@@ -0,0 +1,143 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use super::{tralbum::Tralbum, unescape_html, ParseError};
+
+/// Lyrics, notes and credits of a track for the now playing view
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrackDetails {
+    pub track_id: u64,
+    pub title: String,
+    pub artist: String,
+    pub lyrics: Option<String>,
+    /// Release notes of the page, album notes on album pages
+    pub about: Option<String>,
+    pub credits: Option<String>,
+    pub tags: Vec<String>,
+    pub release_date: Option<String>,
+}
+
+/// Multiline text of an HTML element, with line breaks kept
+fn multiline_text(html: &str) -> Option<String> {
+    // line breaks are either tags or newlines, sometimes both
+    let breaks = Regex::new(r"<br\s*/?>\r?\n?").unwrap();
+    let tags = Regex::new(r"<[^>]*>").unwrap();
+    let text = breaks.replace_all(html, "\n");
+    plain_text(&unescape_html(&tags.replace_all(&text, "")))
+}
+
+/// Text with trimmed lines and `\n` line endings, `None` if it's empty
+fn plain_text(text: &str) -> Option<String> {
+    let lines: Vec<&str> = text.lines().map(|line| line.trim()).collect();
+    let text = lines.join("\n").trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+/// Lyrics rendered in page HTML, for tracks which don't have them in `data-tralbum`
+fn parse_lyrics(html: &str, track_num: Option<u32>) -> Option<String> {
+    let lyrics = match track_num {
+        // album pages have a lyrics row for each track
+        Some(track_num) => Regex::new(&format!(
+            r#"(?s)<div id="_lyrics_{}"[^>]*>(?P<lyrics>.*?)</div>"#,
+            track_num
+        )),
+        None => Regex::new(r#"(?s)<div class="lyricsText"[^>]*>(?P<lyrics>.*?)</div>"#),
+    }
+    .unwrap();
+
+    lyrics
+        .captures(html)
+        .and_then(|captures| multiline_text(&captures["lyrics"]))
+}
+
+impl TrackDetails {
+    /// Parses details of `track_id` from album or track page HTML
+    pub fn from_html(html: &str, track_id: u64) -> Result<Self, ParseError> {
+        let tralbum = Tralbum::from_html(html)?;
+        let track = tralbum
+            .trackinfo
+            .iter()
+            .find(|track| track.track_id == track_id)
+            .ok_or(ParseError::Missing("track_id"))?;
+
+        let lyrics = track.lyrics.as_deref().and_then(plain_text).or_else(|| {
+            // track pages have a single track without a lyrics row number
+            let track_num = (tralbum.trackinfo.len() > 1)
+                .then_some(track.track_num)
+                .flatten();
+            parse_lyrics(html, track_num)
+        });
+
+        Ok(Self {
+            track_id,
+            title: track.title.clone(),
+            artist: track
+                .artist
+                .clone()
+                .unwrap_or_else(|| tralbum.artist.clone()),
+            lyrics,
+            about: tralbum.current.about.as_deref().and_then(plain_text),
+            credits: tralbum.current.credits.as_deref().and_then(plain_text),
+            tags: tralbum.tags.clone(),
+            release_date: tralbum.release_date().map(str::to_string),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LYRICS_PAGE: &str = include_str!("../../tests/fixtures/album_lyrics.html");
+    const TRACK_PAGE: &str = include_str!("../../tests/fixtures/track.html");
+
+    #[test]
+    fn lyrics_from_tralbum() {
+        let details = TrackDetails::from_html(LYRICS_PAGE, 401).unwrap();
+
+        assert_eq!(details.title, "Opening");
+        assert_eq!(details.artist, "Night Static");
+        assert_eq!(
+            details.lyrics.as_deref(),
+            Some("Lights go out\nwe start again")
+        );
+        assert_eq!(details.about.as_deref(), Some("Recorded live in one take."));
+        assert_eq!(
+            details.credits.as_deref(),
+            Some("Written by Night Static\nMastered by A. Person")
+        );
+        assert_eq!(details.tags, vec!["ambient", "drone"]);
+        assert_eq!(
+            details.release_date.as_deref(),
+            Some("05 May 2021 00:00:00 GMT")
+        );
+    }
+
+    #[test]
+    fn lyrics_from_page_row() {
+        // second track has no lyrics in data-tralbum, only in the lyrics row
+        let details = TrackDetails::from_html(LYRICS_PAGE, 402).unwrap();
+        assert_eq!(
+            details.lyrics.as_deref(),
+            Some("Static on the line\nsay it's fine & go")
+        );
+
+        let instrumental = TrackDetails::from_html(LYRICS_PAGE, 403).unwrap();
+        assert_eq!(instrumental.lyrics, None);
+        assert_eq!(instrumental.artist, "Guest");
+    }
+
+    #[test]
+    fn track_without_details() {
+        let details = TrackDetails::from_html(TRACK_PAGE, 1268475939).unwrap();
+        assert_eq!(details.title, "Boot");
+        assert_eq!(details.lyrics, None);
+        assert!(details.tags.is_empty());
+    }
+
+    #[test]
+    fn unknown_track_is_error() {
+        let err = TrackDetails::from_html(LYRICS_PAGE, 1).unwrap_err();
+        assert!(matches!(err, ParseError::Missing("track_id")));
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::HashSet;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use super::{absolute_url, html_attribute, html_text, origin, tralbum::ItemType, ParseError};
+
+/// Release on an artist or label page
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Release {
+    pub id: u64,
+    pub item_type: ItemType,
+    pub title: String,
+    /// Artist if it differs from the page owner, as on label pages
+    pub artist: Option<String>,
+    pub art_id: Option<u64>,
+    /// Absolute release page URL
+    pub url: String,
+}
+
+/// All releases from `/music` page of an artist or label
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Discography {
+    pub name: String,
+    pub location: Option<String>,
+    /// Releases in order of the page
+    pub releases: Vec<Release>,
+}
+
+/// Release from `data-client-items`, which holds releases not rendered in the grid yet
+#[derive(Debug, Deserialize)]
+struct ClientItem {
+    id: u64,
+    #[serde(rename = "type")]
+    item_type: ItemType,
+    title: String,
+    artist: Option<String>,
+    art_id: Option<u64>,
+    page_url: String,
+}
+
+fn parse_grid_item(item: &str, origin: &str) -> Option<Release> {
+    let grid_item = Regex::new(
+        r#"(?s)^(?P<type>album|track)-(?P<id>\d+)".*?<a href="(?P<href>[^"]+)".*?<p class="title">(?P<title>.*?)</p>"#,
+    )
+    .unwrap();
+    let captures = grid_item.captures(item)?;
+
+    // images after the first rows are loaded lazily from `data-original`
+    let art = Regex::new(r#"/img/a(?P<art_id>\d+)_\d+\.jpg"#).unwrap();
+    let art_id = art
+        .captures(item)
+        .and_then(|captures| captures["art_id"].parse().ok());
+
+    let title = &captures["title"];
+    let (title, artist) = match title.split_once("<span class=\"artist-override\">") {
+        Some((title, artist)) => (title, Some(html_text(artist))),
+        None => (title, None),
+    };
+
+    Some(Release {
+        id: captures["id"].parse().ok()?,
+        item_type: if &captures["type"] == "album" {
+            ItemType::Album
+        } else {
+            ItemType::Track
+        },
+        title: html_text(title),
+        artist: artist.filter(|artist| !artist.is_empty()),
+        art_id,
+        url: absolute_url(origin, &captures["href"]),
+    })
+}
+
+fn parse_band_name(html: &str) -> Option<(String, Option<String>)> {
+    let band = Regex::new(
+        r#"(?s)<p id="band-name-location">\s*<span class="title">(?P<name>.*?)</span>\s*(<span class="location[^"]*">(?P<location>.*?)</span>)?"#,
+    )
+    .unwrap();
+    let captures = band.captures(html)?;
+    let location = captures
+        .name("location")
+        .map(|location| html_text(location.as_str()))
+        .filter(|location| !location.is_empty());
+    Some((html_text(&captures["name"]), location))
+}
+
+impl Discography {
+    /// Parses `/music` page HTML, `page_url` is used to resolve release links.
+    ///
+    /// Artists with a single release have no `/music` page, Bandcamp redirects to the release instead
+    pub fn from_html(html: &str, page_url: &str) -> Result<Self, ParseError> {
+        if !html.contains("id=\"music-grid\"") {
+            return Err(ParseError::Missing("music-grid"));
+        }
+        let (name, location) =
+            parse_band_name(html).ok_or(ParseError::Missing("band-name-location"))?;
+        let origin = origin(page_url);
+
+        let mut releases: Vec<Release> = html
+            .split("data-item-id=\"")
+            .skip(1)
+            .filter_map(|item| {
+                parse_grid_item(&item[..item.find("</li>").unwrap_or(item.len())], origin)
+            })
+            .collect();
+
+        if let Ok(data) = html_attribute(html, "data-client-items") {
+            let items: Vec<ClientItem> = serde_json::from_str(&data)?;
+            releases.extend(items.into_iter().map(|item| Release {
+                id: item.id,
+                item_type: item.item_type,
+                title: item.title,
+                artist: item.artist,
+                art_id: item.art_id,
+                url: absolute_url(origin, &item.page_url),
+            }));
+        }
+
+        // items can be both rendered and in `data-client-items`
+        let mut seen = HashSet::new();
+        releases.retain(|release| seen.insert((release.item_type, release.id)));
+
+        Ok(Self {
+            name,
+            location,
+            releases,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ARTIST_PAGE: &str = include_str!("../../tests/fixtures/artist_music.html");
+    const LABEL_PAGE: &str = include_str!("../../tests/fixtures/label_music.html");
+
+    #[test]
+    fn parses_artist_page() {
+        let discography =
+            Discography::from_html(ARTIST_PAGE, "https://thealgorithm.bandcamp.com/music").unwrap();
+
+        assert_eq!(discography.name, "The Algorithm");
+        assert_eq!(discography.location.as_deref(), Some("Paris, France"));
+
+        let titles: Vec<&str> = discography
+            .releases
+            .iter()
+            .map(|release| release.title.as_str())
+            .collect();
+        // last two are from `data-client-items`, one of them is also rendered
+        assert_eq!(
+            titles,
+            vec![
+                "Brute Force",
+                "Boot",
+                "Polymorphic Code",
+                "Octopus4",
+                "Identity"
+            ]
+        );
+
+        let first = &discography.releases[0];
+        assert_eq!(first.id, 3526372590);
+        assert_eq!(first.item_type, ItemType::Album);
+        assert_eq!(first.art_id, Some(2311186386));
+        assert_eq!(first.artist, None);
+        assert_eq!(
+            first.url,
+            "https://thealgorithm.bandcamp.com/album/brute-force"
+        );
+
+        let single = &discography.releases[1];
+        assert_eq!(single.item_type, ItemType::Track);
+        assert_eq!(single.url, "https://thealgorithm.bandcamp.com/track/boot");
+
+        // lazily loaded artwork
+        assert_eq!(discography.releases[2].art_id, Some(1577428322));
+
+        let client_item = &discography.releases[4];
+        assert_eq!(client_item.id, 1950357912);
+        assert_eq!(client_item.art_id, Some(3018470013));
+        assert_eq!(
+            client_item.url,
+            "https://thealgorithm.bandcamp.com/album/identity"
+        );
+    }
+
+    #[test]
+    fn parses_label_page() {
+        let discography =
+            Discography::from_html(LABEL_PAGE, "https://basickrecords.bandcamp.com/music?x=1")
+                .unwrap();
+
+        assert_eq!(discography.name, "Basick Records");
+        assert_eq!(discography.location, None);
+        assert_eq!(discography.releases.len(), 2);
+
+        let hosted = &discography.releases[0];
+        assert_eq!(hosted.title, "Vol. 1");
+        assert_eq!(hosted.artist.as_deref(), Some("Various Artists"));
+        assert_eq!(hosted.url, "https://basickrecords.bandcamp.com/album/vol-1");
+
+        // released on the artist's own page
+        let external = &discography.releases[1];
+        assert_eq!(external.title, "Octopus4");
+        assert_eq!(external.artist.as_deref(), Some("The Algorithm"));
+        assert_eq!(
+            external.url,
+            "https://thealgorithm.bandcamp.com/album/octopus4"
+        );
+    }
+
+    #[test]
+    fn release_page_is_error() {
+        let album = include_str!("../../tests/fixtures/album.html");
+        let err =
+            Discography::from_html(album, "https://thealgorithm.bandcamp.com/music").unwrap_err();
+        assert!(matches!(err, ParseError::Missing("music-grid")));
+    }
+}
@@ -0,0 +1,235 @@
+use serde::{Deserialize, Serialize};
+
+use super::{html_attribute, tralbum::ItemType, ParseError};
+
+/// Token which pages from the newest item
+pub const FIRST_PAGE_TOKEN: &str = "9999999999::a::";
+
+/// Purchased items or wishlist of a fan
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CollectionKind {
+    Collection,
+    Wishlist,
+}
+
+impl CollectionKind {
+    /// Fancollection API endpoint listing the items
+    pub fn api_url(&self) -> &'static str {
+        match self {
+            CollectionKind::Collection => {
+                "https://bandcamp.com/api/fancollection/1/collection_items"
+            }
+            CollectionKind::Wishlist => "https://bandcamp.com/api/fancollection/1/wishlist_items",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct FanData {
+    fan_id: u64,
+    name: Option<String>,
+    username: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct CollectionData {
+    #[serde(default)]
+    item_count: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct PageData {
+    fan_data: FanData,
+    #[serde(default)]
+    collection_data: CollectionData,
+    #[serde(default)]
+    wishlist_data: CollectionData,
+}
+
+/// Public fan profile from `bandcamp.com/<username>`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FanProfile {
+    pub fan_id: u64,
+    /// Display name, username if the fan hasn't set one
+    pub name: String,
+    pub username: String,
+    pub collection_count: u32,
+    pub wishlist_count: u32,
+}
+
+impl FanProfile {
+    /// Parses fan page HTML
+    pub fn from_html(html: &str) -> Result<Self, ParseError> {
+        let data: PageData = serde_json::from_str(&html_attribute(html, "data-blob")?)?;
+        let fan = data.fan_data;
+
+        Ok(Self {
+            fan_id: fan.fan_id,
+            name: fan
+                .name
+                .filter(|name| !name.is_empty())
+                .unwrap_or_else(|| fan.username.clone()),
+            username: fan.username,
+            collection_count: data.collection_data.item_count,
+            wishlist_count: data.wishlist_data.item_count,
+        })
+    }
+}
+
+/// Body of fancollection API requests
+#[derive(Debug, Clone, Serialize)]
+pub struct CollectionRequest<'a> {
+    pub fan_id: u64,
+    /// `last_token` of the previous page, [`FIRST_PAGE_TOKEN`] for the first one
+    pub older_than_token: &'a str,
+    pub count: u32,
+}
+
+/// Item as the fancollection API returns it
+#[derive(Debug, Clone, Deserialize)]
+struct ApiItem {
+    tralbum_id: u64,
+    /// `a` for albums and `t` for tracks, also for merch with an album
+    tralbum_type: String,
+    item_title: String,
+    band_name: String,
+    item_url: Option<String>,
+    item_art_id: Option<u64>,
+}
+
+/// Album or track in a collection or wishlist
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FanItem {
+    pub id: u64,
+    pub item_type: ItemType,
+    pub title: String,
+    pub artist: String,
+    pub url: String,
+    pub art_id: Option<u64>,
+}
+
+/// One page of a fancollection API response
+#[derive(Debug, Clone)]
+pub struct CollectionPage {
+    pub items: Vec<FanItem>,
+    pub more_available: bool,
+    pub last_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiPage {
+    items: Vec<ApiItem>,
+    #[serde(default)]
+    more_available: bool,
+    last_token: Option<String>,
+}
+
+impl CollectionPage {
+    /// Parses fancollection API response, items without release page are skipped
+    pub fn from_json(json: &str) -> Result<Self, ParseError> {
+        let page: ApiPage = serde_json::from_str(json)?;
+        let items = page
+            .items
+            .into_iter()
+            .filter_map(|item| {
+                let item_type = match item.tralbum_type.as_str() {
+                    "a" => ItemType::Album,
+                    "t" => ItemType::Track,
+                    _ => return None,
+                };
+                Some(FanItem {
+                    id: item.tralbum_id,
+                    item_type,
+                    title: item.item_title,
+                    artist: item.band_name,
+                    url: item.item_url?,
+                    art_id: item.item_art_id,
+                })
+            })
+            .collect();
+
+        Ok(Self {
+            items,
+            more_available: page.more_available,
+            last_token: page.last_token,
+        })
+    }
+}
+
+/// Fan profile with all items of a collection or wishlist
+#[derive(Debug, Clone, Serialize)]
+pub struct FanCollection {
+    pub fan: FanProfile,
+    pub kind: CollectionKind,
+    pub items: Vec<FanItem>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FAN_PAGE: &str = include_str!("../../tests/fixtures/fan.html");
+    const COLLECTION_ITEMS: &str = include_str!("../../tests/fixtures/collection_items.json");
+
+    #[test]
+    fn parses_fan_page() {
+        let fan = FanProfile::from_html(FAN_PAGE).unwrap();
+
+        assert_eq!(
+            fan,
+            FanProfile {
+                fan_id: 1044781,
+                name: String::from("Jane Doe"),
+                username: String::from("janedoe"),
+                collection_count: 3,
+                wishlist_count: 12,
+            }
+        );
+    }
+
+    #[test]
+    fn parses_collection_page() {
+        let page = CollectionPage::from_json(COLLECTION_ITEMS).unwrap();
+
+        // merch without a release is skipped
+        assert_eq!(page.items.len(), 2);
+        assert!(page.more_available);
+        assert_eq!(
+            page.last_token.as_deref(),
+            Some("1680000000:1268475939:t::")
+        );
+
+        let album = &page.items[0];
+        assert_eq!(album.id, 3526372590);
+        assert_eq!(album.item_type, ItemType::Album);
+        assert_eq!(album.title, "Brute Force");
+        assert_eq!(album.artist, "The Algorithm");
+        assert_eq!(
+            album.url,
+            "https://thealgorithm.bandcamp.com/album/brute-force"
+        );
+        assert_eq!(album.art_id, Some(2311186386));
+
+        assert_eq!(page.items[1].item_type, ItemType::Track);
+    }
+
+    #[test]
+    fn request_body() {
+        let request = CollectionRequest {
+            fan_id: 1044781,
+            older_than_token: FIRST_PAGE_TOKEN,
+            count: 100,
+        };
+        assert_eq!(
+            serde_json::to_string(&request).unwrap(),
+            r#"{"fan_id":1044781,"older_than_token":"9999999999::a::","count":100}"#
+        );
+    }
+
+    #[test]
+    fn missing_blob_is_error() {
+        let err = FanProfile::from_html("<html></html>").unwrap_err();
+        assert!(matches!(err, ParseError::Missing("data-blob")));
+    }
+}
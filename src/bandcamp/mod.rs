@@ -0,0 +1,80 @@
+pub mod details;
+pub mod discography;
+pub mod fan;
+pub mod search;
+pub mod tralbum;
+
+use std::fmt::{self, Display};
+
+use regex::Regex;
+
+/// Error returned when Bandcamp page can't be parsed into typed data
+#[derive(Debug)]
+pub enum ParseError {
+    /// Page does not contain expected attribute or element
+    Missing(&'static str),
+    /// Embedded JSON blob does not match the model
+    Json(serde_json::Error),
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Missing(what) => write!(f, "Unable to find `{}` in page", what),
+            ParseError::Json(err) => write!(f, "Invalid JSON data: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<serde_json::Error> for ParseError {
+    fn from(value: serde_json::Error) -> Self {
+        ParseError::Json(value)
+    }
+}
+
+/// Returns unescaped contents of the first `name="..."` HTML attribute in page
+pub(crate) fn html_attribute(html: &str, name: &'static str) -> Result<String, ParseError> {
+    let start = format!("{}=\"", name);
+    let data = &html[html.find(&start).ok_or(ParseError::Missing(name))? + start.len()..];
+    let data = &data[..data.find('"').ok_or(ParseError::Missing(name))?];
+    Ok(unescape_html(data))
+}
+
+/// Decodes HTML entities Bandcamp uses inside attributes and text
+pub(crate) fn unescape_html(data: &str) -> String {
+    data.replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+/// Makes page link absolute, without tracking parameters
+pub(crate) fn absolute_url(origin: &str, href: &str) -> String {
+    let href = href.split('?').next().unwrap_or(href);
+    if href.starts_with('/') {
+        format!("{}{}", origin, href)
+    } else {
+        href.to_string()
+    }
+}
+
+/// `https://artist.bandcamp.com` from any URL on the page
+pub(crate) fn origin(page_url: &str) -> &str {
+    let host_end = page_url
+        .find("://")
+        .map(|scheme| scheme + 3)
+        .and_then(|host| page_url[host..].find('/').map(|path| host + path))
+        .unwrap_or(page_url.len());
+    &page_url[..host_end]
+}
+
+/// Text of an HTML fragment, with tags stripped and whitespace collapsed
+pub(crate) fn html_text(fragment: &str) -> String {
+    let tags = Regex::new(r"<[^>]*>").unwrap();
+    let text = tags.replace_all(fragment, " ");
+    unescape_html(&text.split_whitespace().collect::<Vec<_>>().join(" "))
+}
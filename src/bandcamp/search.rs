@@ -0,0 +1,231 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use super::html_text;
+
+/// Bandcamp search page
+pub const SEARCH_URL: &str = "https://bandcamp.com/search";
+
+/// What to search for, `item_type` parameter of the search page
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchKind {
+    All,
+    /// Artists and labels
+    Artists,
+    Albums,
+    Tracks,
+}
+
+impl SearchKind {
+    pub fn item_type(&self) -> &'static str {
+        match self {
+            SearchKind::All => "",
+            SearchKind::Artists => "b",
+            SearchKind::Albums => "a",
+            SearchKind::Tracks => "t",
+        }
+    }
+}
+
+/// Kind of search result, fans and other results are skipped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResultType {
+    Artist,
+    Label,
+    Album,
+    Track,
+}
+
+impl ResultType {
+    fn from_label(label: &str) -> Option<Self> {
+        match label.trim().to_lowercase().as_str() {
+            "artist" => Some(ResultType::Artist),
+            "label" => Some(ResultType::Label),
+            "album" => Some(ResultType::Album),
+            "track" => Some(ResultType::Track),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchResult {
+    #[serde(rename = "type")]
+    pub kind: ResultType,
+    pub name: String,
+    /// Page of the result without search tracking parameters
+    pub url: String,
+    /// Artwork of albums and tracks, artists and labels have images which are not artworks
+    pub art_id: Option<u64>,
+    /// Artist of albums and tracks
+    pub artist: Option<String>,
+    /// Album a track is from
+    pub album: Option<String>,
+    /// Genre of artists and labels
+    pub genre: Option<String>,
+    /// Location of artists and labels
+    pub location: Option<String>,
+}
+
+/// One page of search results
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchPage {
+    pub results: Vec<SearchResult>,
+    /// There are more results on the next page
+    pub has_next: bool,
+}
+
+/// Text content of the first `<div class="{class}">` in a result, with tags stripped
+fn div_text(item: &str, class: &str) -> Option<String> {
+    let start = format!("<div class=\"{}\">", class);
+    let text = &item[item.find(&start)? + start.len()..];
+    let text = &text[..text.find("</div>")?];
+
+    let text = html_text(text);
+    (!text.is_empty()).then_some(text)
+}
+
+fn parse_result(item: &str) -> Option<SearchResult> {
+    let kind = ResultType::from_label(&div_text(item, "itemtype")?)?;
+    let name = div_text(item, "heading")?;
+    let url = div_text(item, "itemurl")?;
+
+    let art = Regex::new(r#"<img src="[^"]*/img/a(?P<art_id>\d+)_\d+\.jpg""#).unwrap();
+    let art_id = art
+        .captures(item)
+        .and_then(|captures| captures["art_id"].parse().ok());
+
+    let subhead = div_text(item, "subhead");
+    let (artist, album, location) = match (kind, subhead) {
+        (ResultType::Artist | ResultType::Label, location) => (None, None, location),
+        // "by Artist" for albums, "from Album by Artist" for tracks
+        (_, Some(subhead)) => match subhead.strip_prefix("by ") {
+            Some(artist) => (Some(artist.to_string()), None, None),
+            None => match subhead
+                .strip_prefix("from ")
+                .and_then(|from| from.rsplit_once(" by "))
+            {
+                Some((album, artist)) => (Some(artist.to_string()), Some(album.to_string()), None),
+                None => (None, None, None),
+            },
+        },
+        (_, None) => (None, None, None),
+    };
+
+    let genre = div_text(item, "genre").map(|genre| {
+        genre
+            .strip_prefix("genre:")
+            .unwrap_or(&genre)
+            .trim()
+            .to_string()
+    });
+
+    Some(SearchResult {
+        kind,
+        name,
+        url,
+        art_id,
+        artist,
+        album,
+        genre,
+        location,
+    })
+}
+
+impl SearchPage {
+    /// Parses search page HTML, a page without results has none
+    pub fn from_html(html: &str) -> Self {
+        let results = html
+            .split("<li class=\"searchresult")
+            .skip(1)
+            .map(|item| &item[..item.find("</li>").unwrap_or(item.len())])
+            .filter_map(parse_result)
+            .collect();
+
+        Self {
+            results,
+            has_next: html.contains("class=\"next\""),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEARCH_ALL: &str = include_str!("../../tests/fixtures/search_all.html");
+    const SEARCH_ALBUMS: &str = include_str!("../../tests/fixtures/search_albums.html");
+
+    #[test]
+    fn parses_all_kinds() {
+        let page = SearchPage::from_html(SEARCH_ALL);
+        let kinds: Vec<ResultType> = page.results.iter().map(|result| result.kind).collect();
+
+        // fan result is skipped
+        assert_eq!(
+            kinds,
+            vec![
+                ResultType::Artist,
+                ResultType::Album,
+                ResultType::Track,
+                ResultType::Label
+            ]
+        );
+        assert!(!page.has_next);
+
+        let artist = &page.results[0];
+        assert_eq!(artist.name, "The Algorithm");
+        assert_eq!(artist.url, "https://thealgorithm.bandcamp.com");
+        assert_eq!(artist.art_id, None);
+        assert_eq!(artist.genre.as_deref(), Some("metal"));
+        assert_eq!(artist.location.as_deref(), Some("Paris, France"));
+
+        let track = &page.results[2];
+        assert_eq!(track.name, "Boot");
+        assert_eq!(track.art_id, Some(2311186386));
+        assert_eq!(track.artist.as_deref(), Some("The Algorithm"));
+        assert_eq!(track.album.as_deref(), Some("Brute Force"));
+
+        let label = &page.results[3];
+        assert_eq!(label.name, "Basick Records");
+        assert_eq!(label.location.as_deref(), Some("London, UK"));
+    }
+
+    #[test]
+    fn parses_album_page() {
+        let page = SearchPage::from_html(SEARCH_ALBUMS);
+
+        assert_eq!(page.results.len(), 2);
+        assert!(page.has_next);
+
+        let album = &page.results[1];
+        assert_eq!(album.kind, ResultType::Album);
+        assert_eq!(album.name, "Compiler Optimization Techniques");
+        assert_eq!(
+            album.url,
+            "https://thealgorithm.bandcamp.com/album/compiler-optimization-techniques"
+        );
+        assert_eq!(album.art_id, Some(1234567890));
+        assert_eq!(album.artist.as_deref(), Some("The Algorithm & Friends"));
+        assert_eq!(album.album, None);
+        assert_eq!(album.genre, None);
+    }
+
+    #[test]
+    fn serializes_type_field() {
+        let page = SearchPage::from_html(SEARCH_ALBUMS);
+        let json = serde_json::to_value(&page).unwrap();
+
+        assert_eq!(json["results"][0]["type"], "album");
+        assert_eq!(json["has_next"], true);
+    }
+
+    #[test]
+    fn empty_page() {
+        let page = SearchPage::from_html("<html><body><p>No results</p></body></html>");
+        assert!(page.results.is_empty());
+        assert!(!page.has_next);
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use super::{html_attribute, unescape_html, ParseError};
+
+/// Kind of Bandcamp release page
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemType {
+    Album,
+    Track,
+}
+
+/// Album or track data embedded in `data-tralbum` attribute of release page.
+/// Field names kept same as Bandcamp uses, so frontend gets the familiar JSON.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tralbum {
+    pub id: u64,
+    pub item_type: ItemType,
+    pub artist: String,
+    pub art_id: Option<u64>,
+    pub url: Option<String>,
+    pub album_release_date: Option<String>,
+    pub current: Current,
+    #[serde(default)]
+    pub trackinfo: Vec<TrackInfo>,
+    /// Release tags, these are not in `data-tralbum` and parsed from page HTML
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Release metadata from `current` field
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Current {
+    pub title: String,
+    pub release_date: Option<String>,
+    pub publish_date: Option<String>,
+    pub credits: Option<String>,
+    pub about: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrackInfo {
+    pub id: Option<u64>,
+    pub track_id: u64,
+    pub title: String,
+    pub artist: Option<String>,
+    pub track_num: Option<u32>,
+    pub title_link: Option<String>,
+    #[serde(default)]
+    pub duration: f64,
+    /// Stream URLs, `null` for tracks which can't be streamed
+    pub file: Option<TrackFile>,
+    /// Some pages have lyrics only in HTML, see [`TrackDetails`](super::details::TrackDetails)
+    #[serde(default)]
+    pub lyrics: Option<String>,
+}
+
+/// Stream URLs for different file formats
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TrackFile {
+    #[serde(rename = "mp3-128", skip_serializing_if = "Option::is_none")]
+    pub mp3_128: Option<String>,
+    #[serde(rename = "mp3-v0", skip_serializing_if = "Option::is_none")]
+    pub mp3_v0: Option<String>,
+    /// Formats we don't know about yet
+    #[serde(flatten)]
+    pub other: HashMap<String, String>,
+}
+
+impl TrackFile {
+    /// Best available stream URL
+    pub fn stream_url(&self) -> Option<&str> {
+        self.mp3_v0
+            .as_deref()
+            .or(self.mp3_128.as_deref())
+            .or_else(|| self.other.values().next().map(|url| url.as_str()))
+    }
+}
+
+impl Tralbum {
+    /// Parses album or track page HTML
+    pub fn from_html(html: &str) -> Result<Self, ParseError> {
+        let data = html_attribute(html, "data-tralbum")?;
+        let mut tralbum: Tralbum = serde_json::from_str(&data)?;
+        tralbum.tags = parse_tags(html);
+        Ok(tralbum)
+    }
+
+    /// Release date of the album, or track if it is a single
+    pub fn release_date(&self) -> Option<&str> {
+        self.current
+            .release_date
+            .as_deref()
+            .or(self.album_release_date.as_deref())
+    }
+
+    /// Tracks which have stream URL
+    pub fn streamable_tracks(&self) -> impl Iterator<Item = &TrackInfo> {
+        self.trackinfo.iter().filter(|track| {
+            track
+                .file
+                .as_ref()
+                .and_then(|file| file.stream_url())
+                .is_some()
+        })
+    }
+}
+
+fn parse_tags(html: &str) -> Vec<String> {
+    let regex = Regex::new(r#"<a class="tag"[^>]*>(?P<tag>[^<]+)</a>"#).unwrap();
+    regex
+        .captures_iter(html)
+        .map(|captures| unescape_html(captures["tag"].trim()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALBUM_PAGE: &str = include_str!("../../tests/fixtures/album.html");
+    const TRACK_PAGE: &str = include_str!("../../tests/fixtures/track.html");
+
+    #[test]
+    fn parses_album_page() {
+        let tralbum = Tralbum::from_html(ALBUM_PAGE).unwrap();
+
+        assert_eq!(tralbum.item_type, ItemType::Album);
+        assert_eq!(tralbum.artist, "The Algorithm");
+        assert_eq!(tralbum.art_id, Some(2311186386));
+        assert_eq!(tralbum.current.title, "Brute Force");
+        assert_eq!(tralbum.release_date(), Some("01 Apr 2016 00:00:00 GMT"));
+        assert_eq!(
+            tralbum.current.credits.as_deref(),
+            Some("Mixed & mastered by Rémi Gallego")
+        );
+        assert_eq!(tralbum.tags, vec!["electronic", "djent", "france"]);
+        assert_eq!(tralbum.trackinfo.len(), 3);
+        assert_eq!(tralbum.streamable_tracks().count(), 2);
+
+        let first = &tralbum.trackinfo[0];
+        assert_eq!(first.track_id, 1268475939);
+        assert_eq!(first.title_link.as_deref(), Some("/track/boot"));
+        assert_eq!(
+            first.file.as_ref().and_then(|file| file.mp3_128.as_deref()),
+            Some("https://t4.bcbits.com/stream/aaa/mp3-128/1268475939?p=0&ts=1680000000&t=aaa")
+        );
+    }
+
+    #[test]
+    fn parses_track_page() {
+        let tralbum = Tralbum::from_html(TRACK_PAGE).unwrap();
+
+        assert_eq!(tralbum.item_type, ItemType::Track);
+        assert_eq!(tralbum.current.title, "Boot");
+        assert_eq!(tralbum.trackinfo.len(), 1);
+        assert_eq!(tralbum.trackinfo[0].duration, 236.571);
+        assert!(tralbum.tags.is_empty());
+    }
+
+    #[test]
+    fn keeps_bandcamp_field_names() {
+        let tralbum = Tralbum::from_html(ALBUM_PAGE).unwrap();
+        let json: serde_json::Value = serde_json::to_value(&tralbum).unwrap();
+
+        assert!(json["trackinfo"][0]["file"]["mp3-128"].is_string());
+        assert_eq!(json["item_type"], "album");
+    }
+
+    #[test]
+    fn missing_tralbum_is_error() {
+        let err = Tralbum::from_html("<html></html>").unwrap_err();
+        assert!(matches!(err, ParseError::Missing("data-tralbum")));
+    }
+}
@@ -0,0 +1,567 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::clock::now;
+
+/// Recently played tracks listed in the cache management view
+const RECENT_TRACKS: usize = 20;
+/// Directory of the audio cache when none is configured, relative to the working directory
+pub const DEFAULT_CACHE_DIR: &str = "cache";
+/// Index of cached tracks, inside the cache directory
+const INDEX_FILE: &str = "index.json";
+/// Extension of unfinished downloads, which are removed when the cache is opened
+const PARTIAL_EXTENSION: &str = "part";
+
+/// Cache shared by the player handler and the audio backend threads
+pub type SharedCache = Arc<Mutex<AudioCache>>;
+
+/// Cache key of a stream URL. Bandcamp stream URLs of the same track differ only in the query (signature and expiry)
+pub fn cache_key(url: &str) -> String {
+    let url = url.split(['?', '#']).next().unwrap_or(url);
+    url.trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .to_string()
+}
+
+/// 64-bit FNV-1a, stable between builds unlike `DefaultHasher`
+#[derive(Debug, Clone, Copy)]
+struct ContentHash(u64);
+
+impl ContentHash {
+    fn new() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for byte in data {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    /// File with the data, named by hash of its content so identical tracks are stored once
+    blob: String,
+    size: u64,
+    /// Unix time of the last play, least recently played tracks are evicted first
+    last_used: u64,
+    /// Album the track is kept offline for, pinned tracks are never evicted
+    #[serde(default)]
+    pinned: Option<String>,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    artist: String,
+}
+
+/// Album kept offline, for the cache management view
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PinnedAlbum {
+    pub name: String,
+    pub tracks: usize,
+    pub size: u64,
+}
+
+/// Cached track, for the cache management view
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CachedTrack {
+    pub title: String,
+    pub artist: String,
+    pub size: u64,
+    pub pinned: bool,
+}
+
+/// Cache usage, for the cache management view
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheInfo {
+    pub dir: String,
+    pub used: u64,
+    pub max_size: u64,
+    pub tracks: usize,
+    pub albums: Vec<PinnedAlbum>,
+    /// Most recently played tracks first
+    pub recent: Vec<CachedTrack>,
+}
+
+/// Content-addressed cache of downloaded tracks, limited to `max_size` bytes by evicting least recently played tracks
+pub struct AudioCache {
+    dir: PathBuf,
+    max_size: u64,
+    /// Entries by [`cache_key`] of their stream URL
+    entries: HashMap<String, CacheEntry>,
+    /// Title and artist of tracks which are not cached yet, by cache key
+    descriptions: HashMap<String, (String, String)>,
+    /// Play times changed since the index was saved, they are saved with the next change or on drop
+    dirty: bool,
+}
+
+impl AudioCache {
+    /// Opens the cache in `dir`, creating it if needed. Entries without data and unfinished downloads are removed
+    pub fn open(dir: impl Into<PathBuf>, max_size: u64) -> Self {
+        let dir = dir.into();
+        if let Err(err) = std::fs::create_dir_all(&dir) {
+            log::error!("Unable to create cache directory `{}`: {}", dir.display(), err);
+        }
+
+        let mut entries: HashMap<String, CacheEntry> = std::fs::read_to_string(dir.join(INDEX_FILE))
+            .ok()
+            .and_then(|data| {
+                serde_json::from_str(&data)
+                    .map_err(|err| log::error!("Unable to parse cache index: {}", err))
+                    .ok()
+            })
+            .unwrap_or_default();
+        entries.retain(|_, entry| dir.join(&entry.blob).is_file());
+
+        if let Ok(files) = std::fs::read_dir(&dir) {
+            let partial = files
+                .flatten()
+                .map(|file| file.path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == PARTIAL_EXTENSION));
+            for path in partial {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+
+        let mut cache = Self {
+            dir,
+            max_size,
+            entries,
+            descriptions: HashMap::new(),
+            dirty: false,
+        };
+        cache.evict();
+        cache
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn set_max_size(&mut self, max_size: u64) {
+        self.max_size = max_size;
+        self.evict();
+        self.save();
+    }
+
+    pub fn contains(&self, url: &str) -> bool {
+        self.entries.contains_key(&cache_key(url))
+    }
+
+    /// Path of the cached data of `url`, and marks the track as recently played
+    pub fn get(&mut self, url: &str) -> Option<PathBuf> {
+        let entry = self.entries.get_mut(&cache_key(url))?;
+        entry.last_used = now();
+        self.dirty = true;
+        Some(self.dir.join(&entry.blob))
+    }
+
+    /// Starts writing data of `url` to a temporary file, stored with [`AudioCache::finish`]
+    pub fn writer(&self, url: &str) -> std::io::Result<CacheWriter> {
+        let path = self
+            .dir
+            .join(format!("{:016x}.{}", fastrand::u64(..), PARTIAL_EXTENSION));
+
+        Ok(CacheWriter {
+            key: cache_key(url),
+            file: File::create(&path)?,
+            path: Some(path),
+            hash: ContentHash::new(),
+            size: 0,
+        })
+    }
+
+    /// Stores the complete data written with `writer`, and evicts old tracks when over the size limit
+    pub fn finish(&mut self, mut writer: CacheWriter) -> std::io::Result<()> {
+        writer.file.flush()?;
+        let Some(partial) = writer.path.take() else {
+            return Ok(());
+        };
+
+        let blob = format!("{:016x}.mp3", writer.hash.0);
+        let path = self.dir.join(&blob);
+        if path.is_file() {
+            // same content is already stored for another track
+            std::fs::remove_file(partial)?;
+        } else {
+            std::fs::rename(partial, path)?;
+        }
+
+        let (title, artist) = self.descriptions.remove(&writer.key).unwrap_or_default();
+        let previous = self.entries.insert(
+            writer.key.clone(),
+            CacheEntry {
+                blob,
+                size: writer.size,
+                last_used: now(),
+                pinned: None,
+                title,
+                artist,
+            },
+        );
+        if let (Some(previous), Some(entry)) = (previous, self.entries.get_mut(&writer.key)) {
+            entry.pinned = previous.pinned;
+            self.remove_unused(&previous.blob);
+        }
+
+        self.evict();
+        self.save();
+        Ok(())
+    }
+
+    /// Sets the track shown in the cache management view for `url`, kept until the track is cached if it is not yet
+    pub fn describe(&mut self, url: &str, title: &str, artist: &str) {
+        let key = cache_key(url);
+        match self.entries.get_mut(&key) {
+            Some(entry) if entry.title != title || entry.artist != artist => {
+                entry.title = title.to_string();
+                entry.artist = artist.to_string();
+                self.save();
+            }
+            Some(_) => {}
+            None => {
+                self.descriptions
+                    .insert(key, (title.to_string(), artist.to_string()));
+            }
+        }
+    }
+
+    /// Keeps the cached track offline as a part of `album`, `false` if it is not cached
+    pub fn pin(&mut self, url: &str, album: &str) -> bool {
+        let Some(entry) = self.entries.get_mut(&cache_key(url)) else {
+            return false;
+        };
+        entry.pinned = Some(album.to_string());
+        self.save();
+        true
+    }
+
+    /// Lets tracks of the album be evicted again
+    pub fn unpin_album(&mut self, album: &str) {
+        for entry in self.entries.values_mut() {
+            if entry.pinned.as_deref() == Some(album) {
+                entry.pinned = None;
+            }
+        }
+        self.evict();
+        self.save();
+    }
+
+    /// Removes all tracks which are not kept offline
+    pub fn clear(&mut self) {
+        let removed: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.pinned.is_none())
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        for key in removed {
+            self.remove(&key);
+        }
+        self.save();
+    }
+
+    /// Size of all stored data, tracks with the same content are counted once
+    pub fn used(&self) -> u64 {
+        let mut blobs = HashSet::new();
+        self.entries
+            .values()
+            .filter(|entry| blobs.insert(&entry.blob))
+            .map(|entry| entry.size)
+            .sum()
+    }
+
+    pub fn info(&self) -> CacheInfo {
+        let mut albums: Vec<PinnedAlbum> = Vec::new();
+        for entry in self.entries.values() {
+            let Some(name) = &entry.pinned else {
+                continue;
+            };
+
+            match albums.iter_mut().find(|album| &album.name == name) {
+                Some(album) => {
+                    album.tracks += 1;
+                    album.size += entry.size;
+                }
+                None => albums.push(PinnedAlbum {
+                    name: name.clone(),
+                    tracks: 1,
+                    size: entry.size,
+                }),
+            }
+        }
+        albums.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut recent: Vec<&CacheEntry> = self.entries.values().collect();
+        recent.sort_by_key(|entry| std::cmp::Reverse(entry.last_used));
+        let recent = recent
+            .into_iter()
+            .take(RECENT_TRACKS)
+            .map(|entry| CachedTrack {
+                title: entry.title.clone(),
+                artist: entry.artist.clone(),
+                size: entry.size,
+                pinned: entry.pinned.is_some(),
+            })
+            .collect();
+
+        CacheInfo {
+            dir: self.dir.display().to_string(),
+            used: self.used(),
+            max_size: self.max_size,
+            tracks: self.entries.len(),
+            albums,
+            recent,
+        }
+    }
+
+    /// Removes least recently played tracks until the cache fits its size limit
+    fn evict(&mut self) {
+        let mut candidates: Vec<(u64, String)> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.pinned.is_none())
+            .map(|(key, entry)| (entry.last_used, key.clone()))
+            .collect();
+        candidates.sort();
+
+        let mut candidates = candidates.into_iter();
+        while self.used() > self.max_size {
+            let Some((_, key)) = candidates.next() else {
+                break;
+            };
+            log::info!("Evicting `{}` from audio cache", key);
+            self.remove(&key);
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.remove_unused(&entry.blob);
+        }
+    }
+
+    /// Deletes data file which no entry refers to anymore
+    fn remove_unused(&self, blob: &str) {
+        if self.entries.values().all(|entry| entry.blob != blob) {
+            if let Err(err) = std::fs::remove_file(self.dir.join(blob)) {
+                log::error!("Unable to remove cached track: {}", err);
+            }
+        }
+    }
+
+    fn save(&mut self) {
+        self.dirty = false;
+        let result = serde_json::to_string(&self.entries)
+            .map_err(anyhow::Error::from)
+            .and_then(|data| Ok(std::fs::write(self.dir.join(INDEX_FILE), data)?));
+
+        result.unwrap_or_else(|op| {
+            log::error!("Unable to save cache index: {}", op);
+        });
+    }
+}
+
+impl Drop for AudioCache {
+    fn drop(&mut self) {
+        if self.dirty {
+            self.save();
+        }
+    }
+}
+
+/// Track being written to the cache, the temporary file is removed if it is not finished
+pub struct CacheWriter {
+    key: String,
+    file: File,
+    path: Option<PathBuf>,
+    hash: ContentHash,
+    size: u64,
+}
+
+impl CacheWriter {
+    pub fn write(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.file.write_all(data)?;
+        self.hash.update(data);
+        self.size += data.len() as u64;
+        Ok(())
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+impl Drop for CacheWriter {
+    fn drop(&mut self) {
+        if let Some(path) = self.path.take() {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Reader which stores everything read through it in the cache, once the whole track is read
+pub struct CachingReader<R> {
+    inner: R,
+    writer: Option<CacheWriter>,
+    /// Size of the complete track if known, shorter reads (ie broken connection) are not stored
+    length: Option<u64>,
+    cache: SharedCache,
+}
+
+impl<R: Read> CachingReader<R> {
+    /// Caches data of `url` read from `inner`, reads without caching if the cache can't be written
+    pub fn new(inner: R, url: &str, length: Option<u64>, cache: SharedCache) -> Self {
+        let writer = cache
+            .lock()
+            .unwrap()
+            .writer(url)
+            .map_err(|err| log::error!("Unable to write audio cache: {}", err))
+            .ok();
+
+        Self {
+            inner,
+            writer,
+            length,
+            cache,
+        }
+    }
+}
+
+impl<R: Read> Read for CachingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+
+        if read > 0 {
+            if let Some(Err(err)) = self.writer.as_mut().map(|writer| writer.write(&buf[..read])) {
+                log::error!("Unable to write audio cache: {}", err);
+                self.writer = None;
+            }
+        } else if let Some(writer) = self.writer.take() {
+            if self.length.is_none_or(|length| length == writer.size()) {
+                let key = writer.key.clone();
+                match self.cache.lock().unwrap().finish(writer) {
+                    Ok(()) => log::info!("Cached `{}`", key),
+                    Err(err) => log::error!("Unable to store cached track: {}", err),
+                }
+            }
+        }
+
+        Ok(read)
+    }
+}
+
+/// Downloads the track at `url` into the cache, unless it is cached already
+pub fn fetch(cache: &SharedCache, agent: &ureq::Agent, url: &str) -> anyhow::Result<()> {
+    if cache.lock().unwrap().contains(url) {
+        return Ok(());
+    }
+
+    let response = agent.get(url).call()?;
+    let length = response
+        .header("Content-Length")
+        .and_then(|length| length.parse().ok());
+
+    let mut reader = CachingReader::new(response.into_reader(), url, length, cache.clone());
+    std::io::copy(&mut reader, &mut std::io::sink())?;
+
+    if !cache.lock().unwrap().contains(url) {
+        anyhow::bail!("Incomplete download of `{}`", url);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_cache(name: &str, max_size: u64) -> AudioCache {
+        let dir = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        AudioCache::open(dir, max_size)
+    }
+
+    fn store(cache: &mut AudioCache, url: &str, data: &[u8]) {
+        let mut writer = cache.writer(url).unwrap();
+        writer.write(data).unwrap();
+        cache.finish(writer).unwrap();
+    }
+
+    #[test]
+    fn content_addressed() {
+        let mut cache = temp_cache("bc_rs_cache_test_content", 1000);
+        store(&mut cache, "https://t4.bcbits.com/stream/a/mp3-128/1?token=x", b"track");
+        store(&mut cache, "https://t4.bcbits.com/stream/b/mp3-128/2", b"track");
+
+        // same track with a new token
+        let path = cache.get("http://t4.bcbits.com/stream/a/mp3-128/1?token=y").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"track");
+        assert_eq!(cache.get("https://t4.bcbits.com/stream/b/mp3-128/2"), Some(path));
+        assert_eq!(cache.used(), 5);
+
+        // index survives reopening, unfinished writes don't
+        let unfinished = cache.writer("https://t4.bcbits.com/stream/c/mp3-128/3").unwrap();
+        drop(unfinished);
+        let cache = AudioCache::open(cache.dir().to_path_buf(), 1000);
+        assert_eq!(cache.info().tracks, 2);
+        assert_eq!(std::fs::read_dir(cache.dir()).unwrap().count(), 2);
+        std::fs::remove_dir_all(cache.dir()).unwrap();
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = temp_cache("bc_rs_cache_test_lru", 10);
+        store(&mut cache, "http://host/1", b"1111");
+        store(&mut cache, "http://host/2", b"2222");
+        assert!(cache.pin("http://host/2", "Album"));
+        cache.entries.get_mut("host/1").unwrap().last_used = 0;
+        store(&mut cache, "http://host/3", b"3333");
+
+        // pinned track stays even if it is older
+        assert!(!cache.contains("http://host/1"));
+        assert!(cache.contains("http://host/2"));
+        assert!(cache.contains("http://host/3"));
+        assert_eq!(
+            cache.info().albums,
+            vec![PinnedAlbum {
+                name: String::from("Album"),
+                tracks: 1,
+                size: 4
+            }]
+        );
+
+        cache.clear();
+        assert_eq!(cache.info().tracks, 1);
+        cache.unpin_album("Album");
+        cache.set_max_size(0);
+        assert_eq!(cache.used(), 0);
+        std::fs::remove_dir_all(cache.dir()).unwrap();
+    }
+
+    #[test]
+    fn saves_play_time_on_drop() {
+        let mut cache = temp_cache("bc_rs_cache_test_drop", 1000);
+        store(&mut cache, "http://host/1", b"1111");
+        cache.entries.get_mut("host/1").unwrap().last_used = 0;
+        cache.save();
+        let dir = cache.dir().to_path_buf();
+
+        // playing doesn't rewrite the index
+        assert!(cache.get("http://host/1").is_some());
+        assert_eq!(AudioCache::open(&dir, 1000).entries["host/1"].last_used, 0);
+
+        drop(cache);
+        assert!(AudioCache::open(&dir, 1000).entries["host/1"].last_used > 0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
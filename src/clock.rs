@@ -0,0 +1,13 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Longest listening time counted between two updates of history and scrobbler, so suspended system
+/// doesn't count as listening
+pub const MAX_LISTEN_STEP: Duration = Duration::from_secs(5);
+
+/// Current unix time in seconds
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
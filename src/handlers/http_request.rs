@@ -1,4 +1,9 @@
-use std::time::Duration;
+use std::{
+    collections::HashMap,
+    io::Read,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use base64::{engine::general_purpose, Engine};
 use copypasta::{ClipboardContext, ClipboardProvider};
@@ -8,40 +13,40 @@ use serde::Deserialize;
 use threadpool::ThreadPool;
 use ureq::Response;
 
+use crate::{
+    artwork::{ArtworkCache, ArtworkKey, Lookup},
+    bandcamp::{
+        details::TrackDetails,
+        discography::Discography,
+        fan::{
+            CollectionKind, CollectionPage, CollectionRequest, FanCollection, FanProfile,
+            FIRST_PAGE_TOKEN,
+        },
+        search::{SearchKind, SearchPage, SEARCH_URL},
+        tralbum::Tralbum,
+    },
+    handlers::config::{ArtworkThumbnailQuality, CacheConfig, ProxyConfig},
+};
+
 const THREAD_COUNT: usize = 10;
+/// Items requested per fancollection API call
+const COLLECTION_PAGE_SIZE: u32 = 100;
+/// Stops paging huge collections
+const MAX_COLLECTION_PAGES: usize = 100;
+
+type SharedArtworks = Arc<Mutex<ArtworkCache<Element>>>;
 
 pub struct HttpRequest {
     pool: ThreadPool,
+    agent: ureq::Agent,
+    artworks: SharedArtworks,
+    artwork_config: CacheConfig,
+    /// Track details by track id, pages are loaded once per session
+    track_details: Arc<Mutex<HashMap<u64, TrackDetails>>>,
     artwork_http: bool,
     request_http: bool,
 }
 
-fn fix_json(data: &str) -> String {
-    // fix url field
-    let regex = Regex::new("(?P<root>url: \".+)\" \\+ \"(?P<album>.+\",)").unwrap();
-    let data = regex.replace_all(data, "$root$album");
-
-    // add quotes to fields
-    let regex = Regex::new("    (?P<property>[a-zA-Z_]+):").unwrap();
-    let data = regex.replace_all(&data, "\"$property\":");
-
-    // remove comments
-    let regex = Regex::new("// .*").unwrap();
-    let data = regex.replace_all(&data, "");
-
-    data.into()
-}
-
-fn parse_album(html_code: String) -> Option<String> {
-    let start = "data-tralbum=\"{";
-    let stop = "}\"";
-
-    let album_data = &html_code[html_code.find(start)? + start.len() - 1..];
-    let album_data = &album_data[..=album_data.find(stop)?];
-    let album_data_json = fix_json(&album_data.replace("&quot;", "\""));
-    Some(album_data_json)
-}
-
 #[derive(Deserialize)]
 pub struct DiscoverAppData {
     #[serde(rename = "appData")]
@@ -67,9 +72,9 @@ pub struct Data {
     label: String,
 }
 
-fn get_tags_from_internet() -> anyhow::Result<Vec<String>> {
+fn get_tags_from_internet(agent: &ureq::Agent) -> anyhow::Result<Vec<String>> {
     log::info!("Loading tags from bandcamp.com...");
-    let response = ureq::get("https://bandcamp.com/discover").call()?;
+    let response = agent.get("https://bandcamp.com/discover").call()?;
 
     let response_text = &response.into_string().unwrap_or_default();
     log::info!("{}", response_text);
@@ -174,24 +179,171 @@ fn encode_response(
     }
 }
 
+/// Loads public fan profile and pages through its collection or wishlist
+fn get_fan_collection(
+    agent: &ureq::Agent,
+    username: &str,
+    kind: CollectionKind,
+) -> anyhow::Result<FanCollection> {
+    let profile = agent
+        .get(&format!("https://bandcamp.com/{}", username))
+        .timeout(Duration::from_secs(5))
+        .call()?
+        .into_string()?;
+    let fan = FanProfile::from_html(&profile)?;
+
+    let mut items = Vec::new();
+    let mut token = FIRST_PAGE_TOKEN.to_string();
+    for _ in 0..MAX_COLLECTION_PAGES {
+        let request = CollectionRequest {
+            fan_id: fan.fan_id,
+            older_than_token: &token,
+            count: COLLECTION_PAGE_SIZE,
+        };
+        let response = agent
+            .post(kind.api_url())
+            .timeout(Duration::from_secs(10))
+            .send_string(&serde_json::to_string(&request)?)?
+            .into_string()?;
+        let page = CollectionPage::from_json(&response)?;
+        items.extend(page.items);
+
+        match page.last_token {
+            Some(last_token) if page.more_available => token = last_token,
+            _ => break,
+        }
+    }
+
+    log::info!("Loaded {} items of {}'s {:?}", items.len(), fan.username, kind);
+    Ok(FanCollection { fan, kind, items })
+}
+
+/// Reads the artwork from disk, or downloads and stores it, as a `data:` URL
+fn load_artwork(
+    artworks: &SharedArtworks,
+    agent: &ureq::Agent,
+    key: ArtworkKey,
+    http: bool,
+) -> anyhow::Result<Arc<str>> {
+    let path = artworks.lock().unwrap().disk_path(&key);
+    let data = match path.map(std::fs::read) {
+        Some(Ok(data)) => {
+            log::debug!("Artwork {} loaded from disk cache", key.art_id);
+            data
+        }
+        cached => {
+            if let Some(Err(err)) = cached {
+                log::warn!("Unable to read cached artwork: {}", err);
+            }
+
+            let resp = agent.get(&key.url(http)).timeout(Duration::from_secs(5)).call()?;
+            if resp.status() != 200 {
+                anyhow::bail!("Artwork request returned status {}", resp.status());
+            }
+            let mut data = Vec::new();
+            resp.into_reader().read_to_end(&mut data)?;
+
+            let mut artworks = artworks.lock().unwrap();
+            if let Err(err) = artworks.store(key, &data) {
+                log::error!("Unable to cache artwork: {}", err);
+            }
+            let stats = artworks.stats();
+            log::info!(
+                "Artwork {} downloaded (cache hits: {} memory, {} disk; misses: {})",
+                key.art_id,
+                stats.memory_hits,
+                stats.disk_hits,
+                stats.misses
+            );
+            data
+        }
+    };
+
+    Ok(Arc::from(format!(
+        "data:image/jpeg;base64,{}",
+        general_purpose::STANDARD_NO_PAD.encode(data)
+    )))
+}
+
 impl HttpRequest {
-    pub fn new() -> Self {
-        let artwork_http = ureq::head("https://f4.bcbits.com/")
+    pub fn new(proxy: ProxyConfig, cache: &CacheConfig) -> Self {
+        let agent = proxy.agent(Duration::from_secs(3));
+
+        let artwork_http = agent
+            .head("https://f4.bcbits.com/")
             .timeout(Duration::from_secs(2))
             .call()
             .is_err();
-        let request_http = ureq::head("https://bandcamp.com/")
+        let request_http = agent
+            .head("https://bandcamp.com/")
             .timeout(Duration::from_secs(2))
             .call()
             .is_err();
 
         Self {
             pool: ThreadPool::new(THREAD_COUNT),
+            agent,
+            artworks: Arc::new(Mutex::new(ArtworkCache::open(
+                cache.artwork_path(),
+                cache.artwork_max_size(),
+            ))),
+            artwork_config: cache.clone(),
+            track_details: Arc::new(Mutex::new(HashMap::new())),
             artwork_http,
             request_http,
         }
     }
 
+    /// Applies proxy settings passed as JSON from [`Config::get_proxy`](crate::handlers::config::Config::get_proxy)
+    fn set_proxy(&mut self, proxy: String) {
+        match serde_json::from_str::<ProxyConfig>(&proxy) {
+            Ok(proxy) => self.agent = proxy.agent(Duration::from_secs(3)),
+            Err(err) => log::error!("Invalid proxy settings: {}", err),
+        }
+    }
+
+    /// Applies artwork cache settings passed as JSON from [`Config::get_cache`](crate::handlers::config::Config::get_cache)
+    fn set_artwork_cache(&mut self, config: String) {
+        let config = match serde_json::from_str::<CacheConfig>(&config) {
+            Ok(config) => config,
+            Err(err) => {
+                log::error!("Invalid cache settings: {}", err);
+                return;
+            }
+        };
+
+        if config.artwork_path() == self.artwork_config.artwork_path() {
+            let mut artworks = self.artworks.lock().unwrap();
+            artworks.set_max_size(config.artwork_max_size());
+        } else {
+            // requests in flight finish with the previous cache
+            self.artworks = Arc::new(Mutex::new(ArtworkCache::open(
+                config.artwork_path(),
+                config.artwork_max_size(),
+            )));
+        }
+        self.artwork_config = config;
+    }
+
+    /// Checks if bandcamp.com is reachable with current proxy settings
+    fn test_connection(&self, done: sciter::Value) {
+        let agent = self.agent.clone();
+        self.pool.execute(move || {
+            let result = agent
+                .head("https://bandcamp.com/")
+                .timeout(Duration::from_secs(5))
+                .call();
+
+            let message = match &result {
+                Ok(response) => format!("Connection OK ({})", response.status()),
+                Err(err) => format!("Connection failed: {}", err),
+            };
+            log::info!("{}", message);
+            done.call(None, &make_args!(result.is_ok(), message), None)
+                .unwrap();
+        });
+    }
+
     fn artwork_http(&self) -> bool {
         self.artwork_http
     }
@@ -202,6 +354,7 @@ impl HttpRequest {
 
     fn get_tags(&self, done: sciter::Value) {
         log::info!("Active pool count: {}", self.pool.active_count());
+        let agent = self.agent.clone();
         self.pool.execute(move || {
             let file = std::fs::read_to_string("tag.cache");
 
@@ -211,7 +364,7 @@ impl HttpRequest {
                 return;
             }
 
-            match get_tags_from_internet() {
+            match get_tags_from_internet(&agent) {
                 Ok(tags) => {
                     let tag_string = tags.join("\n");
                     std::fs::write("tag.cache", tag_string.clone()).unwrap();
@@ -225,9 +378,10 @@ impl HttpRequest {
     }
 
     fn http_request_get(&self, url: String, done: sciter::Value, failed: sciter::Value) {
+        let agent = self.agent.clone();
         self.pool.execute(move || {
             encode_response(
-                ureq::get(&url).timeout(Duration::from_secs(3)).call(),
+                agent.get(&url).timeout(Duration::from_secs(3)).call(),
                 done,
                 failed,
             );
@@ -241,26 +395,171 @@ impl HttpRequest {
         done: sciter::Value,
         failed: sciter::Value,
     ) {
-        let proxy = ureq::Proxy::new("socks5://51.222.146.133:59166").unwrap();
+        let agent = self.agent.clone();
+        self.pool.execute(move || {
+            encode_response(
+                agent
+                    .post(&url)
+                    .timeout(Duration::from_secs(3))
+                    .send_string(&body),
+                done,
+                failed,
+            );
+        });
+    }
 
-        let agent = if self.request_http {
-            ureq::AgentBuilder::new()
-                .proxy(proxy)
-                .timeout_connect(Duration::from_secs(5))
-                .build()
-        } else {
-            ureq::AgentBuilder::new()
-                .timeout(Duration::from_secs(3))
-                .build()
+    /// Searches Bandcamp, `kind` is `all`, `artists`, `albums` or `tracks`.
+    /// Calls `done` with [`SearchPage`] on success
+    fn search(&self, query: String, kind: String, page: i32, done: Value, failed: Value) {
+        let kind = match serde_json::from_value::<SearchKind>(serde_json::Value::String(kind)) {
+            Ok(kind) => kind,
+            Err(err) => {
+                failed
+                    .call(None, &make_args!(format!("Invalid search kind: {}", err)), None)
+                    .unwrap();
+                return;
+            }
+        };
+
+        let agent = self.agent.clone();
+        self.pool.execute(move || {
+            let response = agent
+                .get(SEARCH_URL)
+                .query("q", &query)
+                .query("item_type", kind.item_type())
+                .query("page", &page.max(1).to_string())
+                .timeout(Duration::from_secs(5))
+                .call();
+
+            let html = match response.map_err(anyhow::Error::from).and_then(|response| Ok(response.into_string()?)) {
+                Ok(html) => html,
+                Err(err) => {
+                    log::error!("Search failed: {}", err);
+                    failed
+                        .call(None, &make_args!(format!("Search failed: {}", err)), None)
+                        .unwrap();
+                    return;
+                }
+            };
+
+            let results = SearchPage::from_html(&html);
+            log::info!("Found {} results for `{}`", results.results.len(), query);
+            let results = serde_json::to_string(&results)
+                .ok()
+                .and_then(|json| Value::parse(&json).ok())
+                .unwrap_or(Value::null());
+            done.call(None, &make_args!(results), None).unwrap();
+        });
+    }
+
+    fn parse_album_data(&self, html_code: String) -> Value {
+        let tralbum = match Tralbum::from_html(&html_code) {
+            Ok(tralbum) => tralbum,
+            Err(err) => {
+                log::error!("Unable to parse album data: {}", err);
+                return Value::null();
+            }
+        };
+
+        serde_json::to_string(&tralbum)
+            .ok()
+            .and_then(|json| Value::parse(&json).ok())
+            .unwrap_or(Value::null())
+    }
+
+    /// Loads collection or wishlist (`kind`) of a public fan profile.
+    /// Calls `done` with [`FanCollection`] on success
+    fn fan_collection(&self, username: String, kind: String, done: Value, failed: Value) {
+        let kind = match serde_json::from_value::<CollectionKind>(serde_json::Value::String(kind)) {
+            Ok(kind) => kind,
+            Err(err) => {
+                failed
+                    .call(None, &make_args!(format!("Invalid collection kind: {}", err)), None)
+                    .unwrap();
+                return;
+            }
+        };
+
+        let agent = self.agent.clone();
+        self.pool.execute(move || match get_fan_collection(&agent, username.trim(), kind) {
+            Ok(collection) => {
+                let collection = serde_json::to_string(&collection)
+                    .ok()
+                    .and_then(|json| Value::parse(&json).ok())
+                    .unwrap_or(Value::null());
+                done.call(None, &make_args!(collection), None).unwrap();
+            }
+            Err(err) => {
+                log::error!("Unable to load fan collection: {}", err);
+                failed
+                    .call(None, &make_args!(format!("Unable to load collection: {}", err)), None)
+                    .unwrap();
+            }
+        });
+    }
+
+    /// Loads lyrics, notes, credits and tags of a track from its page `url`.
+    /// Calls `done` with [`TrackDetails`] on success
+    fn get_track_details(&self, url: String, track_id: String, done: Value, failed: Value) {
+        let Ok(track_id) = track_id.parse::<u64>() else {
+            failed
+                .call(None, &make_args!(format!("Invalid track id {}", track_id)), None)
+                .unwrap();
+            return;
         };
 
+        let to_value = |details: &TrackDetails| {
+            serde_json::to_string(details)
+                .ok()
+                .and_then(|json| Value::parse(&json).ok())
+                .unwrap_or(Value::null())
+        };
+        if let Some(details) = self.track_details.lock().unwrap().get(&track_id) {
+            done.call(None, &make_args!(to_value(details)), None).unwrap();
+            return;
+        }
+
+        let agent = self.agent.clone();
+        let track_details = self.track_details.clone();
         self.pool.execute(move || {
-            encode_response(agent.post(&url).send_string(&body), done, failed);
+            let details = agent
+                .get(&url)
+                .timeout(Duration::from_secs(5))
+                .call()
+                .map_err(anyhow::Error::from)
+                .and_then(|response| Ok(response.into_string()?))
+                .and_then(|html| Ok(TrackDetails::from_html(&html, track_id)?));
+
+            match details {
+                Ok(details) => {
+                    let value = to_value(&details);
+                    track_details.lock().unwrap().insert(track_id, details);
+                    done.call(None, &make_args!(value), None).unwrap();
+                }
+                Err(err) => {
+                    log::error!("Unable to load track details: {}", err);
+                    failed
+                        .call(None, &make_args!(format!("Unable to load track details: {}", err)), None)
+                        .unwrap();
+                }
+            }
         });
     }
 
-    fn parse_album_data(&self, html_code: String) -> String {
-        parse_album(html_code).unwrap_or_default()
+    /// Parses artist or label `/music` page, `null` if the page has no discography
+    fn parse_discography(&self, html_code: String, url: String) -> Value {
+        let discography = match Discography::from_html(&html_code, &url) {
+            Ok(discography) => discography,
+            Err(err) => {
+                log::warn!("Unable to parse discography: {}", err);
+                return Value::null();
+            }
+        };
+
+        serde_json::to_string(&discography)
+            .ok()
+            .and_then(|json| Value::parse(&json).ok())
+            .unwrap_or(Value::null())
     }
 
     fn open_in_browser(&self, url: String) -> bool {
@@ -272,30 +571,37 @@ impl HttpRequest {
         ctx.set_contents(url).is_ok()
     }
 
-    fn set_image(&self, url: String, mut element: Element) {
-        self.pool.execute(move || {
-            let response = ureq::get(&url).timeout(Duration::from_secs(5)).call();
-
-            match response {
-                Ok(resp) => {
-                    if resp.status() == 200 {
-                        let mut buf = Vec::new();
-                        let _ = resp.into_reader().read_to_end(&mut buf);
-
-                        element
-                            .set_attribute(
-                                "src",
-                                &format!(
-                                    "data:image/jpeg;base64,{}",
-                                    general_purpose::STANDARD_NO_PAD.encode(buf)
-                                ),
-                            )
-                            .unwrap();
-                    }
-                }
+    /// Sets `src` of the element to the artwork, `art_id` is a string as it doesn't fit in script integers
+    fn set_image(&self, art_id: String, quality: i32, mut element: Element) {
+        let (Ok(art_id), Some(quality)) = (art_id.parse(), ArtworkThumbnailQuality::from_id(quality)) else {
+            log::error!("Invalid artwork {} with quality {}", art_id, quality);
+            return;
+        };
+        let key = ArtworkKey::new(art_id, quality);
+
+        let lookup = self.artworks.lock().unwrap().request(key, element.clone());
+        match lookup {
+            Lookup::Memory(data_url) => {
+                log::debug!("Artwork {} found in memory cache", art_id);
+                element.set_attribute("src", &data_url).unwrap();
+                return;
+            }
+            Lookup::Pending => return,
+            Lookup::Load => {}
+        }
 
-                Err(msg) => {
-                    log::error!("Failed to get artwork: {}", msg);
+        let agent = self.agent.clone();
+        let artworks = self.artworks.clone();
+        let http = self.artwork_http;
+        self.pool.execute(move || {
+            let data_url = load_artwork(&artworks, &agent, key, http)
+                .map_err(|msg| log::error!("Failed to get artwork: {}", msg))
+                .ok();
+
+            let waiters = artworks.lock().unwrap().finish(key, data_url.clone());
+            if let Some(data_url) = data_url {
+                for mut element in waiters {
+                    element.set_attribute("src", &data_url).unwrap();
                 }
             }
         });
@@ -304,7 +610,7 @@ impl HttpRequest {
 
 impl Default for HttpRequest {
     fn default() -> Self {
-        Self::new()
+        Self::new(ProxyConfig::default(), &CacheConfig::default())
     }
 }
 
@@ -312,12 +618,19 @@ impl sciter::EventHandler for HttpRequest {
     dispatch_script_call! {
         fn http_request_get(String, Value, Value);
         fn http_request_post(String, String, Value, Value);
-        fn set_image(String, Element);
+        fn set_image(String, i32, Element);
         fn parse_album_data(String);
+        fn parse_discography(String, String);
+        fn fan_collection(String, String, Value, Value);
+        fn get_track_details(String, String, Value, Value);
+        fn search(String, String, i32, Value, Value);
         fn open_in_browser(String);
         fn copy_to_clipboard(String);
         fn get_tags(Value);
         fn artwork_http();
         fn request_http();
+        fn set_proxy(String);
+        fn set_artwork_cache(String);
+        fn test_connection(Value);
     }
 }
@@ -1,65 +1,403 @@
-use std::{sync::mpsc, time::Duration};
+use std::{
+    sync::{mpsc, Arc, Mutex},
+    time::Duration,
+};
 
 use raw_window_handle::Win32WindowHandle;
 use sciter::{dispatch_script_call, make_args, Element, Value, dom::{self, event::{BEHAVIOR_EVENTS, PHASE_MASK}}};
 use souvlaki::{MediaControlEvent, MediaControls, MediaMetadata, MediaPlayback, PlatformConfig};
 
-use crate::players::{self, bass::BassPlayer, AudioSystem};
+use crate::{
+    cache::{self, AudioCache, SharedCache},
+    history::{History, HISTORY_FILE},
+    playlist::{self, Playlist, PlaylistFormat, PlaylistStore, PLAYLISTS_DIR},
+    handlers::config::{
+        CacheConfig, CrossfadeConfig, NormalizationConfig, ProxyConfig, ScrobblerConfig, TempoConfig,
+    },
+    loudness::{LoudnessDb, LOUDNESS_FILE},
+    players::{
+        self, bass::BassPlayer, equalizer::EqualizerConfig, null::NullPlayer,
+        unavailable::UnavailablePlayer, AudioSystem, PlaybackOptions, PlayerEvent,
+    },
+    queue::{Queue, QueueTrack, RepeatMode, QUEUE_FILE},
+    resolver::StreamResolver,
+    scrobbler::{self, Scrobbler, SCROBBLES_FILE},
+};
+
+const REPEAT_MODES: [RepeatMode; 3] = [RepeatMode::Off, RepeatMode::One, RepeatMode::All];
+
+/// Queue track which starts once its stream URL is refreshed in background
+struct PendingPlay {
+    track_id: u64,
+    /// Restored queue starts paused at this position, in seconds
+    restore_position: Option<u64>,
+}
 
 pub struct Player {
     player: Box<dyn players::Player>,
     _selected_audiosystem: AudioSystem,
-    event: sciter::Value,
+    /// Why no audio backend could be started, shown once the window is loaded
+    backend_error: Option<String>,
+    /// Called when playback state changes, set by the UI
+    event: Option<sciter::Value>,
     rx: mpsc::Receiver<MediaControlEvent>,
     tx: mpsc::SyncSender<MediaControlEvent>,
     controls: Option<MediaControls>,
-    sample_values: sciter::Value,
+    proxy: ProxyConfig,
+    options: PlaybackOptions,
+    queue: Queue,
+    loudness: LoudnessDb,
+    cache_config: CacheConfig,
+    cache: Option<SharedCache>,
+    resolver: StreamResolver,
+    /// Current queue track waiting for its stream URL
+    pending: Option<PendingPlay>,
+    scrobbler: Scrobbler,
+    scrobbler_config: ScrobblerConfig,
+    history: History,
+    playlists: PlaylistStore,
+}
+
+fn open_cache(config: &CacheConfig) -> Option<SharedCache> {
+    config.enabled.then(|| {
+        let cache = AudioCache::open(config.path(), config.max_size());
+        Arc::new(Mutex::new(cache))
+    })
+}
+
+fn create_backend(
+    backend: AudioSystem,
+    device_id: usize,
+    proxy: &ProxyConfig,
+    options: &PlaybackOptions,
+    cache: &Option<SharedCache>,
+) -> anyhow::Result<Box<dyn players::Player>> {
+    let mut player: Box<dyn players::Player> = match backend {
+        AudioSystem::Bass => Box::new(BassPlayer::new(device_id)?),
+        #[cfg(feature = "native-audio")]
+        AudioSystem::Native => Box::new(players::native::NativePlayer::new(device_id)?),
+        #[cfg(not(feature = "native-audio"))]
+        AudioSystem::Native => anyhow::bail!("Built without `native-audio` feature"),
+        AudioSystem::Null => Box::new(NullPlayer::new(1.0)),
+    };
+    player.set_proxy(proxy);
+    player.set_audio_cache(cache.clone());
+    options.apply(player.as_mut());
+    Ok(player)
 }
 
 impl Player {
-    pub fn new(backend: AudioSystem, device_id: usize) -> Self {
+    pub fn new(
+        backend: AudioSystem,
+        device_id: usize,
+        proxy: ProxyConfig,
+        options: PlaybackOptions,
+        cache_config: CacheConfig,
+        scrobbler_config: ScrobblerConfig,
+    ) -> Self {
         let (tx, rx): (
             mpsc::SyncSender<MediaControlEvent>,
             mpsc::Receiver<MediaControlEvent>,
         ) = mpsc::sync_channel(32);
+        let cache = open_cache(&cache_config);
 
-        let bass = BassPlayer::new(device_id).expect("Unable to initialize bass library");
-
-        match backend {
-            AudioSystem::Bass => {
-                Self {
-                    sample_values: sciter::Value::new(),
-                    controls: None,
-                    rx,
-                    tx,
-                    event: sciter::Value::new(),
-                    player: Box::new(bass),
-                    _selected_audiosystem: AudioSystem::Bass,
+        // fall back to the other backend which plays sound, no sound only if it was selected
+        let mut errors = Vec::new();
+        let mut candidates = vec![backend];
+        candidates.extend(
+            [AudioSystem::Bass, AudioSystem::Native]
+                .into_iter()
+                .filter(|&candidate| candidate != backend),
+        );
+        let started = candidates.into_iter().find_map(|backend| {
+            match create_backend(backend, device_id, &proxy, &options, &cache) {
+                Ok(player) => Some((player, backend)),
+                Err(err) => {
+                    log::error!("Unable to initialize {:?} audio backend: {}", backend, err);
+                    errors.push(format!("{:?}: {}", backend, err));
+                    None
+                }
+            }
+        });
+        let (player, backend_error): (Box<dyn players::Player>, _) = match started {
+            Some((player, started)) => {
+                if started != backend {
+                    log::warn!("Using {:?} audio backend instead of {:?}", started, backend);
                 }
+                (player, None)
             }
+            None => {
+                let error = format!("Unable to start audio: {}", errors.join(", "));
+                (Box::new(UnavailablePlayer::new(error.clone())), Some(error))
+            }
+        };
+
+        Self {
+            controls: None,
+            rx,
+            tx,
+            event: None,
+            player,
+            _selected_audiosystem: backend,
+            backend_error,
+            resolver: StreamResolver::new(&proxy),
+            pending: None,
+            scrobbler: Scrobbler::start(scrobbler_config.clone(), &proxy, SCROBBLES_FILE),
+            scrobbler_config,
+            history: History::load(HISTORY_FILE),
+            playlists: PlaylistStore::new(PLAYLISTS_DIR),
+            proxy,
+            options,
+            queue: Queue::load(QUEUE_FILE),
+            loudness: LoudnessDb::load(LOUDNESS_FILE),
+            cache_config,
+            cache,
         }
     }
 
     fn switch_backend(&mut self, backend: i32, device_id: i32) -> bool {
-        match backend {
-            0 => {
-                if let Ok(bass) = BassPlayer::new(device_id as usize) {
-                    self.player.stop();
-                    self.player = Box::new(bass);
-                    true
-                } else {
-                    false
-                }
-            }
+        let backend = match backend {
+            0 => AudioSystem::Bass,
+            1 => AudioSystem::Native,
+            2 => AudioSystem::Null,
             _ => {
-                log::error!("Invalid backend value out of range 1 < {}", backend);
+                log::error!("Invalid backend value out of range 2 < {}", backend);
+                return false;
+            }
+        };
+
+        match create_backend(
+            backend,
+            device_id.max(0) as usize,
+            &self.proxy,
+            &self.options,
+            &self.cache,
+        ) {
+            Ok(player) => {
+                self.player.stop();
+                self.player = player;
+                self._selected_audiosystem = backend;
+                self.backend_error = None;
+                true
+            }
+            Err(err) => {
+                log::error!("Unable to switch audio backend: {}", err);
                 false
             }
         }
     }
 
+    /// Applies proxy settings passed as JSON from [`Config::get_proxy`](crate::handlers::config::Config::get_proxy)
+    fn set_stream_proxy(&mut self, proxy: String) {
+        match serde_json::from_str::<ProxyConfig>(&proxy) {
+            Ok(proxy) => {
+                self.player.set_proxy(&proxy);
+                self.resolver.set_proxy(&proxy);
+                self.scrobbler.configure(self.scrobbler_config.clone(), proxy.clone());
+                self.proxy = proxy;
+            }
+            Err(err) => log::error!("Invalid proxy settings: {}", err),
+        }
+    }
+
+    /// Applies crossfade settings passed as JSON from [`Config::get_crossfade`](crate::handlers::config::Config::get_crossfade)
+    fn set_crossfade(&mut self, crossfade: String) {
+        match serde_json::from_str::<CrossfadeConfig>(&crossfade) {
+            Ok(crossfade) => {
+                self.player.set_crossfade(&crossfade);
+                self.options.crossfade = crossfade;
+            }
+            Err(err) => log::error!("Invalid crossfade settings: {}", err),
+        }
+    }
+
+    fn set_pause_fade(&mut self, enabled: bool) {
+        self.player.set_pause_fade(enabled);
+        self.options.pause_fade = enabled;
+    }
+
+    /// Applies speed and pitch passed as JSON from [`Config::get_tempo`](crate::handlers::config::Config::get_tempo)
+    fn set_tempo(&mut self, tempo: String) {
+        match serde_json::from_str::<TempoConfig>(&tempo) {
+            Ok(tempo) => {
+                let tempo = tempo.clamped();
+                self.player.set_speed(tempo.speed);
+                self.player.set_pitch(tempo.pitch);
+                self.options.tempo = tempo;
+            }
+            Err(err) => log::error!("Invalid speed and pitch settings: {}", err),
+        }
+    }
+
+    /// Applies equalizer settings passed as JSON from [`Config::update_equalizer`](crate::handlers::config::Config::update_equalizer)
+    fn apply_equalizer(&mut self, equalizer: String) {
+        match serde_json::from_str::<EqualizerConfig>(&equalizer) {
+            Ok(equalizer) => {
+                self.player.set_equalizer(&equalizer);
+                self.options.equalizer = equalizer;
+            }
+            Err(err) => log::error!("Invalid equalizer settings: {}", err),
+        }
+    }
+
+    /// Applies normalization settings passed as JSON from [`Config::get_normalization`](crate::handlers::config::Config::get_normalization),
+    /// they are used from the next track
+    fn set_normalization(&mut self, normalization: String) {
+        match serde_json::from_str::<NormalizationConfig>(&normalization) {
+            Ok(normalization) => self.options.normalization = normalization,
+            Err(err) => log::error!("Invalid normalization settings: {}", err),
+        }
+    }
+
+    /// Tells the player gain of the track before it is opened, or that its loudness is unknown,
+    /// and names the track in the audio cache
+    fn prepare_track(&mut self, track: &QueueTrack, url: &str) {
+        let normalization = self.options.normalization;
+        let gain = match self.loudness.gain(track.track_id, normalization.target) {
+            Some(gain) if normalization.enabled => Some(gain),
+            Some(_) => Some(0.0),
+            None => None,
+        };
+        self.player.set_track_gain(url, gain);
+
+        if let Some(cache) = &self.cache {
+            cache.lock().unwrap().describe(url, &track.title, &track.artist);
+        }
+    }
+
+    /// Applies audio cache settings passed as JSON from [`Config::get_cache`](crate::handlers::config::Config::get_cache)
+    fn set_cache(&mut self, config: String) {
+        let config = match serde_json::from_str::<CacheConfig>(&config) {
+            Ok(config) => config,
+            Err(err) => {
+                log::error!("Invalid cache settings: {}", err);
+                return;
+            }
+        };
+        if config == self.cache_config {
+            return;
+        }
+
+        match &self.cache {
+            // only the size changed
+            Some(cache) if config.enabled && config.path() == self.cache_config.path() => {
+                cache.lock().unwrap().set_max_size(config.max_size());
+            }
+            _ => {
+                self.cache = open_cache(&config);
+                self.player.set_audio_cache(self.cache.clone());
+            }
+        }
+        self.cache_config = config;
+    }
+
+    /// Applies scrobbler settings and credentials from [`ScrobblerConfig`] JSON
+    fn set_scrobbler(&mut self, config: String) {
+        match serde_json::from_str::<ScrobblerConfig>(&config) {
+            Ok(config) => {
+                self.scrobbler.configure(config.clone(), self.proxy.clone());
+                self.scrobbler_config = config;
+            }
+            Err(err) => log::error!("Invalid scrobbler settings: {}", err),
+        }
+    }
+
+    /// Logs in to the scrobbling service, `done` gets the session key to store in settings
+    fn scrobbler_login(&self, username: String, password: String, done: Value, failed: Value) {
+        let config = self.scrobbler_config.clone();
+        let proxy = self.proxy.clone();
+        std::thread::spawn(move || {
+            match scrobbler::login(&config, &proxy, &username, &password) {
+                Ok(session_key) => done.call(None, &make_args!(session_key), None).unwrap(),
+                Err(err) => {
+                    log::error!("{}", err);
+                    failed.call(None, &make_args!(err.to_string()), None).unwrap()
+                }
+            };
+        });
+    }
+
+    /// Returns cache usage as JSON, see [`cache::CacheInfo`], `null` if the cache is disabled
+    fn get_cache_info(&self) -> String {
+        let info = self.cache.as_ref().map(|cache| cache.lock().unwrap().info());
+        serde_json::to_string(&info).unwrap_or_default()
+    }
+
+    /// Removes cached tracks, except albums kept offline
+    fn clear_cache(&mut self) {
+        if let Some(cache) = &self.cache {
+            cache.lock().unwrap().clear();
+        }
+    }
+
+    /// Downloads tracks passed as JSON into the cache in background, and keeps them as `album`.
+    /// Returns `false` if the cache is disabled
+    fn cache_keep_offline(&mut self, tracks: String, album: String) -> bool {
+        let (Some(cache), Some(tracks)) = (self.cache.clone(), Self::parse_tracks(&tracks)) else {
+            return false;
+        };
+        let agent = self
+            .proxy
+            .agent_builder()
+            .timeout_connect(Duration::from_secs(15))
+            .timeout_read(Duration::from_secs(15))
+            .build();
+
+        std::thread::spawn(move || {
+            let mut kept = 0;
+            for track in tracks.iter() {
+                let Some(url) = track.stream_url() else {
+                    continue;
+                };
+
+                cache.lock().unwrap().describe(url, &track.title, &track.artist);
+                match cache::fetch(&cache, &agent, url) {
+                    Ok(()) => {
+                        cache.lock().unwrap().pin(url, &album);
+                        kept += 1;
+                    }
+                    Err(err) => log::error!("Unable to download `{}`: {}", track.title, err),
+                }
+            }
+            log::info!("Kept {} of {} tracks of `{}` offline", kept, tracks.len(), album);
+        });
+        true
+    }
+
+    /// Lets tracks of the album be evicted from the cache again
+    fn cache_unpin_album(&mut self, album: String) {
+        if let Some(cache) = &self.cache {
+            cache.lock().unwrap().unpin_album(&album);
+        }
+    }
+
+    /// Stores loudness the player measured, by id of the queue track with the URL
+    fn store_loudness(&mut self) {
+        let mut changed = false;
+
+        while let Some((url, loudness)) = self.player.poll_loudness() {
+            let track = self
+                .queue
+                .tracks()
+                .iter()
+                .find(|track| track.stream_url() == Some(url.as_str()));
+
+            if let Some(track) = track {
+                log::info!("Measured loudness of `{}`: {:.1} LUFS", track.title, loudness);
+                self.loudness.insert(track.track_id, loudness);
+                changed = true;
+            }
+        }
+
+        if changed {
+            self.loudness.save(LOUDNESS_FILE).unwrap_or_else(|op| {
+                log::error!("Unable to save loudness database: {}", op);
+            });
+        }
+    }
+
     fn set_state_change_callback(&mut self, value: sciter::Value) {
-        self.event = value;
+        self.event = Some(value);
     }
 
     fn fmt_time(&mut self, time: i32) -> String {
@@ -68,10 +406,455 @@ impl Player {
 
     pub fn load_track(&mut self, url: String) -> bool {
         let res = self.player.switch_track(url).is_ok();
-        self.event.call(None, &make_args!(""), None).unwrap();
+        self.force_update();
+        res
+    }
+
+    /// Follows the queue when track ends. Returns `"advanced"` if playback continued with the next track
+    /// or the track waiting for its stream URL started, `"failed"` if the track couldn't be started,
+    /// `"ended"` if the queue ended, or empty string if nothing happened since last call
+    fn poll_track_event(&mut self) -> String {
+        self.store_loudness();
+        if let Some(started) = self.apply_resolved_urls() {
+            self.force_update();
+            return String::from(if started { "advanced" } else { "failed" });
+        }
+        self.scrobbler.update(!self.player.is_paused());
+        self.history.update(!self.player.is_paused());
+
+        match self.player.poll_event() {
+            Some(PlayerEvent::Advanced) => {
+                self.scrobbler.track_ended();
+                self.queue.advance();
+                if let Some(track) = self.queue.current() {
+                    self.history.track_started(track);
+                }
+                self.preload_next();
+                self.update_playback();
+                self.force_update();
+                String::from("advanced")
+            }
+            Some(PlayerEvent::Ended) => {
+                if self.queue.advance().is_none() {
+                    self.history.track_finished();
+                    self.force_update();
+                    String::from("ended")
+                } else if self.play_current() {
+                    String::from("advanced")
+                } else {
+                    String::from("failed")
+                }
+            }
+            None => String::new(),
+        }
+    }
+
+    /// `true` if the track plays from the audio cache, so its stream URL doesn't have to be fresh
+    fn is_cached(&self, track: &QueueTrack) -> bool {
+        let cache = self.cache.as_ref();
+        track
+            .stream_url()
+            .is_some_and(|url| cache.is_some_and(|cache| cache.lock().unwrap().contains(url)))
+    }
+
+    /// Stream URL of the track which can be played now, `None` if it has to be refreshed first.
+    /// Tracks from playlists have no stream URL yet either
+    fn stream_url(&self, track: &QueueTrack) -> Option<String> {
+        if self.is_cached(track) {
+            return track.stream_url().map(String::from);
+        }
+        self.resolver.fresh_url(track)
+    }
+
+    /// Stores stream URLs refreshed in background, starts the current track if it was waiting for one,
+    /// and preloads the next track if it was. Returns whether the waiting track started, `None` if there was none
+    fn apply_resolved_urls(&mut self) -> Option<bool> {
+        let next_track = self.queue.next_track().map(|track| track.track_id);
+        let mut preload = false;
+        let mut started = None;
+        for (track_id, url) in self.resolver.poll() {
+            if let Some(url) = &url {
+                self.queue.set_stream_url(track_id, url);
+                preload |= next_track == Some(track_id);
+            }
+
+            if let Some(pending) = self.pending.take_if(|pending| pending.track_id == track_id) {
+                started = self.start_pending(pending, url);
+            }
+        }
+
+        if preload {
+            self.preload_next();
+        }
+        started
+    }
+
+    /// Starts the track which waited for its stream URL, `None` if it is not current anymore
+    fn start_pending(&mut self, pending: PendingPlay, url: Option<String>) -> Option<bool> {
+        let track = self
+            .queue
+            .current()
+            .filter(|track| track.track_id == pending.track_id)
+            .cloned()?;
+
+        let started = url.is_some_and(|url| self.start_track(&track, url, false));
+        if let Some(position) = pending.restore_position.filter(|_| started) {
+            self.seek(position as i32);
+            self.player.set_paused(true);
+        }
+        self.preload_next();
+        self.update_playback();
+        Some(started)
+    }
+
+    /// Stops playback until stream URL of the track is refreshed in background, it is started then
+    fn wait_for_url(&mut self, track: &QueueTrack) {
+        self.player.stop();
+        self.resolver.resolve_in_background(track);
+        self.pending = Some(PendingPlay {
+            track_id: track.track_id,
+            restore_position: None,
+        });
+    }
+
+    /// Starts the track from `url`, returns `false` if it can't be loaded.
+    /// With `retry`, track which fails to load (ie access denied to expired stream URL) waits for a fresh URL instead
+    fn start_track(&mut self, track: &QueueTrack, url: String, retry: bool) -> bool {
+        self.prepare_track(track, &url);
+        if self.load_track(url) {
+            self.history.track_started(track);
+        } else if retry {
+            log::warn!("Retrying `{}` with refreshed stream URL", track.title);
+            self.wait_for_url(track);
+        } else {
+            return false;
+        }
+        true
+    }
+
+    /// Starts current queue track, returns `false` if it can't be loaded.
+    /// Track without fresh stream URL starts once the URL is refreshed in background, see [`Player::poll_track_event`]
+    fn play_current(&mut self) -> bool {
+        self.scrobbler.track_ended();
+        self.history.track_finished();
+        self.pending = None;
+
+        let res = match self.queue.current().cloned() {
+            Some(track) => match self.stream_url(&track) {
+                Some(url) => self.start_track(&track, url, true),
+                None => {
+                    self.wait_for_url(&track);
+                    true
+                }
+            },
+            None => false,
+        };
+        self.preload_next();
+        self.update_playback();
         res
     }
 
+    /// Lets player buffer the track which plays after current one.
+    /// Expired stream URL is refreshed in background first, and the track is preloaded once it's ready
+    fn preload_next(&mut self) {
+        let track = self.queue.next_track().cloned();
+        let url = track.as_ref().and_then(|track| {
+            if self.is_cached(track) {
+                return track.stream_url().map(String::from);
+            }
+
+            let url = self.resolver.fresh_url(track);
+            if url.is_none() {
+                self.resolver.resolve_in_background(track);
+            }
+            url
+        });
+
+        if let (Some(track), Some(url)) = (&track, &url) {
+            self.prepare_track(track, url);
+        }
+        self.player.set_next_track(url);
+    }
+
+    /// Returns queue tracks and state: `{ tracks, position, shuffle, repeat }`
+    fn get_queue(&self) -> Value {
+        let state = serde_json::json!({
+            "tracks": self.queue.tracks(),
+            "position": self.queue.position(),
+            "shuffle": self.queue.shuffle(),
+            "repeat": REPEAT_MODES.iter().position(|&v| v == self.queue.repeat()).unwrap_or(0),
+        });
+
+        Value::parse(&state.to_string()).unwrap_or_else(|_| Value::null())
+    }
+
+    fn parse_tracks(tracks: &str) -> Option<Vec<QueueTrack>> {
+        serde_json::from_str(tracks)
+            .map_err(|err| log::error!("Invalid queue tracks: {}", err))
+            .ok()
+    }
+
+    /// Appends tracks passed as JSON array to the queue
+    fn queue_add(&mut self, tracks: String) -> bool {
+        let Some(tracks) = Self::parse_tracks(&tracks) else {
+            return false;
+        };
+        self.queue.add(tracks);
+        self.preload_next();
+        self.force_update();
+        true
+    }
+
+    /// Inserts tracks passed as JSON array right after the current one
+    fn queue_insert_next(&mut self, tracks: String) -> bool {
+        let Some(tracks) = Self::parse_tracks(&tracks) else {
+            return false;
+        };
+        self.queue.insert_next(tracks);
+        self.preload_next();
+        self.force_update();
+        true
+    }
+
+    fn queue_remove(&mut self, index: i32) -> bool {
+        let current = index as usize == self.queue.position();
+        if !self.queue.remove(index as usize) {
+            return false;
+        }
+
+        if current {
+            self.player.stop();
+        }
+        self.preload_next();
+        self.force_update();
+        true
+    }
+
+    fn queue_move(&mut self, from: i32, to: i32) -> bool {
+        let res = self.queue.move_track(from as usize, to as usize);
+        self.preload_next();
+        self.force_update();
+        res
+    }
+
+    fn queue_clear(&mut self) {
+        self.pending = None;
+        self.player.stop();
+        self.scrobbler.track_ended();
+        self.history.track_finished();
+        self.queue.clear();
+        self.preload_next();
+        self.force_update();
+    }
+
+    /// Plays track at the queue index, returns `false` if it couldn't be started
+    fn queue_play(&mut self, index: i32) -> bool {
+        self.queue.set_position(index as usize) && self.play_current()
+    }
+
+    /// Plays next track, returns `false` if it couldn't be started
+    fn queue_next(&mut self) -> bool {
+        self.queue.skip_next().is_none() || self.play_current()
+    }
+
+    /// Plays previous track, or seeks current to the start if there is nothing before it.
+    /// Returns `false` if the track couldn't be started
+    fn queue_previous(&mut self) -> bool {
+        if self.queue.previous().is_some() {
+            self.play_current()
+        } else {
+            self.seek(0);
+            true
+        }
+    }
+
+    /// Refreshes stream URLs of all queue tracks in background
+    fn queue_refresh_stream_urls(&mut self) {
+        self.resolver.clear();
+        for track in self.queue.tracks().to_vec() {
+            self.resolver.resolve_in_background(&track);
+        }
+    }
+
+    /// Latest played tracks as JSON array of [`crate::history::Play`], newest first
+    fn history_recent(&self, limit: i32) -> Value {
+        let plays = self.history.recently_played(limit.max(0) as usize);
+        serde_json::to_string(&plays)
+            .ok()
+            .and_then(|json| Value::parse(&json).ok())
+            .unwrap_or(Value::null())
+    }
+
+    /// Tracks played the most as JSON array of [`crate::history::PlayCount`]
+    fn history_most_played(&self, limit: i32) -> Value {
+        let counts = self.history.most_played(limit.max(0) as usize);
+        serde_json::to_string(&counts)
+            .ok()
+            .and_then(|json| Value::parse(&json).ok())
+            .unwrap_or(Value::null())
+    }
+
+    /// Adds a track from history to the queue again, after the current one if `next`.
+    /// Its stream URL has likely expired by now, it's refreshed before playing
+    fn history_enqueue(&mut self, track_id: String, next: bool) -> bool {
+        let Some(track) = track_id.parse().ok().and_then(|track_id| self.history.find(track_id)) else {
+            log::warn!("Track {} is not in history", track_id);
+            return false;
+        };
+
+        let tracks = vec![track.clone()];
+        if next {
+            self.queue.insert_next(tracks);
+        } else {
+            self.queue.add(tracks);
+        }
+        self.preload_next();
+        self.force_update();
+        true
+    }
+
+    /// Stored playlists as JSON array of [`crate::playlist::PlaylistInfo`]
+    fn playlist_list(&self) -> Value {
+        serde_json::to_string(&self.playlists.list())
+            .ok()
+            .and_then(|json| Value::parse(&json).ok())
+            .unwrap_or(Value::null())
+    }
+
+    /// Saves queue tracks as a playlist, replacing one with the same name
+    fn playlist_save(&self, name: String) -> bool {
+        let playlist = Playlist::from_queue(name.trim(), self.queue.tracks());
+        self.playlists
+            .save(&playlist)
+            .map_err(|err| log::error!("Unable to save playlist: {}", err))
+            .is_ok()
+    }
+
+    /// Adds playlist tracks to the queue, or replaces the queue with them.
+    /// Stream URLs are resolved before each track plays
+    fn playlist_load(&mut self, name: String, replace: bool) -> bool {
+        let playlist = match self.playlists.load(&name) {
+            Ok(playlist) => playlist,
+            Err(err) => {
+                log::error!("Unable to load playlist `{}`: {}", name, err);
+                return false;
+            }
+        };
+
+        if replace {
+            self.queue_clear();
+        }
+        self.queue.add(playlist.tracks.iter().map(|track| track.to_queue_track()).collect());
+        self.preload_next();
+        self.force_update();
+        true
+    }
+
+    fn playlist_delete(&self, name: String) -> bool {
+        self.playlists
+            .delete(&name)
+            .map_err(|err| log::error!("Unable to delete playlist: {}", err))
+            .is_ok()
+    }
+
+    /// Writes stored playlist to `path`, format is chosen by its extension
+    fn playlist_export(&self, name: String, path: String) -> bool {
+        let path = std::path::PathBuf::from(path);
+        let result = PlaylistFormat::from_path(&path)
+            .ok_or_else(|| anyhow::anyhow!("Unknown playlist format of `{}`", path.display()))
+            .and_then(|format| self.playlists.load(&name)?.export(format))
+            .and_then(|data| Ok(std::fs::write(&path, data)?));
+
+        match result {
+            Ok(()) => {
+                log::info!("Playlist `{}` exported to `{}`", name, path.display());
+                true
+            }
+            Err(err) => {
+                log::error!("Unable to export playlist: {}", err);
+                false
+            }
+        }
+    }
+
+    /// Imports M3U8, XSPF or JSON playlist and stores it under its name, numbered if the name is taken.
+    /// Tracks which can't be found are dropped.
+    /// `done` gets the name, JSON array of tracks with fresh stream URLs and number of missing tracks
+    fn playlist_import(&self, path: String, done: Value, failed: Value) {
+        let dir = self.playlists.dir().to_path_buf();
+        let agent = self.proxy.agent(Duration::from_secs(10));
+        std::thread::spawn(move || {
+            let path = std::path::PathBuf::from(path);
+            let import = || -> anyhow::Result<(Playlist, Vec<QueueTrack>, usize)> {
+                let format = PlaylistFormat::from_path(&path)
+                    .ok_or_else(|| anyhow::anyhow!("Unknown playlist format of `{}`", path.display()))?;
+                let name = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("Imported");
+                let playlist = Playlist::import(&std::fs::read_to_string(&path)?, format, name)?;
+
+                let (tracks, missing) = playlist::resolve(&agent, &playlist.tracks);
+                // existing playlists are kept, the import gets a free name
+                let store = PlaylistStore::new(dir);
+                let playlist = Playlist::from_queue(&store.free_name(&playlist.name), &tracks);
+                store.save(&playlist)?;
+                Ok((playlist, tracks, missing))
+            };
+
+            match import() {
+                Ok((playlist, tracks, missing)) => {
+                    log::info!(
+                        "Imported playlist `{}`: {} tracks, {} not found",
+                        playlist.name,
+                        tracks.len(),
+                        missing
+                    );
+                    let tracks = serde_json::to_string(&tracks).unwrap_or_default();
+                    done.call(None, &make_args!(playlist.name, tracks, missing as i32), None)
+                        .unwrap();
+                }
+                Err(err) => {
+                    log::error!("Unable to import playlist: {}", err);
+                    failed.call(None, &make_args!(err.to_string()), None).unwrap();
+                }
+            }
+        });
+    }
+
+    /// souvlaki has no shuffle and repeat state (MPRIS `Shuffle` and `LoopStatus`, SMTC `ShuffleEnabled`
+    /// and `AutoRepeatMode`), so the OS only sees the tracks the queue switches to
+    fn set_shuffle(&mut self, shuffle: bool) {
+        self.queue.set_shuffle(shuffle);
+        self.preload_next();
+    }
+
+    /// Not shown by the OS either, see `set_shuffle`
+    fn set_repeat(&mut self, mode: i32) {
+        match REPEAT_MODES.get(mode as usize) {
+            Some(&mode) => self.queue.set_repeat(mode),
+            None => log::warn!("Value out of range {} > {}", mode, REPEAT_MODES.len() - 1),
+        }
+        self.preload_next();
+    }
+
+    fn save_queue(&mut self) -> bool {
+        self.queue.play_position = self.player.get_time().unwrap_or_default().as_secs();
+        self.queue.save(QUEUE_FILE).map_err(|err| log::error!("Unable to save queue: {}", err)).is_ok()
+    }
+
+    /// Loads current track of the saved queue paused at saved position
+    fn restore_queue(&mut self) -> bool {
+        if self.queue.is_empty() || !self.play_current() {
+            return false;
+        }
+
+        if let Some(pending) = &mut self.pending {
+            pending.restore_position = Some(self.queue.play_position);
+            return true;
+        }
+        self.seek(self.queue.play_position as i32);
+        self.set_paused(true);
+        true
+    }
+
     pub fn update_metadata(
         &mut self,
         title: String,
@@ -79,6 +862,9 @@ impl Player {
         artist: String,
         cover_url: String,
     ) {
+        let duration = self.queue.current().map(|track| track.duration).unwrap_or_default();
+        self.scrobbler.track_started(&artist, &title, &album, duration);
+
         self.controls
             .as_mut()
             .unwrap()
@@ -86,36 +872,33 @@ impl Player {
                 title: Some(&title),
                 album: Some(&album),
                 artist: Some(&artist),
-                duration: self.player.get_time(),
+                duration: Duration::try_from_secs_f64(duration).ok(),
                 cover_url: Some(&cover_url),
             })
             .unwrap();
     }
 
+    /// Tells the OS whether the player plays, and where in the track it is
+    fn update_playback(&mut self) {
+        let Some(controls) = self.controls.as_mut() else {
+            return;
+        };
+        let playback = match self.player.get_time() {
+            None => MediaPlayback::Stopped,
+            Some(time) if self.player.is_paused() => MediaPlayback::Paused {
+                progress: Some(souvlaki::MediaPosition(time)),
+            },
+            Some(time) => MediaPlayback::Playing {
+                progress: Some(souvlaki::MediaPosition(time)),
+            },
+        };
+        controls.set_playback(playback).ok();
+    }
+
     fn set_paused(&mut self, state: bool) {
         self.player.set_paused(state);
-        if state {
-            self.controls
-                .as_mut()
-                .unwrap()
-                .set_playback(MediaPlayback::Paused {
-                    progress: Some(souvlaki::MediaPosition(
-                        self.player.get_time().unwrap_or_default(),
-                    )),
-                })
-                .ok();
-        } else {
-            self.controls
-                .as_mut()
-                .unwrap()
-                .set_playback(MediaPlayback::Playing {
-                    progress: Some(souvlaki::MediaPosition(
-                        self.player.get_time().unwrap_or_default(),
-                    )),
-                })
-                .ok();
-        }
-        self.event.call(None, &make_args!(""), None).unwrap();
+        self.update_playback();
+        self.force_update();
     }
 
     fn is_paused(&self) -> bool {
@@ -123,13 +906,17 @@ impl Player {
     }
 
     fn stop(&mut self) {
+        self.pending = None;
         self.player.stop();
-        self.event.call(None, &make_args!(""), None).unwrap();
+        self.scrobbler.track_ended();
+        self.history.track_finished();
+        self.update_playback();
+        self.force_update();
     }
 
     fn seek(&mut self, seconds: i32) {
         self.player.seek(Duration::from_secs(seconds as u64));
-        self.event.call(None, &make_args!(""), None).unwrap();
+        self.force_update();
     }
 
     fn get_time(&mut self) -> i32 {
@@ -146,18 +933,17 @@ impl Player {
     }
 
     fn force_update(&self) {
-        self.event.call(None, &make_args!(""), None).unwrap();
+        if let Some(event) = &self.event {
+            event.call(None, &make_args!(""), None).unwrap();
+        }
     }
 
     fn get_samples(&mut self) -> Value {
-        self.sample_values.clear();
+        let mut values = Value::new();
         for sample in self.player.get_samples() {
-            self.sample_values.push(*sample as f64);
+            values.push(*sample as f64);
         }
-
-        let new_value = std::mem::take(&mut self.sample_values);
-
-        new_value
+        values
     }
 }
 
@@ -177,6 +963,43 @@ impl sciter::EventHandler for Player {
         fn force_update();
         fn update_metadata(String, String, String, String);
         fn get_samples();
+        fn set_stream_proxy(String);
+        fn set_crossfade(String);
+        fn set_pause_fade(bool);
+        fn apply_equalizer(String);
+        fn set_tempo(String);
+        fn set_cache(String);
+        fn set_scrobbler(String);
+        fn scrobbler_login(String, String, Value, Value);
+        fn get_cache_info();
+        fn clear_cache();
+        fn cache_keep_offline(String, String);
+        fn cache_unpin_album(String);
+        fn set_normalization(String);
+        fn poll_track_event();
+        fn get_queue();
+        fn queue_add(String);
+        fn queue_insert_next(String);
+        fn queue_remove(i32);
+        fn queue_move(i32, i32);
+        fn queue_clear();
+        fn queue_play(i32);
+        fn queue_next();
+        fn queue_previous();
+        fn queue_refresh_stream_urls();
+        fn history_recent(i32);
+        fn history_most_played(i32);
+        fn history_enqueue(String, bool);
+        fn playlist_list();
+        fn playlist_save(String);
+        fn playlist_load(String, bool);
+        fn playlist_delete(String);
+        fn playlist_export(String, String);
+        fn playlist_import(String, Value, Value);
+        fn set_shuffle(bool);
+        fn set_repeat(i32);
+        fn save_queue();
+        fn restore_queue();
     }
 
     fn on_event(
@@ -198,6 +1021,12 @@ impl sciter::EventHandler for Player {
                 MediaControlEvent::Pause => self.set_paused(true),
                 MediaControlEvent::Stop => self.stop(),
                 MediaControlEvent::SeekBy(_, duration) => self.player.seek(duration),
+                MediaControlEvent::Next => {
+                    self.queue_next();
+                }
+                MediaControlEvent::Previous => {
+                    self.queue_previous();
+                }
                 _ => (),
             }
         }
@@ -216,6 +1045,10 @@ impl sciter::EventHandler for Player {
                 }
                 false
             }
+            BEHAVIOR_EVENTS::DOCUMENT_CLOSE_REQUEST => {
+                self.history.track_finished();
+                false
+            }
             _ => {
                 false
             },
@@ -244,6 +1077,11 @@ impl sciter::EventHandler for Player {
 
         log::debug!("Window handle: {:?}", hwnd);
 
+        if let Some(error) = &self.backend_error {
+            root.call_function("showErrorModal", &make_args!(error.as_str()))
+                .unwrap();
+        }
+
         let config = PlatformConfig {
             dbus_name: "bc_rs",
             display_name: "BandcampOnlinePlayer",
@@ -276,3 +1114,121 @@ impl sciter::EventHandler for Player {
         audio_device_dropdown.set_html(html.as_bytes(), Some(dom::SET_ELEMENT_HTML::SIH_REPLACE_CONTENT)).unwrap();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::players::decoder::tests::{serve, wav};
+
+    /// Queue tracks as JSON, with given durations in seconds, streamed from a local server.
+    /// Tracks with negative duration have no stream
+    fn tracks(durations: &[f64]) -> String {
+        let files = durations
+            .iter()
+            .enumerate()
+            .filter(|(_, &duration)| duration >= 0.0)
+            .map(|(index, &duration)| (format!("/{}", index + 1), wav(Duration::from_secs_f64(duration))))
+            .collect();
+        let base = serve(files);
+
+        let tracks: Vec<serde_json::Value> = durations
+            .iter()
+            .enumerate()
+            .map(|(index, &duration)| {
+                let track_id = index + 1;
+                let file = (duration >= 0.0)
+                    .then(|| serde_json::json!({ "mp3-128": format!("{}/{}", base, track_id) }));
+                serde_json::json!({
+                    "track_id": track_id,
+                    "title": format!("Track {}", track_id),
+                    "art_id": null,
+                    "duration": duration,
+                    "file": file,
+                })
+            })
+            .collect();
+        serde_json::Value::from(tracks).to_string()
+    }
+
+    fn null_player(history: &str) -> Player {
+        let cache = CacheConfig {
+            enabled: false,
+            ..Default::default()
+        };
+        let mut player = Player::new(
+            AudioSystem::Null,
+            0,
+            ProxyConfig::default(),
+            PlaybackOptions::default(),
+            cache,
+            ScrobblerConfig::default(),
+        );
+        // keep queue and history of the app untouched
+        player.queue = Queue::default();
+        let path = std::env::temp_dir().join(history);
+        let _ = std::fs::remove_file(&path);
+        player.history = History::load(path);
+        player
+    }
+
+    /// Polls the player like the UI does, until something happens
+    fn wait_event(player: &mut Player) -> String {
+        for _ in 0..200 {
+            let event = player.poll_track_event();
+            if !event.is_empty() {
+                return event;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        String::new()
+    }
+
+    #[test]
+    fn follows_queue_on_null_backend() {
+        let mut player = null_player("bc_rs_player_test_queue.jsonl");
+        assert_eq!(player.backend_error, None);
+
+        assert!(player.queue_add(tracks(&[0.05, 0.05, 0.05, -1.0])));
+        assert!(player.queue_play(1));
+        assert!(!player.is_paused());
+        assert_eq!(player.poll_track_event(), "");
+
+        // third track was preloaded and continues right away
+        assert_eq!(wait_event(&mut player), "advanced");
+        assert_eq!(player.queue.position(), 2);
+
+        // last track has no stream and no page to find one on, it waits for the page without blocking
+        assert_eq!(wait_event(&mut player), "advanced");
+        assert!(player.pending.is_some());
+        assert_eq!(wait_event(&mut player), "failed");
+        assert_eq!(player.queue.position(), 3);
+
+        assert!(player.queue_play(0));
+        player.stop();
+        assert!(player.is_paused());
+        assert_eq!(player.get_time(), 0);
+
+        let recent: Vec<u64> = player
+            .history
+            .recently_played(10)
+            .iter()
+            .map(|play| play.track.track_id)
+            .collect();
+        assert_eq!(recent, vec![1, 3, 2]);
+        std::fs::remove_file(player.history.path()).unwrap();
+    }
+
+    #[test]
+    fn ends_with_queue() {
+        let mut player = null_player("bc_rs_player_test_end.jsonl");
+        assert!(player.queue_add(tracks(&[0.05, 0.05])));
+        assert!(player.queue_play(0));
+
+        assert_eq!(wait_event(&mut player), "advanced");
+        assert_eq!(wait_event(&mut player), "ended");
+        assert_eq!(player.queue.position(), 1);
+        assert!(player.is_paused());
+        assert_eq!(player.poll_track_event(), "");
+        std::fs::remove_file(player.history.path()).unwrap();
+    }
+}
@@ -0,0 +1,326 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::{now, MAX_LISTEN_STEP},
+    queue::QueueTrack,
+};
+
+/// File where played tracks are appended, one JSON record per line
+pub const HISTORY_FILE: &str = "history.jsonl";
+/// Plays listened shorter than this, in seconds, are not counted as played, like skipped or restored tracks
+const MIN_COUNTED: f64 = 30.0;
+
+/// Line of the history file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+enum Record {
+    Start {
+        started: u64,
+        track: Box<QueueTrack>,
+    },
+    Finish {
+        track_id: u64,
+        started: u64,
+        finished: u64,
+        /// In seconds, pauses excluded
+        listened: f64,
+    },
+}
+
+/// Single play of a track
+#[derive(Debug, Clone, Serialize)]
+pub struct Play {
+    pub track: QueueTrack,
+    /// Unix time when the track started
+    pub started: u64,
+    /// `None` while it plays, or if the player was closed without finishing it
+    pub finished: Option<u64>,
+    /// In seconds
+    pub listened: f64,
+}
+
+/// Track with the number of its plays
+#[derive(Debug, Clone, Serialize)]
+pub struct PlayCount {
+    pub track: QueueTrack,
+    pub plays: u32,
+    /// Total listening time in seconds
+    pub listened: f64,
+    /// Unix time of the last play
+    pub last_played: u64,
+}
+
+/// Listening history, kept in memory and appended to the file as tracks start and finish
+pub struct History {
+    path: PathBuf,
+    plays: Vec<Play>,
+    /// Index of the playing track in `plays`
+    current: Option<usize>,
+    listened: Duration,
+    last_update: Instant,
+}
+
+impl History {
+    /// Reads the history file, lines which can't be parsed are skipped
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let mut plays: Vec<Play> = Vec::new();
+
+        let data = std::fs::read_to_string(&path).unwrap_or_default();
+        for line in data.lines().filter(|line| !line.trim().is_empty()) {
+            match serde_json::from_str::<Record>(line) {
+                Ok(Record::Start { started, track }) => plays.push(Play {
+                    track: *track,
+                    started,
+                    finished: None,
+                    listened: 0.0,
+                }),
+                Ok(Record::Finish {
+                    track_id,
+                    started,
+                    finished,
+                    listened,
+                }) => {
+                    if let Some(play) = plays
+                        .iter_mut()
+                        .rev()
+                        .find(|play| play.track.track_id == track_id && play.started == started)
+                    {
+                        play.finished = Some(finished);
+                        play.listened = listened;
+                    }
+                }
+                Err(err) => log::warn!("Skipping invalid history record: {}", err),
+            }
+        }
+
+        Self {
+            path,
+            plays,
+            current: None,
+            listened: Duration::ZERO,
+            last_update: Instant::now(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn append(&self, record: &Record) {
+        let result = serde_json::to_string(record)
+            .map_err(std::io::Error::from)
+            .and_then(|line| {
+                let mut file = File::options().create(true).append(true).open(&self.path)?;
+                writeln!(file, "{}", line)
+            });
+        if let Err(err) = result {
+            log::error!("Unable to write history: {}", err);
+        }
+    }
+
+    /// Records start of the track, finishing the previous one
+    pub fn track_started(&mut self, track: &QueueTrack) {
+        self.track_finished();
+
+        let started = now();
+        self.append(&Record::Start {
+            started,
+            track: Box::new(track.clone()),
+        });
+        self.plays.push(Play {
+            track: track.clone(),
+            started,
+            finished: None,
+            listened: 0.0,
+        });
+        self.current = Some(self.plays.len() - 1);
+        self.listened = Duration::ZERO;
+        self.last_update = Instant::now();
+    }
+
+    /// Records how long the playing track was listened to, if there is one
+    pub fn track_finished(&mut self) {
+        let Some(play) = self
+            .current
+            .take()
+            .and_then(|index| self.plays.get_mut(index))
+        else {
+            return;
+        };
+        let finished = now();
+        play.finished = Some(finished);
+        play.listened = self.listened.as_secs_f64();
+
+        let record = Record::Finish {
+            track_id: play.track.track_id,
+            started: play.started,
+            finished,
+            listened: play.listened,
+        };
+        self.append(&record);
+    }
+
+    /// Counts time since the last update as listened if `playing`
+    pub fn update(&mut self, playing: bool) {
+        let elapsed = self.last_update.elapsed();
+        self.last_update = Instant::now();
+        if playing && self.current.is_some() {
+            self.listened += elapsed.min(MAX_LISTEN_STEP);
+        }
+    }
+
+    /// Latest plays, newest first, each track once
+    pub fn recently_played(&self, limit: usize) -> Vec<&Play> {
+        let mut seen = HashSet::new();
+        self.plays
+            .iter()
+            .rev()
+            .filter(|play| seen.insert(play.track.track_id))
+            .take(limit)
+            .collect()
+    }
+
+    /// Tracks by number of plays, ties broken by the latest play
+    pub fn most_played(&self, limit: usize) -> Vec<PlayCount> {
+        let mut counts: HashMap<u64, PlayCount> = HashMap::new();
+        for play in self
+            .plays
+            .iter()
+            .filter(|play| play.listened >= MIN_COUNTED)
+        {
+            let count = counts
+                .entry(play.track.track_id)
+                .or_insert_with(|| PlayCount {
+                    track: play.track.clone(),
+                    plays: 0,
+                    listened: 0.0,
+                    last_played: 0,
+                });
+            count.plays += 1;
+            count.listened += play.listened;
+            if play.started >= count.last_played {
+                // latest metadata and stream URL of the track
+                count.track = play.track.clone();
+                count.last_played = play.started;
+            }
+        }
+
+        let mut counts: Vec<PlayCount> = counts.into_values().collect();
+        counts.sort_by(|a, b| {
+            b.plays
+                .cmp(&a.plays)
+                .then(b.last_played.cmp(&a.last_played))
+        });
+        counts.truncate(limit);
+        counts
+    }
+
+    /// Latest recorded version of the track, for adding it to the queue again
+    pub fn find(&self, track_id: u64) -> Option<&QueueTrack> {
+        self.plays
+            .iter()
+            .rev()
+            .map(|play| &play.track)
+            .find(|track| track.track_id == track_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(track_id: u64) -> QueueTrack {
+        serde_json::from_value(serde_json::json!({
+            "track_id": track_id,
+            "title": format!("Track {}", track_id),
+            "art_id": null,
+            "file": null,
+        }))
+        .unwrap()
+    }
+
+    fn temp_history(name: &str) -> History {
+        let path = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_file(&path);
+        History::load(path)
+    }
+
+    #[test]
+    fn records_starts_and_finishes() {
+        let mut history = temp_history("bc_rs_history_test_records.jsonl");
+        history.track_started(&track(1));
+        history.listened = Duration::from_secs(100);
+        history.track_started(&track(2));
+
+        let history = History::load(history.path().to_path_buf());
+        assert_eq!(history.plays.len(), 2);
+        assert_eq!(history.plays[0].listened, 100.0);
+        assert!(history.plays[0].finished.is_some());
+        // never finished
+        assert_eq!(history.plays[1].finished, None);
+
+        let recent: Vec<u64> = history
+            .recently_played(10)
+            .iter()
+            .map(|play| play.track.track_id)
+            .collect();
+        assert_eq!(recent, vec![2, 1]);
+        std::fs::remove_file(history.path()).unwrap();
+    }
+
+    #[test]
+    fn counts_listened_plays() {
+        let mut history = temp_history("bc_rs_history_test_counts.jsonl");
+        for (track_id, listened) in [(1, 60), (2, 60), (2, 90), (3, 5), (1, 10)] {
+            history.track_started(&track(track_id));
+            history.listened = Duration::from_secs(listened);
+        }
+        history.track_finished();
+
+        let most_played: Vec<(u64, u32)> = history
+            .most_played(10)
+            .iter()
+            .map(|count| (count.track.track_id, count.plays))
+            .collect();
+        // skipped plays are not counted
+        assert_eq!(most_played, vec![(2, 2), (1, 1)]);
+        assert_eq!(history.most_played(1).len(), 1);
+
+        let recent: Vec<u64> = history
+            .recently_played(10)
+            .iter()
+            .map(|play| play.track.track_id)
+            .collect();
+        assert_eq!(recent, vec![1, 3, 2]);
+        assert_eq!(
+            history.find(3).map(|track| track.title.as_str()),
+            Some("Track 3")
+        );
+        assert!(history.find(4).is_none());
+        std::fs::remove_file(history.path()).unwrap();
+    }
+
+    #[test]
+    fn skips_invalid_lines() {
+        let path = std::env::temp_dir().join("bc_rs_history_test_invalid.jsonl");
+        std::fs::write(
+            &path,
+            "{\"event\":\"start\",\"started\":5,\"track\":{\"track_id\":1,\"title\":\"a\",\"art_id\":null,\"file\":null}}\nnot json\n",
+        )
+        .unwrap();
+
+        let history = History::load(&path);
+        assert_eq!(history.plays.len(), 1);
+        assert_eq!(history.plays[0].started, 5);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,241 @@
+use std::{collections::HashMap, path::Path, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+/// File where measured track loudness is stored
+pub const LOUDNESS_FILE: &str = "loudness.json";
+/// Normalization never boosts tracks more than this, in dB, to avoid clipping
+pub const MAX_BOOST: f32 = 6.0;
+
+/// Gating block length and step of EBU R128 (400 ms blocks, overlapping by 75%)
+const BLOCK_STEPS: usize = 4;
+const STEP: Duration = Duration::from_millis(100);
+const ABSOLUTE_GATE: f64 = -70.0;
+const RELATIVE_GATE: f64 = -10.0;
+
+/// Second order IIR filter section
+#[derive(Debug, Clone, Copy, Default)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+/// K-weighting filter of ITU-R BS.1770 for the sample rate: high shelf followed by high pass
+fn k_weighting(rate: f64) -> [Biquad; 2] {
+    use std::f64::consts::PI;
+
+    let k = (PI * 1681.974450955533 / rate).tan();
+    let q = 0.7071752369554196;
+    let vh = 10f64.powf(3.999843853973347 / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b: [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        z: [0.0; 2],
+    };
+
+    let k = (PI * 38.13547087602444 / rate).tan();
+    let q = 0.5003270373238773;
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad {
+        b: [1.0, -2.0, 1.0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        z: [0.0; 2],
+    };
+
+    [shelf, high_pass]
+}
+
+fn to_lufs(power: f64) -> f64 {
+    -0.691 + 10.0 * power.log10()
+}
+
+/// EBU R128 integrated loudness meter
+pub struct LoudnessMeter {
+    channels: usize,
+    filters: Vec<[Biquad; 2]>,
+    step_frames: usize,
+    /// Frames and weighted power sum of the step being filled
+    frames: usize,
+    sum: f64,
+    /// Mean power of every finished 100 ms step
+    steps: Vec<f64>,
+    total_frames: u64,
+    rate: u32,
+}
+
+impl LoudnessMeter {
+    pub fn new(rate: u32, channels: usize) -> Self {
+        let channels = channels.max(1);
+        Self {
+            channels,
+            filters: vec![k_weighting(rate as f64); channels],
+            step_frames: (rate as usize * STEP.as_millis() as usize / 1000).max(1),
+            frames: 0,
+            sum: 0.0,
+            steps: Vec::new(),
+            total_frames: 0,
+            rate,
+        }
+    }
+
+    /// Adds interleaved samples
+    pub fn add(&mut self, samples: &[f32]) {
+        for frame in samples.chunks_exact(self.channels) {
+            // all channels of stereo and mono tracks have weight 1
+            for (sample, filter) in frame.iter().zip(self.filters.iter_mut()) {
+                let shelved = filter[0].process(*sample as f64);
+                let weighted = filter[1].process(shelved);
+                self.sum += weighted * weighted;
+            }
+
+            self.frames += 1;
+            if self.frames == self.step_frames {
+                self.steps.push(self.sum / self.frames as f64);
+                self.frames = 0;
+                self.sum = 0.0;
+            }
+        }
+        self.total_frames += (samples.len() / self.channels) as u64;
+    }
+
+    /// Length of the measured audio
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.total_frames as f64 / self.rate.max(1) as f64)
+    }
+
+    /// Gated integrated loudness in LUFS, `None` if there is no audio above the absolute gate
+    pub fn integrated(&self) -> Option<f64> {
+        let blocks: Vec<f64> = self
+            .steps
+            .windows(BLOCK_STEPS)
+            .map(|steps| steps.iter().sum::<f64>() / BLOCK_STEPS as f64)
+            .filter(|&power| power > 0.0 && to_lufs(power) > ABSOLUTE_GATE)
+            .collect();
+        if blocks.is_empty() {
+            return None;
+        }
+
+        let relative = to_lufs(blocks.iter().sum::<f64>() / blocks.len() as f64) + RELATIVE_GATE;
+        let gated: Vec<f64> = blocks
+            .into_iter()
+            .filter(|&power| to_lufs(power) > relative)
+            .collect();
+
+        Some(to_lufs(gated.iter().sum::<f64>() / gated.len() as f64))
+    }
+}
+
+/// Measured integrated loudness of tracks, by track id
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LoudnessDb {
+    tracks: HashMap<u64, f64>,
+}
+
+impl LoudnessDb {
+    /// Loads stored measurements, empty database if there is no valid file
+    pub fn load(path: impl AsRef<Path>) -> Self {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|data| {
+                serde_json::from_str(&data)
+                    .map_err(|err| log::error!("Unable to parse loudness database: {}", err))
+                    .ok()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        std::fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn insert(&mut self, track_id: u64, loudness: f64) {
+        self.tracks.insert(track_id, loudness);
+    }
+
+    pub fn loudness(&self, track_id: u64) -> Option<f64> {
+        self.tracks.get(&track_id).copied()
+    }
+
+    /// Gain in dB which brings the track to `target` LUFS, `None` if it was not measured yet
+    pub fn gain(&self, track_id: u64, target: f32) -> Option<f32> {
+        self.loudness(track_id)
+            .map(|loudness| (target - loudness as f32).min(MAX_BOOST))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 48000;
+
+    /// Stereo sine with peak level in dBFS
+    fn sine(level: f32, seconds: f32) -> Vec<f32> {
+        let amplitude = 10f32.powf(level / 20.0);
+        (0..(RATE as f32 * seconds) as usize)
+            .flat_map(|i| {
+                let sample = amplitude * (2.0 * std::f32::consts::PI * 1000.0 * i as f32 / RATE as f32).sin();
+                [sample, sample]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sine_reference_level() {
+        // EBU Tech 3341: 1 kHz stereo sine at -23 dBFS reads -23 LUFS
+        let mut meter = LoudnessMeter::new(RATE, 2);
+        for chunk in sine(-23.0, 5.0).chunks(4096) {
+            meter.add(chunk);
+        }
+
+        let loudness = meter.integrated().unwrap();
+        assert!((loudness + 23.0).abs() < 0.1, "{}", loudness);
+        assert_eq!(meter.duration(), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn gates_silence_and_quiet_parts() {
+        let mut meter = LoudnessMeter::new(RATE, 2);
+        meter.add(&vec![0.0; RATE as usize * 2]);
+        assert_eq!(meter.integrated(), None);
+
+        // part 30 dB quieter is below the relative gate and doesn't lower the result
+        meter.add(&sine(-20.0, 10.0));
+        meter.add(&sine(-50.0, 10.0));
+        let loudness = meter.integrated().unwrap();
+        assert!((loudness + 20.0).abs() < 0.2, "{}", loudness);
+    }
+
+    #[test]
+    fn gain_to_target() {
+        let path = std::env::temp_dir().join("bc_rs_loudness_test.json");
+        let mut db = LoudnessDb::default();
+        db.insert(1, -8.0);
+        db.insert(2, -30.0);
+        db.save(&path).unwrap();
+
+        let db = LoudnessDb::load(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(db.gain(1, -14.0), Some(-6.0));
+        assert_eq!(db.gain(2, -14.0), Some(MAX_BOOST));
+        assert_eq!(db.gain(3, -14.0), None);
+    }
+}
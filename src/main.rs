@@ -1,8 +1,18 @@
 use anyhow::anyhow;
 use sciter::Value;
 
+pub mod artwork;
+pub mod bandcamp;
 pub mod handlers;
 pub mod players;
+pub mod cache;
+pub mod clock;
+pub mod history;
+pub mod loudness;
+pub mod playlist;
+pub mod queue;
+pub mod resolver;
+pub mod scrobbler;
 
 #[cfg(target_os = "windows")]
 fn hide_console_window() {
@@ -57,6 +67,10 @@ fn main() -> anyhow::Result<()> {
     let config = handlers::config::Config::new();
     let audio_system = config.get_audio_system();
     let audio_index = config.get_audio_device_index();
+    let proxy = config.proxy.clone();
+    let playback_options = config.get_playback_options();
+    let cache = config.cache.clone();
+    let scrobbler = config.scrobbler.clone();
 
     let mut frame = sciter::WindowBuilder::main_window()
         .with_rect(config.window_geometry.into())
@@ -65,11 +79,18 @@ fn main() -> anyhow::Result<()> {
     frame
         .set_options(sciter::window::Options::DebugMode(true))
         .unwrap();
-    frame.event_handler(handlers::http_request::HttpRequest::new());
+    frame.event_handler(handlers::http_request::HttpRequest::new(proxy.clone(), &cache));
     frame.event_handler(handlers::log::Log);
     frame.event_handler(config);
     frame.event_handler(handlers::io::Io);
-    frame.event_handler(handlers::player::Player::new(audio_system, audio_index));
+    frame.event_handler(handlers::player::Player::new(
+        audio_system,
+        audio_index,
+        proxy,
+        playback_options,
+        cache,
+        scrobbler,
+    ));
 
     frame.set_variable("debugMode", Value::from(cfg!(debug_assertions)))?;
     frame.set_variable("bcRsVersion", Value::from(env!("CARGO_PKG_VERSION")))?;
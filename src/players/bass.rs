@@ -1,19 +1,538 @@
-use std::{env, time::Duration};
+use std::{
+    collections::HashMap,
+    env,
+    fs::File,
+    ops::Deref,
+    sync::{mpsc, Arc, Mutex, Weak},
+    time::{Duration, Instant},
+};
 
 use anyhow::bail;
 use bass_rs::{
-    prelude::{BassDevice, PlaybackState, StreamChannel},
+    prelude::{
+        BassDevice, BassResult, Channel, ChannelAttribute, Fx, ParamEqFx, PlaybackState, StreamChannel,
+        StreamFlags, SyncFlags, SyncType, VolumeFx,
+    },
     Bass,
 };
 
-use crate::players::Player;
+use crate::{
+    cache::{CachingReader, SharedCache},
+    handlers::config::{CrossfadeConfig, ProxyConfig, TempoConfig},
+    loudness::LoudnessMeter,
+    players::{
+        equalizer::{EqualizerConfig, EQ_BANDS, EQ_BANDWIDTH},
+        CrossfadeCurve, Player, PlayerEvent,
+    },
+};
+
+/// How often the crossfade thread updates volumes
+const FADE_STEP: Duration = Duration::from_millis(100);
+/// Length of volume fades on pause, resume and stop
+const PAUSE_FADE: Duration = Duration::from_millis(300);
+/// Volume bar changes are smoothed over this time
+const VOLUME_SLIDE: Duration = Duration::from_millis(80);
+/// Connect and read timeout of track downloads
+const STREAM_TIMEOUT: Duration = Duration::from_secs(15);
+/// Shortest playback which gives loudness of a stream with unknown length
+const MIN_MEASURED: Duration = Duration::from_secs(30);
+
+/// Played stream. Streams play through a tempo channel when built with `bass_fx` and the library is
+/// present, so speed and pitch can be changed
+#[derive(Clone)]
+enum Stream {
+    Plain(StreamChannel),
+    #[cfg(feature = "bass_fx")]
+    Tempo(bass_rs::prelude::TempoChannel),
+}
+
+impl Stream {
+    fn open(url: &str, source: &StreamSource) -> anyhow::Result<Self> {
+        #[cfg(feature = "bass_fx")]
+        if tempo_available() {
+            let stream = source.open(url, vec![StreamFlags::Decode])?;
+            let stream = bass_rs::prelude::TempoChannel::new(stream).map_err(|e| anyhow::anyhow!("{}", e))?;
+            return Ok(Self::Tempo(stream));
+        }
+
+        source.open(url, Vec::new()).map(Self::Plain)
+    }
+}
+
+impl Deref for Stream {
+    type Target = Channel;
+
+    fn deref(&self) -> &Channel {
+        match self {
+            Self::Plain(stream) => stream,
+            #[cfg(feature = "bass_fx")]
+            Self::Tempo(stream) => stream,
+        }
+    }
+}
+
+/// Speed and pitch can be changed only with `bass_fx` feature and library
+fn tempo_available() -> bool {
+    #[cfg(feature = "bass_fx")]
+    return bass_rs::bass_fx::is_bass_fx_available();
+    #[cfg(not(feature = "bass_fx"))]
+    false
+}
+
+/// State shared between player, stream syncs and the crossfade thread.
+/// The end sync locks it on the BASS mixing thread, so BASS is never called while the lock is held,
+/// and streams are never freed under it, because freeing a stream waits for its syncs.
+#[derive(Default)]
+struct Transition {
+    /// Track which should play after the current one
+    url: Option<String>,
+    /// Buffered stream for `url`, ready to start
+    stream: Option<Stream>,
+    /// Stream started by a sync, waiting to become current
+    started: Option<Stream>,
+    /// Current stream ended and there was nothing preloaded
+    ended: bool,
+    /// Handle of the playing stream, end syncs of other streams (ie fading out) are ignored
+    current: u32,
+    /// The playing stream, crossfade starts from it
+    playing: Option<Stream>,
+    /// Streams replaced by syncs, dropped by the player outside of the lock
+    released: Vec<Stream>,
+    crossfade: CrossfadeConfig,
+    /// Crossfade syncs set with older settings are ignored
+    crossfade_generation: u32,
+    /// Volume set by the user, crossfade slides to it
+    volume: f32,
+}
+
+/// Volume fades in progress. Fades call BASS while holding the lock, so it is never taken on
+/// the mixing thread, and never while `Transition` is locked
+#[derive(Default)]
+struct Fades {
+    /// Previous stream fading out during crossfade
+    crossfade: Option<Stream>,
+    /// What the slide sync does with the playing stream once it is silent
+    fade_out: Option<FadeOut>,
+}
+
+/// State the stream syncs and the crossfade thread work with
+#[derive(Clone)]
+struct Shared {
+    transition: Weak<Mutex<Transition>>,
+    fades: Weak<Mutex<Fades>>,
+}
+
+/// Equalizer effects set on one stream
+struct StreamEq {
+    preamp: Fx<VolumeFx>,
+    /// Effect of each band in `EQ_BANDS`, `None` if it couldn't be set
+    bands: Vec<Option<Fx<ParamEqFx>>>,
+    /// Band frequencies have to be below a third of the stream sample rate
+    max_center: f32,
+}
+
+impl StreamEq {
+    fn new(stream: &Stream, settings: &EqualizerConfig) -> BassResult<Self> {
+        // preamp goes first in the chain
+        let preamp = stream.set_fx::<VolumeFx>(1)?;
+        // other bands still work if one fails
+        let bands = EQ_BANDS
+            .iter()
+            .map(|center| {
+                stream
+                    .set_fx::<ParamEqFx>(0)
+                    .map_err(|err| log::error!("Unable to set {} Hz equalizer band: {}", center, err))
+                    .ok()
+            })
+            .collect();
+
+        let eq = Self {
+            preamp,
+            bands,
+            max_center: stream.default_frequency / 3.0 - 1.0,
+        };
+        eq.apply(settings)?;
+        Ok(eq)
+    }
+
+    /// Fails only if the preamp can't be set, ie when the stream is freed
+    fn apply(&self, settings: &EqualizerConfig) -> BassResult<()> {
+        self.preamp.set_parameters(&VolumeFx {
+            target: settings.preamp_volume(),
+            ..Default::default()
+        })?;
+
+        for ((fx, center), gain) in self.bands.iter().zip(EQ_BANDS).zip(settings.band_gains()) {
+            let Some(fx) = fx else {
+                continue;
+            };
+            // lowest bands are below the range of the effect, they boost or cut from its lowest frequency
+            let band = ParamEqFx {
+                center: center.max(ParamEqFx::MIN_CENTER).min(self.max_center),
+                bandwidth: EQ_BANDWIDTH,
+                gain,
+            };
+            if let Err(err) = fx.set_parameters(&band) {
+                log::error!("Unable to set {} Hz equalizer band: {}", center, err);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Equalizer settings, and effects of the streams they are applied to
+#[derive(Default)]
+struct Equalizer {
+    settings: EqualizerConfig,
+    streams: Vec<StreamEq>,
+}
+
+impl Equalizer {
+    /// Sets up equalizer on a newly opened stream
+    fn attach(&mut self, stream: &Stream) {
+        // effects of freed streams are not valid anymore
+        self.streams.retain(|eq| eq.preamp.get_parameters().is_ok());
+
+        match StreamEq::new(stream, &self.settings) {
+            Ok(eq) => self.streams.push(eq),
+            Err(err) => log::error!("Unable to set equalizer: {}", err),
+        }
+    }
+
+    fn update(&mut self, settings: &EqualizerConfig) {
+        self.settings = *settings;
+        self.streams.retain(|eq| eq.apply(settings).is_ok());
+    }
+}
+
+/// Measures loudness of everything the stream plays, and reports it when the stream is freed
+struct LoudnessProbe {
+    url: String,
+    meter: LoudnessMeter,
+    /// Partly played (ie skipped) tracks are not reported
+    length: Option<Duration>,
+    tx: mpsc::Sender<(String, f64)>,
+}
+
+impl Drop for LoudnessProbe {
+    fn drop(&mut self) {
+        let measured = self.meter.duration();
+        let complete = match self.length {
+            Some(length) => measured >= length.mul_f64(0.9),
+            None => measured >= MIN_MEASURED,
+        };
+
+        if let (true, Some(loudness)) = (complete, self.meter.integrated()) {
+            // player is gone if nobody receives
+            let _ = self.tx.send((std::mem::take(&mut self.url), loudness));
+        }
+    }
+}
+
+/// Track gains set before streams are opened, and sender for loudness measured by streams
+struct Normalization {
+    /// Gain in dB by stream URL, `None` for tracks which have to be measured
+    gains: HashMap<String, Option<f32>>,
+    tx: mpsc::Sender<(String, f64)>,
+}
+
+impl Normalization {
+    /// Applies gain of the track to a newly opened stream, or starts measuring it
+    fn attach(&mut self, url: &str, stream: &Stream) {
+        match self.gains.remove(url) {
+            Some(Some(gain)) => {
+                let volume = VolumeFx {
+                    target: 10f32.powf(gain / 20.0),
+                    ..Default::default()
+                };
+                // higher priority effects go first, so this is applied ahead of the equalizer
+                if let Err(err) = stream.set_fx_with(&volume, 2) {
+                    log::error!("Unable to set track gain: {}", err);
+                }
+            }
+            Some(None) => {
+                let mut probe = LoudnessProbe {
+                    url: url.to_string(),
+                    meter: LoudnessMeter::new(
+                        stream.default_frequency as u32,
+                        stream.get_channel_count().unwrap_or(2) as usize,
+                    ),
+                    length: stream.get_length_seconds().ok().map(Duration::from_secs_f64),
+                    tx: self.tx.clone(),
+                };
+
+                // measure the track before any effects
+                if let Err(err) = stream.set_dsp(3, move |samples| probe.meter.add(samples)) {
+                    log::error!("Unable to measure loudness: {}", err);
+                }
+            }
+            None => {}
+        }
+    }
+}
+
+/// What to do with the current stream once its volume slides to silence
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum FadeOut {
+    Pause,
+    Stop,
+}
 
 pub struct BassPlayer {
-    stream_channel: Option<StreamChannel>,
+    stream_channel: Option<Stream>,
+    transition: Arc<Mutex<Transition>>,
+    fades: Arc<Mutex<Fades>>,
     _bass: Vec<Bass>,
     sample_data: Vec<f32>,
     volume: f32,
     device: BassDevice,
+    pause_fade: bool,
+    equalizer: Arc<Mutex<Equalizer>>,
+    normalization: Arc<Mutex<Normalization>>,
+    loudness: mpsc::Receiver<(String, f64)>,
+    tempo: TempoConfig,
+    source: StreamSource,
+}
+
+/// Where streams are loaded from: the audio cache, a download which is stored in the cache as it plays,
+/// or BASS itself when the cache is disabled
+#[derive(Clone)]
+struct StreamSource {
+    agent: ureq::Agent,
+    cache: Option<SharedCache>,
+}
+
+impl StreamSource {
+    fn open(&self, url: &str, flags: Vec<StreamFlags>) -> anyhow::Result<StreamChannel> {
+        let cached = self.cache.as_ref().and_then(|cache| cache.lock().unwrap().get(url));
+        if let Some(path) = cached {
+            let file_flags = [flags.clone(), vec![StreamFlags::Prescan]].concat();
+            let stream = File::open(path)
+                .map_err(anyhow::Error::from)
+                .and_then(|file| {
+                    StreamChannel::from_reader(file, file_flags).map_err(|e| anyhow::anyhow!("{}", e))
+                });
+
+            match stream {
+                Ok(stream) => {
+                    log::info!("Playing track from audio cache");
+                    return Ok(stream);
+                }
+                Err(err) => log::warn!("Unable to play cached track: {}", err),
+            }
+        }
+
+        let Some(cache) = self.cache.clone() else {
+            let url_flags = [flags, vec![StreamFlags::Prescan]].concat();
+            return StreamChannel::load_from_url_with_flags(url.replace("https://", "http://"), 0, url_flags)
+                .map_err(|e| anyhow::anyhow!("{}", e));
+        };
+
+        // prescan would wait for the whole download, seeking works within the downloaded part
+        let response = self.agent.get(url).call()?;
+        let length = response
+            .header("Content-Length")
+            .and_then(|length| length.parse().ok());
+        let reader = CachingReader::new(response.into_reader(), url, length, cache);
+        StreamChannel::from_buffered_reader(reader, length, flags).map_err(|e| anyhow::anyhow!("{}", e))
+    }
+}
+
+/// Opens URL stream and sets up a sync which starts preloaded track at the exact end of it
+fn open_stream(url: &str, shared: Shared, tempo: &TempoConfig, source: &StreamSource) -> anyhow::Result<Stream> {
+    let stream = match Stream::open(url, source) {
+        Ok(stream) => stream,
+        Err(err) => bail!("Unable to load stream: {}", err),
+    };
+    setup_tempo(&stream, tempo);
+
+    let handle = *stream.handle;
+    let fade_shared = shared.clone();
+    stream
+        .set_sync(SyncType::Slide, move |_| finish_fade_out(&fade_shared, handle))
+        .map_err(|e| anyhow::anyhow!("Failed to set slide sync: {}", e))?;
+    stream
+        .set_sync_with_flags(SyncType::End, vec![SyncFlags::MixTime], move |_| {
+            let Some(transition) = shared.transition.upgrade() else {
+                return;
+            };
+
+            let mut state = transition.lock().unwrap();
+            if state.current != handle {
+                return;
+            }
+
+            let Some(next) = state.stream.take() else {
+                state.ended = true;
+                return;
+            };
+            set_current(&mut state, next.clone());
+            let (crossfade, generation) = (state.crossfade, state.crossfade_generation);
+            drop(state);
+
+            next.play(false).unwrap_or_else(|op| {
+                log::error!("Unable to start next track: {}", op);
+            });
+            set_crossfade_sync(&next, &crossfade, generation, &shared);
+        })
+        .map_err(|e| anyhow::anyhow!("Failed to set end sync: {}", e))?;
+
+    Ok(stream)
+}
+
+/// Pauses or stops the playing stream when its fade out is done, and restores its volume for resuming
+fn finish_fade_out(shared: &Shared, handle: u32) {
+    let (Some(fades), Some(transition)) = (shared.fades.upgrade(), shared.transition.upgrade()) else {
+        return;
+    };
+
+    let mut fades = fades.lock().unwrap();
+    let (current, stream, volume) = {
+        let transition = transition.lock().unwrap();
+        (transition.current, transition.playing.clone(), transition.volume)
+    };
+    let Some(action) = fades.fade_out.filter(|_| current == handle) else {
+        return;
+    };
+    let Some(stream) = stream else {
+        return;
+    };
+    // other slides end here too, ie volume changes
+    if stream.is_sliding(ChannelAttribute::Volume) {
+        return;
+    }
+
+    match action {
+        FadeOut::Pause => stream.pause(),
+        FadeOut::Stop => stream.stop(),
+    }
+    .and_then(|_| stream.set_volume(volume))
+    .unwrap_or_else(|op| {
+        log::error!("Unable to switch state due to error: {}", op);
+    });
+    fades.fade_out = None;
+}
+
+/// Makes the stream started by a sync current, the player picks it up on the next poll
+fn set_current(transition: &mut Transition, stream: Stream) {
+    transition.url = None;
+    transition.current = *stream.handle;
+
+    let replaced = [
+        transition.playing.replace(stream.clone()),
+        transition.started.replace(stream),
+    ];
+    transition.released.extend(replaced.into_iter().flatten());
+}
+
+/// Sets a sync which starts crossfade to the preloaded track when the stream is that far from its end
+fn set_crossfade_sync(stream: &Stream, crossfade: &CrossfadeConfig, generation: u32, shared: &Shared) {
+    let duration = crossfade.duration();
+    if duration.is_zero() {
+        return;
+    }
+
+    // length of streams without known size is not available, they end gapless
+    let Ok(length) = stream.get_length_seconds() else {
+        return;
+    };
+    let position = (length - duration.as_secs_f64()).max(0.0);
+
+    let handle = *stream.handle;
+    let shared = shared.clone();
+    let sync = stream.set_sync(SyncType::Position(position * 1000.0), move |_| {
+        start_crossfade(&shared, handle, generation);
+    });
+    if let Err(err) = sync {
+        log::error!("Unable to set crossfade sync: {}", err);
+    }
+}
+
+/// Starts the preloaded track silently and hands both streams to a thread which fades between them
+fn start_crossfade(shared: &Shared, handle: u32, generation: u32) {
+    let (Some(fades), Some(transition)) = (shared.fades.upgrade(), shared.transition.upgrade()) else {
+        return;
+    };
+
+    let mut fades = fades.lock().unwrap();
+    // tracks shorter than the crossfade end gapless
+    if fades.crossfade.is_some() || fades.fade_out.is_some() {
+        return;
+    }
+
+    let (outgoing, next, crossfade) = {
+        let mut transition = transition.lock().unwrap();
+        if transition.current != handle || transition.crossfade_generation != generation {
+            return;
+        }
+        let Some(outgoing) = transition.playing.clone() else {
+            return;
+        };
+        let Some(next) = transition.stream.take() else {
+            return;
+        };
+        set_current(&mut transition, next.clone());
+        (outgoing, next, transition.crossfade)
+    };
+
+    next.set_volume(0.0)
+        .and_then(|_| next.play(false))
+        .unwrap_or_else(|op| {
+            log::error!("Unable to start next track: {}", op);
+        });
+    fades.crossfade = Some(outgoing.clone());
+    drop(fades);
+
+    set_crossfade_sync(&next, &crossfade, generation, shared);
+    let shared = shared.clone();
+    std::thread::spawn(move || run_crossfade(shared, outgoing, next, crossfade.duration(), crossfade.curve));
+}
+
+/// Moves volumes of both tracks along the fade curve, then stops the outgoing one
+fn run_crossfade(shared: Shared, outgoing: Stream, incoming: Stream, duration: Duration, curve: CrossfadeCurve) {
+    let started = Instant::now();
+    loop {
+        let (Some(fades), Some(transition)) = (shared.fades.upgrade(), shared.transition.upgrade()) else {
+            return;
+        };
+
+        let mut fades = fades.lock().unwrap();
+        // the player finished the fade early, ie on seek or pause
+        if fades.crossfade.as_ref().map(|stream| *stream.handle) != Some(*outgoing.handle) {
+            return;
+        }
+        let volume = transition.lock().unwrap().volume;
+
+        let elapsed = started.elapsed();
+        if elapsed >= duration {
+            // last step is still sliding to silence
+            if !outgoing.is_sliding(ChannelAttribute::Volume) {
+                let finished = fades.crossfade.take();
+                drop(fades);
+
+                outgoing.stop().unwrap_or_else(|op| {
+                    log::error!("Unable to stop previous track: {}", op);
+                });
+                drop(finished);
+                return;
+            }
+        } else {
+            // slide to where the fade should be at the next step
+            let progress = (elapsed + FADE_STEP).as_secs_f32() / duration.as_secs_f32().max(f32::EPSILON);
+            let (outgoing_gain, incoming_gain) = curve.gains(progress);
+            let time = FADE_STEP.as_millis() as u32;
+
+            outgoing
+                .slide_attribute(ChannelAttribute::Volume, outgoing_gain * volume, time)
+                .and_then(|_| incoming.slide_attribute(ChannelAttribute::Volume, incoming_gain * volume, time))
+                .unwrap_or_else(|op| {
+                    log::error!("Unable to crossfade: {}", op);
+                });
+        }
+
+        drop(fades);
+        std::thread::sleep(FADE_STEP);
+    }
 }
 
 impl BassPlayer {
@@ -22,10 +541,18 @@ impl BassPlayer {
 
         exe.pop();
 
-        if !exe.join("bass.dll").exists() {
+        // bass.dll, libbass.so or libbass.dylib
+        let library = format!("{}bass{}", env::consts::DLL_PREFIX, env::consts::DLL_SUFFIX);
+
+        if !exe.join(library).exists() {
             bail!("Bass library not found!")
         }
 
+        #[cfg(feature = "bass_fx")]
+        if !tempo_available() {
+            log::warn!("BassFX library not found, speed and pitch can't be changed");
+        }
+
         let mut bases = Vec::new();
 
         let devices = BassDevice::get_all_devices().unwrap_or(Vec::new());
@@ -35,27 +562,141 @@ impl BassPlayer {
             bases.push(Bass::builder().device(dev).build().unwrap());
         }
 
+        let (tx, loudness) = mpsc::channel();
+
         Ok(Self {
             stream_channel: None,
+            transition: Arc::new(Mutex::new(Transition {
+                volume: 1.0,
+                ..Default::default()
+            })),
+            fades: Default::default(),
             sample_data: Vec::with_capacity(4096),
             _bass: bases,
             volume: 1.0,
             device: selected,
+            pause_fade: false,
+            equalizer: Default::default(),
+            normalization: Arc::new(Mutex::new(Normalization {
+                gains: HashMap::new(),
+                tx,
+            })),
+            loudness,
+            tempo: TempoConfig::default(),
+            source: StreamSource {
+                agent: ureq::AgentBuilder::new()
+                    .timeout_connect(STREAM_TIMEOUT)
+                    .timeout_read(STREAM_TIMEOUT)
+                    .build(),
+                cache: None,
+            },
         })
     }
 
+    fn shared(&self) -> Shared {
+        Shared {
+            transition: Arc::downgrade(&self.transition),
+            fades: Arc::downgrade(&self.fades),
+        }
+    }
+
     fn setup_stream_volume(&mut self) {
-        if let Some(stream) = &self.stream_channel {
-            stream.set_volume(self.volume).unwrap_or_else(|op| {
-                log::error!("Unable to change volume due to error: {}", op);
-            });
-            stream.set_device(self.device.clone()).map_err(|e| anyhow::anyhow!("Failed to set device: {}", e)).unwrap_or_else(|op| {
-                log::error!("Unable to switch device due to error: {}", op);
+        let preloaded = self.transition.lock().unwrap().stream.clone();
+        let crossfading = self.is_crossfading();
+        // volume of the fading in stream is set by the crossfade thread
+        let current = self.stream_channel.iter().filter(|_| !crossfading);
+
+        for stream in current.chain(preloaded.iter()) {
+            setup_stream(stream, self.volume, &self.device);
+        }
+    }
+
+    /// Slides current stream to silence, its slide sync does `action` once it is silent
+    fn start_fade_out(&mut self, action: FadeOut) {
+        let Some(stream) = &self.stream_channel else {
+            return;
+        };
+
+        let mut fades = self.fades.lock().unwrap();
+        fades.fade_out = Some(action);
+        stream
+            .slide_volume(0.0, PAUSE_FADE.as_millis() as u32)
+            .unwrap_or_else(|op| {
+                log::error!("Unable to fade out: {}", op);
             });
+    }
+
+    fn fade_out(&self) -> Option<FadeOut> {
+        self.fades.lock().unwrap().fade_out
+    }
+
+    /// Stops the track fading out and restores full volume of the current one
+    fn finish_fade(&mut self) {
+        let Some(outgoing) = self.fades.lock().unwrap().crossfade.take() else {
+            return;
+        };
+
+        outgoing.stop().unwrap_or_else(|op| {
+            log::error!("Unable to stop previous track: {}", op);
+        });
+        drop(outgoing);
+        self.setup_stream_volume();
+    }
+
+    fn is_crossfading(&self) -> bool {
+        self.fades.lock().unwrap().crossfade.is_some()
+    }
+
+    /// Applies speed and pitch to the playing and the preloaded stream
+    fn update_tempo(&mut self) {
+        if !tempo_available() && self.tempo != TempoConfig::default() {
+            log::warn!("Speed and pitch can't be changed without `bass_fx` feature and library");
         }
+
+        let preloaded = self.transition.lock().unwrap().stream.clone();
+        for stream in self.stream_channel.iter().chain(preloaded.iter()) {
+            setup_tempo(stream, &self.tempo);
+        }
+    }
+
+    /// Clears preloaded track and pending events, keeps the settings
+    fn reset_transition(&mut self) -> Transition {
+        let mut transition = self.transition.lock().unwrap();
+        let reset = Transition {
+            crossfade: transition.crossfade,
+            crossfade_generation: transition.crossfade_generation,
+            volume: transition.volume,
+            ..Default::default()
+        };
+        std::mem::replace(&mut *transition, reset)
     }
 }
 
+#[cfg(feature = "bass_fx")]
+fn setup_tempo(stream: &Stream, tempo: &TempoConfig) {
+    let Stream::Tempo(stream) = stream else {
+        return;
+    };
+    stream
+        .set_speed(tempo.speed)
+        .and_then(|_| stream.set_pitch(tempo.pitch))
+        .unwrap_or_else(|op| {
+            log::error!("Unable to change speed and pitch: {}", op);
+        });
+}
+
+#[cfg(not(feature = "bass_fx"))]
+fn setup_tempo(_stream: &Stream, _tempo: &TempoConfig) {}
+
+fn setup_stream(stream: &Stream, volume: f32, device: &BassDevice) {
+    stream.set_volume(volume).unwrap_or_else(|op| {
+        log::error!("Unable to change volume due to error: {}", op);
+    });
+    stream.set_device(device.clone()).map_err(|e| anyhow::anyhow!("Failed to set device: {}", e)).unwrap_or_else(|op| {
+        log::error!("Unable to switch device due to error: {}", op);
+    });
+}
+
 impl Player for BassPlayer {
     fn is_initialized(&self) -> bool {
         true
@@ -71,6 +712,10 @@ impl Player for BassPlayer {
     }
 
     fn is_playing(&self) -> bool {
+        if self.fade_out().is_some() {
+            return false;
+        }
+
         if let Some(stream) = &self.stream_channel {
             //log::info!("{:?}", stream.get_playback_state());
             return stream
@@ -82,6 +727,10 @@ impl Player for BassPlayer {
     }
 
     fn is_paused(&self) -> bool {
+        if self.fade_out() == Some(FadeOut::Pause) {
+            return true;
+        }
+
         if let Some(stream) = &self.stream_channel {
             return stream
                 .get_playback_state()
@@ -92,34 +741,94 @@ impl Player for BassPlayer {
     }
 
     fn set_paused(&mut self, paused: bool) {
+        if paused {
+            self.finish_fade();
+        }
+
         if let Some(stream) = &self.stream_channel {
-            log::info!("{:?}", stream.get_playback_state());
+            let state = stream.get_playback_state();
+            log::info!("{:?}", state);
             if paused {
+                if self.pause_fade && state.ok() == Some(PlaybackState::Playing) {
+                    self.start_fade_out(FadeOut::Pause);
+                    return;
+                }
+
                 stream.pause().unwrap_or_else(|op| {
                     log::error!("Unable to switch state due to error: {}", op);
                 });
             } else {
+                // resuming in the middle of fade out slides the volume back
+                let fading_out = self.fades.lock().unwrap().fade_out.take().is_some();
+                if self.pause_fade && !fading_out && state.ok() != Some(PlaybackState::Playing) {
+                    stream.set_volume(0.0).unwrap_or_else(|op| {
+                        log::error!("Unable to change volume due to error: {}", op);
+                    });
+                }
+
                 stream.play(false).unwrap_or_else(|op| {
                     log::error!("Unable to switch state due to error: {}", op);
                 });
+
+                if !self.is_crossfading() {
+                    stream
+                        .slide_volume(self.volume, PAUSE_FADE.as_millis() as u32)
+                        .unwrap_or_else(|op| {
+                            log::error!("Unable to fade in: {}", op);
+                        });
+                }
             }
         }
     }
 
     fn set_volume(&mut self, value: u16) {
         self.volume = value as f32 / 100.0;
-        self.setup_stream_volume();
+
+        let preloaded = {
+            let mut transition = self.transition.lock().unwrap();
+            transition.volume = self.volume;
+            transition.stream.clone()
+        };
+        if let Some(preloaded) = preloaded {
+            preloaded.set_volume(self.volume).unwrap_or_else(|op| {
+                log::error!("Unable to change volume due to error: {}", op);
+            });
+        }
+
+        let fading = {
+            let fades = self.fades.lock().unwrap();
+            fades.crossfade.is_some() || fades.fade_out.is_some()
+        };
+
+        // fades slide the volume by themselves
+        if fading {
+            return;
+        }
+        if let Some(stream) = &self.stream_channel {
+            stream
+                .slide_volume(self.volume, VOLUME_SLIDE.as_millis() as u32)
+                .unwrap_or_else(|op| {
+                    log::error!("Unable to change volume due to error: {}", op);
+                });
+        }
     }
 
     fn get_volume(&mut self) -> u16 {
-        if let Some(stream) = &self.stream_channel {
-            (stream.get_volume().unwrap_or_default() * 100.0) as u16
+        // the stream volume drops while fading
+        if self.stream_channel.is_some() {
+            (self.volume * 100.0).round() as u16
         } else {
             0
         }
     }
 
     fn stop(&mut self) {
+        self.finish_fade();
+        if self.pause_fade && self.is_playing() {
+            self.start_fade_out(FadeOut::Stop);
+            return;
+        }
+
         if let Some(stream) = &self.stream_channel {
             stream.stop().unwrap_or_else(|op| {
                 log::error!("I CANT STOP THAT: {}", op);
@@ -128,28 +837,126 @@ impl Player for BassPlayer {
     }
 
     fn switch_track(&mut self, url: String) -> anyhow::Result<()> {
+        self.finish_fade();
         if let Some(stream) = &self.stream_channel {
             stream
                 .stop()
                 .map_err(|e| anyhow::anyhow!("Failed to stop stream: {}", e))?;
-            drop(stream);
             self.stream_channel = None;
         }
-        let http = url.replace("https://", "http://");
-        match StreamChannel::load_from_url(http, 0) {
-            Ok(stream) => {
+
+        // use the preloaded stream if it is the requested track
+        self.fades.lock().unwrap().fade_out = None;
+        let transition = self.reset_transition();
+        let stream = match transition.stream {
+            Some(stream) if transition.url.as_deref() == Some(url.as_str()) => {
+                // gain was applied when the stream was preloaded
+                self.normalization.lock().unwrap().gains.remove(&url);
                 stream
-                    .play(true)
-                    .map_err(|e| anyhow::anyhow!("Failed to start stream: {}", e))?;
-                self.stream_channel = Some(stream);
-                self.setup_stream_volume();
             }
-            Err(err) => bail!("Unable to load stream: {}", err),
-        }
+            _ => {
+                let stream = open_stream(&url, self.shared(), &self.tempo, &self.source)?;
+                self.equalizer.lock().unwrap().attach(&stream);
+                self.normalization.lock().unwrap().attach(&url, &stream);
+                stream
+            }
+        };
+        let (crossfade, generation) = {
+            let mut transition = self.transition.lock().unwrap();
+            transition.current = *stream.handle;
+            transition.playing = Some(stream.clone());
+            (transition.crossfade, transition.crossfade_generation)
+        };
+        set_crossfade_sync(&stream, &crossfade, generation, &self.shared());
+
+        stream
+            .play(true)
+            .map_err(|e| anyhow::anyhow!("Failed to start stream: {}", e))?;
+        self.stream_channel = Some(stream);
+        self.setup_stream_volume();
         Ok(())
     }
 
+    fn set_next_track(&mut self, url: Option<String>) {
+        let (old, old_url) = {
+            let mut transition = self.transition.lock().unwrap();
+            if transition.url == url {
+                return;
+            }
+            let old_url = std::mem::replace(&mut transition.url, url.clone());
+            (transition.stream.take(), old_url)
+        };
+        drop(old);
+
+        // gain of the replaced track is not used if its stream wasn't opened yet
+        if let Some(old_url) = old_url {
+            self.normalization.lock().unwrap().gains.remove(&old_url);
+        }
+
+        let Some(url) = url else {
+            return;
+        };
+
+        let shared = self.shared();
+        let volume = self.volume;
+        let device = self.device.clone();
+        let equalizer = self.equalizer.clone();
+        let normalization = self.normalization.clone();
+        let tempo = self.tempo;
+        let source = self.source.clone();
+
+        std::thread::spawn(move || {
+            let stream = match open_stream(&url, shared.clone(), &tempo, &source) {
+                Ok(stream) => stream,
+                Err(err) => {
+                    log::warn!("Unable to preload next track: {}", err);
+                    return;
+                }
+            };
+            setup_stream(&stream, volume, &device);
+            equalizer.lock().unwrap().attach(&stream);
+            normalization.lock().unwrap().attach(&url, &stream);
+
+            let Some(transition) = shared.transition.upgrade() else {
+                return;
+            };
+
+            // drop the stream outside of the lock if it is not needed anymore
+            let _unused = {
+                let mut transition = transition.lock().unwrap();
+                if transition.url.as_deref() == Some(url.as_str()) {
+                    log::info!("Next track preloaded");
+                    transition.stream.replace(stream)
+                } else {
+                    Some(stream)
+                }
+            };
+        });
+    }
+
+    fn poll_event(&mut self) -> Option<PlayerEvent> {
+        let (started, ended, released) = {
+            let mut transition = self.transition.lock().unwrap();
+            (
+                transition.started.take(),
+                std::mem::take(&mut transition.ended),
+                std::mem::take(&mut transition.released),
+            )
+        };
+        drop(released);
+
+        if let Some(stream) = started {
+            self.stream_channel = Some(stream);
+            Some(PlayerEvent::Advanced)
+        } else if ended {
+            Some(PlayerEvent::Ended)
+        } else {
+            None
+        }
+    }
+
     fn seek(&mut self, time: std::time::Duration) {
+        self.finish_fade();
         if let Some(stream) = &self.stream_channel {
             stream
                 .set_position(time.as_millis() as f64)
@@ -162,9 +969,7 @@ impl Player for BassPlayer {
     fn get_samples(&mut self) -> &[f32] {
         if self.is_playing() {
             if let Some(stream) = &self.stream_channel {
-                match stream
-                    .channel
-                    .get_data(bass_rs::prelude::DataType::FFT4096, 4096)
+                match stream.get_data(bass_rs::prelude::DataType::FFT4096, 4096)
                 {
                     Ok(v) => {
                         self.sample_data = v;
@@ -197,8 +1002,67 @@ impl Player for BassPlayer {
 
         let device = devices[index].clone();
         self.device = device;
+        self.finish_fade();
 
         self.setup_stream_volume();
         Ok(())
     }
+
+    fn set_proxy(&mut self, proxy: &ProxyConfig) {
+        Bass::set_net_proxy(proxy.bass_proxy().as_deref()).unwrap_or_else(|op| {
+            log::error!("Unable to set stream proxy: {}", op);
+        });
+        self.source.agent = proxy
+            .agent_builder()
+            .timeout_connect(STREAM_TIMEOUT)
+            .timeout_read(STREAM_TIMEOUT)
+            .build();
+    }
+
+    fn set_crossfade(&mut self, crossfade: &CrossfadeConfig) {
+        let (playing, generation) = {
+            let mut transition = self.transition.lock().unwrap();
+            transition.crossfade = *crossfade;
+            // the playing stream gets a sync for the new settings, the old one is ignored
+            transition.crossfade_generation = transition.crossfade_generation.wrapping_add(1);
+            (transition.playing.clone(), transition.crossfade_generation)
+        };
+        if let Some(playing) = playing {
+            set_crossfade_sync(&playing, crossfade, generation, &self.shared());
+        }
+    }
+
+    fn set_pause_fade(&mut self, enabled: bool) {
+        self.pause_fade = enabled;
+    }
+
+    fn set_equalizer(&mut self, equalizer: &EqualizerConfig) {
+        self.equalizer.lock().unwrap().update(equalizer);
+    }
+
+    fn set_track_gain(&mut self, url: &str, gain: Option<f32>) {
+        self.normalization
+            .lock()
+            .unwrap()
+            .gains
+            .insert(url.to_string(), gain);
+    }
+
+    fn poll_loudness(&mut self) -> Option<(String, f64)> {
+        self.loudness.try_recv().ok()
+    }
+
+    fn set_speed(&mut self, speed: f32) {
+        self.tempo.speed = speed;
+        self.update_tempo();
+    }
+
+    fn set_pitch(&mut self, semitones: f32) {
+        self.tempo.pitch = semitones;
+        self.update_tempo();
+    }
+
+    fn set_audio_cache(&mut self, cache: Option<SharedCache>) {
+        self.source.cache = cache;
+    }
 }
\ No newline at end of file
@@ -0,0 +1,430 @@
+use std::{
+    f32::consts::PI,
+    io::{self, Read, Seek, SeekFrom},
+    sync::{Arc, Condvar, Mutex},
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use symphonia::core::{
+    audio::SampleBuffer,
+    codecs::{Decoder, DecoderOptions},
+    errors::Error as DecodeError,
+    formats::{FormatOptions, FormatReader, SeekMode, SeekTo},
+    io::{MediaSource, MediaSourceStream},
+    meta::MetadataOptions,
+    probe::Hint,
+};
+
+use crate::handlers::config::ProxyConfig;
+
+/// Samples used for the spectrum, same as BASS FFT4096
+pub const FFT_SIZE: usize = 4096;
+
+/// Agent for track downloads
+pub fn stream_agent(proxy: &ProxyConfig) -> ureq::Agent {
+    // no overall timeout, the track is downloaded while playing
+    proxy
+        .agent_builder()
+        .timeout_connect(Duration::from_secs(10))
+        .timeout_read(Duration::from_secs(30))
+        .build()
+}
+
+/// HTTP body downloaded in background, shared with the reader
+#[derive(Default)]
+struct Download {
+    data: Vec<u8>,
+    done: bool,
+}
+
+/// Seekable reader over a file which is still downloading, blocks until requested bytes arrive
+pub struct HttpSource {
+    download: Arc<(Mutex<Download>, Condvar)>,
+    position: usize,
+    length: Option<u64>,
+}
+
+impl HttpSource {
+    pub fn open(url: &str, agent: &ureq::Agent) -> anyhow::Result<(Self, Option<String>)> {
+        let response = agent.get(url).call()?;
+        let mime = Some(response.content_type().to_string());
+        let length = response
+            .header("Content-Length")
+            .and_then(|len| len.parse().ok());
+
+        let download: Arc<(Mutex<Download>, Condvar)> = Default::default();
+        let weak = Arc::downgrade(&download);
+
+        std::thread::spawn(move || {
+            let mut reader = response.into_reader();
+            let mut buffer = [0; 64 * 1024];
+
+            loop {
+                let read = reader.read(&mut buffer);
+                // stop downloading once the reader is gone
+                let Some(download) = weak.upgrade() else {
+                    return;
+                };
+                let (state, cond) = &*download;
+                let mut state = state.lock().unwrap();
+
+                match read {
+                    Ok(0) => state.done = true,
+                    Ok(len) => state.data.extend_from_slice(&buffer[..len]),
+                    Err(err) => {
+                        log::error!("Stream download failed: {}", err);
+                        state.done = true;
+                    }
+                }
+                cond.notify_all();
+
+                if state.done {
+                    return;
+                }
+            }
+        });
+
+        Ok((
+            Self {
+                download,
+                position: 0,
+                length,
+            },
+            mime,
+        ))
+    }
+}
+
+impl Read for HttpSource {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let (state, cond) = &*self.download;
+        let mut state = state.lock().unwrap();
+
+        while state.data.len() <= self.position && !state.done {
+            state = cond.wait(state).unwrap();
+        }
+
+        let available = &state.data[self.position.min(state.data.len())..];
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+impl Seek for HttpSource {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::Current(offset) => self.position as i64 + offset,
+            SeekFrom::End(offset) => match self.length {
+                Some(length) => length as i64 + offset,
+                None => return Err(io::Error::new(io::ErrorKind::Unsupported, "Unknown stream length")),
+            },
+        };
+
+        if position < 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Seek before start of stream"));
+        }
+        self.position = position as usize;
+        Ok(self.position as u64)
+    }
+}
+
+impl MediaSource for HttpSource {
+    fn is_seekable(&self) -> bool {
+        self.length.is_some()
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        self.length
+    }
+}
+
+/// Decoded audio ready for output
+pub struct Chunk {
+    /// Serial of the track the chunk belongs to
+    pub track: u64,
+    /// Position of the first frame in the track, in seconds
+    pub start: f64,
+    pub rate: u32,
+    pub channels: usize,
+    pub samples: Vec<f32>,
+}
+
+impl Chunk {
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.samples.len() as f64 / self.channels as f64 / self.rate as f64)
+    }
+}
+
+/// Opened track decoder
+pub struct Track {
+    pub url: String,
+    reader: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    id: u32,
+    /// Length of the track, if the container tells it
+    pub length: Option<Duration>,
+}
+
+impl Track {
+    pub fn open(url: &str, agent: &ureq::Agent) -> anyhow::Result<Self> {
+        let (source, mime) = HttpSource::open(url, agent)?;
+        let stream = MediaSourceStream::new(Box::new(source), Default::default());
+
+        let mut hint = Hint::new();
+        if let Some(mime) = &mime {
+            hint.mime_type(mime);
+        }
+
+        let probed = symphonia::default::get_probe().format(
+            &hint,
+            stream,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )?;
+
+        let track = probed
+            .format
+            .default_track()
+            .ok_or_else(|| anyhow!("Stream has no audio track"))?;
+        let decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions::default())?;
+        let id = track.id;
+        let length = match (track.codec_params.n_frames, track.codec_params.sample_rate) {
+            (Some(frames), Some(rate)) if rate > 0 => Some(Duration::from_secs_f64(frames as f64 / rate as f64)),
+            _ => None,
+        };
+
+        Ok(Self {
+            url: url.to_string(),
+            reader: probed.format,
+            decoder,
+            id,
+            length,
+        })
+    }
+
+    /// Decodes next packet, `None` at the end of the track
+    pub fn decode(&mut self, serial: u64) -> anyhow::Result<Option<Chunk>> {
+        loop {
+            let packet = match self.reader.next_packet() {
+                Ok(packet) => packet,
+                Err(DecodeError::IoError(err)) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                    return Ok(None)
+                }
+                Err(DecodeError::ResetRequired) => return Ok(None),
+                Err(err) => return Err(err.into()),
+            };
+
+            if packet.track_id() != self.id {
+                continue;
+            }
+
+            let decoded = match self.decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                // corrupted frames are skipped
+                Err(DecodeError::DecodeError(err)) => {
+                    log::warn!("Skipping broken packet: {}", err);
+                    continue;
+                }
+                Err(err) => return Err(err.into()),
+            };
+
+            let spec = *decoded.spec();
+            let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+            buffer.copy_interleaved_ref(decoded);
+
+            let start = self
+                .decoder
+                .codec_params()
+                .time_base
+                .map(|base| {
+                    let time = base.calc_time(packet.ts());
+                    time.seconds as f64 + time.frac
+                })
+                .unwrap_or_default();
+
+            return Ok(Some(Chunk {
+                track: serial,
+                start,
+                rate: spec.rate,
+                channels: spec.channels.count().max(1),
+                samples: buffer.samples().to_vec(),
+            }));
+        }
+    }
+
+    pub fn seek(&mut self, time: Duration) {
+        let seek = self.reader.seek(
+            SeekMode::Coarse,
+            SeekTo::Time {
+                time: time.as_secs_f64().into(),
+                track_id: Some(self.id),
+            },
+        );
+
+        match seek {
+            Ok(_) => self.decoder.reset(),
+            Err(err) => log::error!("Unable to seek: {}", err),
+        }
+    }
+}
+
+/// Magnitude spectrum of the samples, scaled like BASS FFT data
+pub fn spectrum(samples: &[f32]) -> Vec<f32> {
+    let size = samples.len().next_power_of_two();
+    let mut re: Vec<f32> = (0..size)
+        .map(|i| {
+            let window = 0.5 - 0.5 * (2.0 * PI * i as f32 / size as f32).cos();
+            samples.get(i).copied().unwrap_or_default() * window
+        })
+        .collect();
+    let mut im = vec![0.0; size];
+
+    // iterative radix-2 FFT
+    let bits = size.trailing_zeros();
+    for i in 0..size {
+        let j = i.reverse_bits() >> (usize::BITS - bits);
+        if i < j {
+            re.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= size {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..size).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len *= 2;
+    }
+
+    (0..size / 2)
+        .map(|i| (re[i] * re[i] + im[i] * im[i]).sqrt() * 2.0 / size as f32)
+        .collect()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::{
+        collections::HashMap,
+        io::Write,
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    /// Mono 16-bit WAV file with a 440 Hz tone
+    pub fn wav(length: Duration) -> Vec<u8> {
+        const RATE: u32 = 8000;
+        let frames = (length.as_secs_f64() * RATE as f64) as usize;
+        let data: Vec<u8> = (0..frames)
+            .map(|i| ((2.0 * PI * 440.0 * i as f32 / RATE as f32).sin() * 16000.0) as i16)
+            .flat_map(i16::to_le_bytes)
+            .collect();
+
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        // PCM, mono
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&RATE.to_le_bytes());
+        wav.extend_from_slice(&(RATE * 2).to_le_bytes());
+        wav.extend_from_slice(&2u16.to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        wav.extend_from_slice(&data);
+        wav
+    }
+
+    /// Serves `files` by path over HTTP on localhost in background, returns the base URL
+    pub fn serve(files: HashMap<String, Vec<u8>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let files = Arc::new(files);
+
+        std::thread::spawn(move || {
+            for mut connection in listener.incoming().flatten() {
+                let files = files.clone();
+                std::thread::spawn(move || {
+                    let mut request = Vec::new();
+                    let mut buffer = [0; 1024];
+                    while !request.ends_with(b"\r\n\r\n") {
+                        match connection.read(&mut buffer) {
+                            Ok(0) | Err(_) => return,
+                            Ok(read) => request.extend_from_slice(&buffer[..read]),
+                        }
+                    }
+
+                    let request = String::from_utf8_lossy(&request);
+                    let path = request.split(' ').nth(1).unwrap_or_default();
+                    let response = match files.get(path) {
+                        Some(body) => [
+                            format!(
+                                "HTTP/1.1 200 OK\r\nContent-Type: audio/wav\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                                body.len()
+                            )
+                            .into_bytes(),
+                            body.clone(),
+                        ]
+                        .concat(),
+                        None => b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_vec(),
+                    };
+                    let _ = connection.write_all(&response);
+                });
+            }
+        });
+        base
+    }
+
+    #[test]
+    fn decodes_served_track() {
+        let base = serve(HashMap::from([(String::from("/a.wav"), wav(Duration::from_secs(1)))]));
+        let agent = ureq::Agent::new();
+
+        let mut track = Track::open(&format!("{}/a.wav", base), &agent).unwrap();
+        assert_eq!(track.length, Some(Duration::from_secs(1)));
+
+        let mut decoded = Duration::ZERO;
+        while let Some(chunk) = track.decode(0).unwrap() {
+            decoded += chunk.duration();
+        }
+        assert_eq!(decoded, Duration::from_secs(1));
+        assert!(Track::open(&format!("{}/missing.wav", base), &agent).is_err());
+    }
+
+    #[test]
+    fn spectrum_peaks_at_sine_frequency() {
+        // 1 kHz sine at 44.1 kHz lands in bin 1000 / (44100 / 4096) ~ 93
+        let samples: Vec<f32> = (0..FFT_SIZE)
+            .map(|i| (2.0 * PI * 1000.0 * i as f32 / 44100.0).sin())
+            .collect();
+
+        let spectrum = spectrum(&samples);
+        let peak = spectrum
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(i, _)| i)
+            .unwrap();
+
+        assert_eq!(spectrum.len(), FFT_SIZE / 2);
+        assert!((92..=94).contains(&peak), "peak at {}", peak);
+    }
+}
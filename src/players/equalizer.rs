@@ -0,0 +1,142 @@
+use serde::{Deserialize, Serialize};
+
+/// Center frequencies of the equalizer bands, in Hz
+pub const EQ_BANDS: [f32; 10] = [
+    31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+];
+/// Band gain and preamp limit, in dB
+pub const EQ_MAX_GAIN: f32 = 12.0;
+/// Bandwidth of each band, one octave
+pub const EQ_BANDWIDTH: f32 = 12.0;
+
+/// Presets which are always available, user presets can't use these names
+pub const BUILTIN_PRESETS: [(&str, f32, [f32; 10]); 8] = [
+    ("Flat", 0.0, [0.0; 10]),
+    ("Bass boost", -4.0, [6.0, 5.0, 4.0, 2.0, 0.5, 0.0, 0.0, 0.0, 0.0, 0.0]),
+    ("Treble boost", -4.0, [0.0, 0.0, 0.0, 0.0, 0.0, 0.5, 2.0, 4.0, 5.0, 6.0]),
+    ("Vocal", -3.0, [-2.0, -2.0, -1.0, 1.0, 3.0, 3.5, 3.0, 1.5, 0.0, -1.0]),
+    ("Rock", -3.0, [4.0, 3.0, 1.5, -0.5, -1.5, -0.5, 1.5, 3.0, 3.5, 4.0]),
+    ("Electronic", -3.5, [5.0, 4.0, 1.0, 0.0, -2.0, 1.5, 0.5, 1.0, 4.0, 5.0]),
+    ("Acoustic", -2.0, [3.0, 3.0, 2.0, 0.5, 1.0, 1.0, 2.0, 2.5, 2.0, 1.0]),
+    ("Loudness", -4.0, [5.0, 3.5, 0.0, 0.0, -1.5, 0.0, -0.5, -3.0, 4.0, 1.0]),
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EqualizerPreset {
+    pub name: String,
+    pub preamp: f32,
+    pub bands: [f32; 10],
+}
+
+/// Equalizer state, applied to every stream the player opens
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EqualizerConfig {
+    /// `false` bypasses the equalizer, band settings are kept
+    pub enabled: bool,
+    /// Gain applied before the bands, in dB
+    pub preamp: f32,
+    /// Gain of each band in [`EQ_BANDS`], in dB
+    pub bands: [f32; 10],
+}
+
+impl Default for EqualizerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            preamp: 0.0,
+            bands: [0.0; 10],
+        }
+    }
+}
+
+impl EqualizerConfig {
+    /// Limits all gains to [`EQ_MAX_GAIN`]
+    pub fn clamped(mut self) -> Self {
+        self.preamp = self.preamp.clamp(-EQ_MAX_GAIN, EQ_MAX_GAIN);
+        for gain in self.bands.iter_mut() {
+            *gain = gain.clamp(-EQ_MAX_GAIN, EQ_MAX_GAIN);
+        }
+        self
+    }
+
+    /// Band gains to apply, all zero when bypassed
+    pub fn band_gains(&self) -> [f32; 10] {
+        if self.enabled {
+            self.bands
+        } else {
+            [0.0; 10]
+        }
+    }
+
+    /// Preamp as a volume multiplier, 1 when bypassed
+    pub fn preamp_volume(&self) -> f32 {
+        if self.enabled {
+            10f32.powf(self.preamp / 20.0)
+        } else {
+            1.0
+        }
+    }
+
+    /// Takes preamp and band gains from the preset
+    pub fn apply_preset(&mut self, preset: &EqualizerPreset) {
+        self.preamp = preset.preamp;
+        self.bands = preset.bands;
+    }
+}
+
+/// Built-in presets followed by user ones
+pub fn all_presets(user: &[EqualizerPreset]) -> Vec<EqualizerPreset> {
+    BUILTIN_PRESETS
+        .iter()
+        .map(|(name, preamp, bands)| EqualizerPreset {
+            name: name.to_string(),
+            preamp: *preamp,
+            bands: *bands,
+        })
+        .chain(user.iter().cloned())
+        .collect()
+}
+
+pub fn is_builtin_preset(name: &str) -> bool {
+    BUILTIN_PRESETS
+        .iter()
+        .any(|(builtin, _, _)| builtin.eq_ignore_ascii_case(name.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bypass_and_preamp() {
+        let mut eq = EqualizerConfig {
+            enabled: true,
+            preamp: -6.0,
+            bands: [20.0; 10],
+        }
+        .clamped();
+
+        assert_eq!(eq.band_gains(), [EQ_MAX_GAIN; 10]);
+        assert!((eq.preamp_volume() - 0.501).abs() < 0.001);
+
+        eq.enabled = false;
+        assert_eq!(eq.band_gains(), [0.0; 10]);
+        assert_eq!(eq.preamp_volume(), 1.0);
+    }
+
+    #[test]
+    fn user_presets_follow_builtin() {
+        let user = EqualizerPreset {
+            name: String::from("Mine"),
+            preamp: 1.0,
+            bands: [1.0; 10],
+        };
+        let presets = all_presets(std::slice::from_ref(&user));
+
+        assert_eq!(presets.len(), BUILTIN_PRESETS.len() + 1);
+        assert_eq!(presets[0].name, "Flat");
+        assert_eq!(presets.last(), Some(&user));
+        assert!(is_builtin_preset(" bass BOOST"));
+        assert!(!is_builtin_preset("Mine"));
+    }
+}
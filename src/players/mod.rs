@@ -1,12 +1,60 @@
 pub mod bass;
+pub mod decoder;
+pub mod equalizer;
+#[cfg(feature = "native-audio")]
+pub mod native;
+pub mod null;
+pub mod unavailable;
 
 use std::{time::Duration, fmt::{Display, self}};
 
 use serde::{Serialize, Deserialize};
 
+use crate::{
+    cache::SharedCache,
+    handlers::config::{CrossfadeConfig, NormalizationConfig, ProxyConfig, TempoConfig},
+};
+
+use self::equalizer::EqualizerConfig;
+
 #[derive(Debug, Clone, PartialEq, Eq, Copy, Serialize, Deserialize)]
 pub enum AudioSystem {
     Bass = 0,
+    /// symphonia + cpal, only available with `native-audio` feature
+    Native = 1,
+    /// No audio output, for headless testing
+    Null = 2,
+}
+
+/// How volumes of two tracks change during crossfade
+#[derive(Debug, Clone, PartialEq, Eq, Copy, Serialize, Deserialize)]
+pub enum CrossfadeCurve {
+    Linear = 0,
+    /// Keeps combined loudness constant, without the dip in the middle of linear fade
+    EqualPower = 1,
+}
+
+impl CrossfadeCurve {
+    /// Volume multipliers of outgoing and incoming track at `progress` of the fade, from 0 to 1
+    pub fn gains(&self, progress: f32) -> (f32, f32) {
+        let progress = progress.clamp(0.0, 1.0);
+        match self {
+            CrossfadeCurve::Linear => (1.0 - progress, progress),
+            CrossfadeCurve::EqualPower => {
+                let angle = progress * std::f32::consts::FRAC_PI_2;
+                (angle.cos(), angle.sin())
+            }
+        }
+    }
+}
+
+/// Playback events which happen without user interaction
+#[derive(Debug, Clone, PartialEq, Eq, Copy)]
+pub enum PlayerEvent {
+    /// Current track ended and playback continued with the track set by `set_next_track`
+    Advanced,
+    /// Current track ended and there was nothing to continue with
+    Ended,
 }
 
 pub trait Player {
@@ -21,8 +69,50 @@ pub trait Player {
     fn get_volume(&mut self) -> u16;
     fn stop(&mut self);
     fn switch_track(&mut self, url: String) -> anyhow::Result<()>;
+    /// Track to preload and start right after the current one ends, `None` to stop at the end
+    fn set_next_track(&mut self, url: Option<String>);
+    /// Returns the event happened since the last call, if any
+    fn poll_event(&mut self) -> Option<PlayerEvent>;
     fn seek(&mut self, time: Duration);
     fn get_samples(&mut self) -> &[f32];
+    fn set_proxy(&mut self, proxy: &ProxyConfig);
+    /// Overlap between consecutive tracks, backends which can't mix two tracks play them gapless
+    fn set_crossfade(&mut self, crossfade: &CrossfadeConfig);
+    /// Fade volume out on pause and stop, and in on resume
+    fn set_pause_fade(&mut self, enabled: bool);
+    fn set_equalizer(&mut self, equalizer: &EqualizerConfig);
+    /// Gain in dB applied to the track at `url` on top of the volume, when it is opened.
+    /// `None` if loudness of the track is unknown, backend measures it while the track plays
+    fn set_track_gain(&mut self, url: &str, gain: Option<f32>);
+    /// Integrated loudness in LUFS of a track which played through, with its URL
+    fn poll_loudness(&mut self) -> Option<(String, f64)>;
+    /// Playback speed without changing pitch, 1 is normal speed
+    fn set_speed(&mut self, speed: f32);
+    /// Pitch in semitones without changing speed, 0 is original pitch
+    fn set_pitch(&mut self, semitones: f32);
+    /// Cache streams are stored in and replayed from, `None` streams without caching
+    fn set_audio_cache(&mut self, cache: Option<SharedCache>);
+}
+
+/// Playback settings from `Config`, kept by the player handler and applied to every backend it creates
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PlaybackOptions {
+    pub crossfade: CrossfadeConfig,
+    pub pause_fade: bool,
+    pub equalizer: EqualizerConfig,
+    /// Used by the player handler, which knows the tracks
+    pub normalization: NormalizationConfig,
+    pub tempo: TempoConfig,
+}
+
+impl PlaybackOptions {
+    pub fn apply(&self, player: &mut dyn Player) {
+        player.set_crossfade(&self.crossfade);
+        player.set_pause_fade(self.pause_fade);
+        player.set_equalizer(&self.equalizer);
+        player.set_speed(self.tempo.speed);
+        player.set_pitch(self.tempo.pitch);
+    }
 }
 
 pub struct FormatTime(pub Duration);
@@ -34,4 +124,24 @@ impl Display for FormatTime {
         let secs = total_secs % 60;
         write!(f, "{:02}:{:02}", mins, secs)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crossfade_curves() {
+        assert_eq!(CrossfadeCurve::Linear.gains(0.0), (1.0, 0.0));
+        assert_eq!(CrossfadeCurve::Linear.gains(0.25), (0.75, 0.25));
+        assert_eq!(CrossfadeCurve::Linear.gains(2.0), (0.0, 1.0));
+
+        // power of both tracks adds up to full volume during the whole fade
+        for step in 0..=10 {
+            let (out, into) = CrossfadeCurve::EqualPower.gains(step as f32 / 10.0);
+            assert!((out * out + into * into - 1.0).abs() < 1e-6);
+        }
+        let (out, into) = CrossfadeCurve::EqualPower.gains(1.0);
+        assert!(out.abs() < 1e-6 && (into - 1.0).abs() < 1e-6);
+    }
+}
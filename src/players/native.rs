@@ -0,0 +1,520 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Condvar, Mutex, Weak},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail};
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    FromSample, SizedSample,
+};
+
+use crate::{
+    cache::SharedCache,
+    handlers::config::{CrossfadeConfig, ProxyConfig},
+    players::{
+        decoder::{spectrum, stream_agent, Chunk, Track, FFT_SIZE},
+        equalizer::EqualizerConfig,
+        Player, PlayerEvent,
+    },
+};
+
+/// Seconds of decoded audio kept ahead of the output
+const BUFFER_AHEAD: f64 = 1.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum State {
+    #[default]
+    Stopped,
+    Playing,
+    Paused,
+}
+
+/// State shared between player, decoder thread and output callback
+#[derive(Default)]
+struct Output {
+    /// Increased on every track switch, stops decoder threads of older tracks
+    generation: u64,
+    /// Last assigned track serial
+    serial: u64,
+    /// Serial of the track which is heard now
+    track: u64,
+    chunks: VecDeque<Chunk>,
+    /// Fractional frame position in the front chunk
+    cursor: f64,
+    /// Position of the heard track, in seconds
+    time: f64,
+    state: State,
+    volume: f32,
+    /// Pending seek for the decoder thread
+    seek: Option<Duration>,
+    /// Decoder reached the end and there is no next track
+    decoded: bool,
+    /// Track which should play after the current one
+    next_url: Option<String>,
+    next: Option<Track>,
+    event: Option<PlayerEvent>,
+    /// Recently played mono samples, for the spectrum
+    history: VecDeque<f32>,
+}
+
+impl Output {
+    fn buffered(&self) -> f64 {
+        self.chunks
+            .iter()
+            .map(|chunk| chunk.samples.len() as f64 / chunk.channels as f64 / chunk.rate as f64)
+            .sum::<f64>()
+            - self
+                .chunks
+                .front()
+                .map(|chunk| self.cursor / chunk.rate as f64)
+                .unwrap_or_default()
+    }
+
+    /// Next stereo frame resampled to `rate`, `None` if nothing is buffered
+    fn next_frame(&mut self, rate: u32) -> Option<(f32, f32)> {
+        loop {
+            let chunk = self.chunks.front()?;
+            let frames = chunk.samples.len() / chunk.channels;
+            let index = self.cursor as usize;
+
+            if index >= frames {
+                self.cursor -= frames as f64;
+                self.chunks.pop_front();
+                continue;
+            }
+
+            if chunk.track != self.track {
+                self.track = chunk.track;
+                self.event = Some(PlayerEvent::Advanced);
+            }
+
+            let frame = |index: usize| {
+                let index = index.min(frames - 1) * chunk.channels;
+                let left = chunk.samples[index];
+                let right = if chunk.channels > 1 { chunk.samples[index + 1] } else { left };
+                (left, right)
+            };
+
+            // linear interpolation between neighbouring frames
+            let fraction = (self.cursor - index as f64) as f32;
+            let (l1, r1) = frame(index);
+            let (l2, r2) = frame(index + 1);
+
+            self.time = chunk.start + self.cursor / chunk.rate as f64;
+            self.cursor += chunk.rate as f64 / rate as f64;
+
+            return Some((l1 + (l2 - l1) * fraction, r1 + (r2 - r1) * fraction));
+        }
+    }
+
+    /// Fills output buffer of the device
+    fn fill<T: SizedSample + FromSample<f32>>(&mut self, data: &mut [T], channels: usize, rate: u32) {
+        for frame in data.chunks_mut(channels) {
+            let (left, right) = if self.state == State::Playing {
+                self.next_frame(rate).unwrap_or_default()
+            } else {
+                (0.0, 0.0)
+            };
+
+            if self.state == State::Playing {
+                self.history.push_back((left + right) / 2.0);
+            }
+
+            for (i, sample) in frame.iter_mut().enumerate() {
+                let value = match (channels, i) {
+                    (1, _) => (left + right) / 2.0,
+                    (_, 0) => left,
+                    (_, 1) => right,
+                    _ => 0.0,
+                };
+                *sample = T::from_sample(value * self.volume);
+            }
+        }
+
+        let excess = self.history.len().saturating_sub(FFT_SIZE);
+        self.history.drain(..excess);
+
+        if self.state == State::Playing && self.chunks.is_empty() && self.decoded {
+            self.state = State::Stopped;
+            self.event = Some(PlayerEvent::Ended);
+        }
+    }
+}
+
+type Shared = Arc<(Mutex<Output>, Condvar)>;
+
+/// Decodes track into output buffer until it ends or another track is selected
+fn decode_loop(shared: Weak<(Mutex<Output>, Condvar)>, generation: u64, mut track: Track, mut serial: u64) {
+    let mut pending: Option<Chunk> = None;
+
+    loop {
+        let Some(shared) = shared.upgrade() else {
+            return;
+        };
+        let (output, cond) = &*shared;
+
+        let seek = {
+            let mut output = output.lock().unwrap();
+            loop {
+                if output.generation != generation {
+                    return;
+                }
+                if output.seek.is_some() {
+                    pending = None;
+                    break output.seek.take();
+                }
+                if let Some(chunk) = pending.take() {
+                    output.chunks.push_back(chunk);
+                }
+                if output.buffered() < BUFFER_AHEAD {
+                    break None;
+                }
+                output = cond.wait_timeout(output, Duration::from_millis(20)).unwrap().0;
+            }
+        };
+
+        if let Some(time) = seek {
+            track.seek(time);
+        }
+
+        match track.decode(serial) {
+            Ok(Some(chunk)) => pending = Some(chunk),
+            Ok(None) => {
+                let mut output = output.lock().unwrap();
+                if output.generation != generation {
+                    return;
+                }
+
+                // continue right away with the preloaded track
+                match output.next.take() {
+                    Some(next) => {
+                        output.next_url = None;
+                        output.serial += 1;
+                        serial = output.serial;
+                        drop(output);
+                        track = next;
+                    }
+                    None => {
+                        output.decoded = true;
+                        return;
+                    }
+                }
+            }
+            Err(err) => {
+                log::error!("Unable to decode stream: {}", err);
+                let mut output = output.lock().unwrap();
+                if output.generation == generation {
+                    output.decoded = true;
+                }
+                return;
+            }
+        }
+    }
+}
+
+fn output_stream(device: &cpal::Device, shared: &Shared) -> anyhow::Result<cpal::Stream> {
+    let config = device.default_output_config()?;
+
+    let stream = match config.sample_format() {
+        cpal::SampleFormat::F32 => build_stream::<f32>(device, &config.into(), shared),
+        cpal::SampleFormat::I16 => build_stream::<i16>(device, &config.into(), shared),
+        cpal::SampleFormat::U16 => build_stream::<u16>(device, &config.into(), shared),
+        format => bail!("Unsupported output format: {}", format),
+    }?;
+
+    stream.play()?;
+    Ok(stream)
+}
+
+fn build_stream<T: SizedSample + FromSample<f32>>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    shared: &Shared,
+) -> anyhow::Result<cpal::Stream> {
+    let shared = shared.clone();
+    let channels = config.channels as usize;
+    let rate = config.sample_rate.0;
+
+    Ok(device.build_output_stream(
+        config,
+        move |data: &mut [T], _| shared.0.lock().unwrap().fill(data, channels, rate),
+        |err| log::error!("Audio output error: {}", err),
+        None,
+    )?)
+}
+
+/// Player built on symphonia decoders and cpal output, doesn't need BASS library
+pub struct NativePlayer {
+    shared: Shared,
+    device: cpal::Device,
+    _stream: cpal::Stream,
+    agent: ureq::Agent,
+    sample_data: Vec<f32>,
+}
+
+fn output_devices() -> Vec<cpal::Device> {
+    cpal::default_host()
+        .output_devices()
+        .map(|devices| devices.collect())
+        .unwrap_or_default()
+}
+
+impl NativePlayer {
+    pub fn new(device_index: usize) -> anyhow::Result<Self> {
+        let device = match output_devices().into_iter().nth(device_index) {
+            Some(device) => device,
+            None => cpal::default_host()
+                .default_output_device()
+                .ok_or_else(|| anyhow!("No audio output device found"))?,
+        };
+
+        let shared: Shared = Arc::new((
+            Mutex::new(Output {
+                volume: 1.0,
+                ..Default::default()
+            }),
+            Condvar::new(),
+        ));
+
+        let stream = output_stream(&device, &shared)?;
+
+        Ok(Self {
+            shared,
+            device,
+            _stream: stream,
+            agent: stream_agent(&ProxyConfig::default()),
+            sample_data: Vec::with_capacity(FFT_SIZE / 2),
+        })
+    }
+
+    fn output(&self) -> std::sync::MutexGuard<'_, Output> {
+        self.shared.0.lock().unwrap()
+    }
+}
+
+impl Player for NativePlayer {
+    fn is_initialized(&self) -> bool {
+        true
+    }
+
+    fn get_time(&self) -> Option<Duration> {
+        let output = self.output();
+        if output.state == State::Stopped && output.chunks.is_empty() {
+            return None;
+        }
+        Some(Duration::from_secs_f64(output.time))
+    }
+
+    fn get_devices(&self) -> Vec<String> {
+        output_devices()
+            .iter()
+            .enumerate()
+            .map(|(i, device)| format!("{} ({})", device.name().unwrap_or_default(), i))
+            .collect()
+    }
+
+    fn switch_device(&mut self, index: usize) -> anyhow::Result<()> {
+        let Some(device) = output_devices().into_iter().nth(index) else {
+            bail!("Device selection out of range...")
+        };
+
+        self._stream = output_stream(&device, &self.shared)?;
+        self.device = device;
+        Ok(())
+    }
+
+    fn is_playing(&self) -> bool {
+        self.output().state == State::Playing
+    }
+
+    fn is_paused(&self) -> bool {
+        self.output().state != State::Playing
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        let mut output = self.output();
+        match (paused, output.state) {
+            (true, State::Playing) => output.state = State::Paused,
+            (false, State::Paused) => output.state = State::Playing,
+            _ => {}
+        }
+    }
+
+    fn set_volume(&mut self, value: u16) {
+        self.output().volume = value as f32 / 100.0;
+    }
+
+    fn get_volume(&mut self) -> u16 {
+        (self.output().volume * 100.0) as u16
+    }
+
+    fn stop(&mut self) {
+        let mut output = self.output();
+        output.generation += 1;
+        output.state = State::Stopped;
+        output.chunks.clear();
+        self.shared.1.notify_all();
+    }
+
+    fn switch_track(&mut self, url: String) -> anyhow::Result<()> {
+        // use the preloaded track if it is the requested one
+        let preloaded = {
+            let mut output = self.output();
+            let preloaded = output.next.take().filter(|next| next.url == url);
+            output.next_url = None;
+            output.generation += 1;
+            output.chunks.clear();
+            preloaded
+        };
+        self.shared.1.notify_all();
+
+        let track = match preloaded {
+            Some(track) => track,
+            None => Track::open(&url, &self.agent)?,
+        };
+
+        let (generation, serial) = {
+            let mut output = self.output();
+            output.generation += 1;
+            output.serial += 1;
+            output.track = output.serial;
+            output.chunks.clear();
+            output.cursor = 0.0;
+            output.time = 0.0;
+            output.seek = None;
+            output.decoded = false;
+            output.event = None;
+            output.state = State::Playing;
+            (output.generation, output.serial)
+        };
+
+        let shared = Arc::downgrade(&self.shared);
+        std::thread::spawn(move || decode_loop(shared, generation, track, serial));
+        Ok(())
+    }
+
+    fn set_next_track(&mut self, url: Option<String>) {
+        let old = {
+            let mut output = self.output();
+            if output.next_url == url {
+                return;
+            }
+            output.next_url = url.clone();
+            output.next.take()
+        };
+        drop(old);
+
+        let Some(url) = url else {
+            return;
+        };
+
+        let shared = Arc::downgrade(&self.shared);
+        let agent = self.agent.clone();
+
+        std::thread::spawn(move || {
+            let track = match Track::open(&url, &agent) {
+                Ok(track) => track,
+                Err(err) => {
+                    log::warn!("Unable to preload next track: {}", err);
+                    return;
+                }
+            };
+
+            let Some(shared) = shared.upgrade() else {
+                return;
+            };
+            let mut output = shared.0.lock().unwrap();
+            if output.next_url.as_deref() == Some(url.as_str()) {
+                log::info!("Next track preloaded");
+                output.next = Some(track);
+            }
+        });
+    }
+
+    fn poll_event(&mut self) -> Option<PlayerEvent> {
+        self.output().event.take()
+    }
+
+    fn seek(&mut self, time: Duration) {
+        let mut output = self.output();
+        output.seek = Some(time);
+        output.chunks.clear();
+        output.cursor = 0.0;
+        output.time = time.as_secs_f64();
+        self.shared.1.notify_all();
+    }
+
+    fn get_samples(&mut self) -> &[f32] {
+        let history: Vec<f32> = {
+            let output = self.output();
+            if output.state == State::Playing {
+                output.history.iter().copied().collect()
+            } else {
+                Vec::new()
+            }
+        };
+
+        if history.is_empty() {
+            self.sample_data.clear();
+        } else {
+            self.sample_data = spectrum(&history);
+        }
+
+        &self.sample_data
+    }
+
+    fn set_proxy(&mut self, proxy: &ProxyConfig) {
+        self.agent = stream_agent(proxy);
+    }
+
+    /// Decoder thread plays a single track at a time, tracks always change gapless
+    fn set_crossfade(&mut self, _crossfade: &CrossfadeConfig) {}
+
+    fn set_pause_fade(&mut self, _enabled: bool) {}
+
+    fn set_equalizer(&mut self, _equalizer: &EqualizerConfig) {}
+
+    fn set_track_gain(&mut self, _url: &str, _gain: Option<f32>) {}
+
+    fn poll_loudness(&mut self) -> Option<(String, f64)> {
+        None
+    }
+
+    fn set_speed(&mut self, _speed: f32) {}
+
+    fn set_pitch(&mut self, _semitones: f32) {}
+
+    fn set_audio_cache(&mut self, _cache: Option<SharedCache>) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_resamples_and_advances() {
+        let mut output = Output {
+            serial: 2,
+            track: 1,
+            state: State::Playing,
+            volume: 1.0,
+            decoded: true,
+            ..Default::default()
+        };
+        output.chunks.push_back(Chunk { track: 1, start: 0.0, rate: 2, channels: 1, samples: vec![0.0, 1.0] });
+        output.chunks.push_back(Chunk { track: 2, start: 0.0, rate: 2, channels: 1, samples: vec![1.0, 1.0] });
+
+        // output rate is twice the track rate, so every frame is interpolated once
+        let mut data = [0.0f32; 5];
+        output.fill(&mut data, 1, 4);
+        assert_eq!(data, [0.0, 0.5, 1.0, 1.0, 1.0]);
+        assert_eq!(output.event.take(), Some(PlayerEvent::Advanced));
+
+        let mut data = [0.0f32; 8];
+        output.fill(&mut data, 2, 4);
+        assert_eq!(output.event, Some(PlayerEvent::Ended));
+        assert_eq!(output.state, State::Stopped);
+    }
+}
@@ -0,0 +1,441 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
+};
+
+use anyhow::bail;
+
+use crate::{
+    cache::SharedCache,
+    handlers::config::{CrossfadeConfig, ProxyConfig},
+    players::{
+        decoder::{spectrum, stream_agent, Chunk, Track, FFT_SIZE},
+        equalizer::EqualizerConfig,
+        Player, PlayerEvent,
+    },
+};
+
+/// How often a paused decoder checks for resume
+const PAUSE_POLL: Duration = Duration::from_millis(5);
+
+/// Playback state shared with the decoder thread
+#[derive(Default)]
+struct Playback {
+    /// Incremented on every track switch and stop, decoder of an older track exits
+    generation: u64,
+    /// Position of the played audio, `None` when stopped
+    time: Option<Duration>,
+    length: Option<Duration>,
+    paused: bool,
+    seek: Option<Duration>,
+    next_url: Option<String>,
+    event: Option<PlayerEvent>,
+    /// Recently played mono samples, for the spectrum
+    history: VecDeque<f32>,
+}
+
+impl Playback {
+    fn play(&mut self, chunk: &Chunk) {
+        self.time = Some(Duration::from_secs_f64(chunk.start) + chunk.duration());
+        self.history.extend(
+            chunk
+                .samples
+                .chunks(chunk.channels)
+                .map(|frame| frame.iter().sum::<f32>() / chunk.channels as f32),
+        );
+        let excess = self.history.len().saturating_sub(FFT_SIZE);
+        self.history.drain(..excess);
+    }
+
+    fn start(&mut self, track: &Track) {
+        self.time = Some(Duration::ZERO);
+        self.length = track.length;
+        self.seek = None;
+        self.history.clear();
+    }
+
+    fn finish(&mut self) {
+        self.time = None;
+        self.length = None;
+        self.next_url = None;
+        self.history.clear();
+        self.event = Some(PlayerEvent::Ended);
+    }
+}
+
+/// Decodes the track without output, sleeping for the decoded duration divided by `speed`
+fn decode_loop(shared: Weak<Mutex<Playback>>, generation: u64, mut track: Track, agent: ureq::Agent, speed: f64) {
+    let mut pending: Option<Chunk> = None;
+
+    loop {
+        let Some(shared) = shared.upgrade() else {
+            return;
+        };
+
+        let wait = {
+            let mut playback = shared.lock().unwrap();
+            if playback.generation != generation {
+                return;
+            }
+            if playback.paused {
+                Some(PAUSE_POLL)
+            } else if let Some(time) = playback.seek.take() {
+                pending = None;
+                playback.history.clear();
+                track.seek(time);
+                None
+            } else {
+                pending.take().map(|chunk| {
+                    playback.play(&chunk);
+                    chunk.duration().div_f64(speed)
+                })
+            }
+        };
+
+        if let Some(wait) = wait {
+            drop(shared);
+            std::thread::sleep(wait);
+            continue;
+        }
+
+        match track.decode(0) {
+            Ok(Some(chunk)) => pending = Some(chunk),
+            Ok(None) => {
+                let next_url = {
+                    let mut playback = shared.lock().unwrap();
+                    if playback.generation != generation {
+                        return;
+                    }
+                    playback.next_url.take()
+                };
+
+                let next = next_url.map(|url| Track::open(&url, &agent));
+                let mut playback = shared.lock().unwrap();
+                if playback.generation != generation {
+                    return;
+                }
+
+                match next {
+                    Some(Ok(next)) => {
+                        playback.start(&next);
+                        playback.event = Some(PlayerEvent::Advanced);
+                        track = next;
+                    }
+                    Some(Err(err)) => {
+                        log::error!("Unable to load stream: {}", err);
+                        playback.finish();
+                        return;
+                    }
+                    None => {
+                        playback.finish();
+                        return;
+                    }
+                }
+            }
+            Err(err) => {
+                log::error!("Unable to decode stream: {}", err);
+                let mut playback = shared.lock().unwrap();
+                if playback.generation == generation {
+                    playback.finish();
+                }
+                return;
+            }
+        }
+    }
+}
+
+/// Player without audio output, for running on machines without sound card.
+/// Downloads and decodes tracks like a real backend, with playback running `speed` times faster than real time
+pub struct NullPlayer {
+    shared: Arc<Mutex<Playback>>,
+    speed: f64,
+    agent: ureq::Agent,
+    volume: u16,
+    sample_data: Vec<f32>,
+}
+
+impl NullPlayer {
+    pub fn new(speed: f64) -> Self {
+        Self {
+            shared: Arc::default(),
+            speed,
+            agent: stream_agent(&ProxyConfig::default()),
+            volume: 100,
+            sample_data: Vec::with_capacity(FFT_SIZE / 2),
+        }
+    }
+}
+
+impl Player for NullPlayer {
+    fn is_initialized(&self) -> bool {
+        true
+    }
+
+    fn get_time(&self) -> Option<Duration> {
+        self.shared.lock().unwrap().time
+    }
+
+    fn get_devices(&self) -> Vec<String> {
+        vec![String::from("No sound (0)")]
+    }
+
+    fn switch_device(&mut self, index: usize) -> anyhow::Result<()> {
+        if index > 0 {
+            bail!("Device selection out of range...")
+        }
+        Ok(())
+    }
+
+    fn is_playing(&self) -> bool {
+        let playback = self.shared.lock().unwrap();
+        playback.time.is_some() && !playback.paused
+    }
+
+    fn is_paused(&self) -> bool {
+        let playback = self.shared.lock().unwrap();
+        playback.time.is_none() || playback.paused
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        let mut playback = self.shared.lock().unwrap();
+        if playback.time.is_some() {
+            playback.paused = paused;
+        }
+    }
+
+    fn set_volume(&mut self, value: u16) {
+        self.volume = value;
+    }
+
+    fn get_volume(&mut self) -> u16 {
+        if self.shared.lock().unwrap().time.is_some() {
+            self.volume
+        } else {
+            0
+        }
+    }
+
+    fn stop(&mut self) {
+        let mut playback = self.shared.lock().unwrap();
+        playback.generation += 1;
+        playback.time = None;
+        playback.length = None;
+        playback.paused = false;
+        playback.history.clear();
+    }
+
+    fn switch_track(&mut self, url: String) -> anyhow::Result<()> {
+        if url.is_empty() {
+            bail!("Unable to load stream: empty URL")
+        }
+
+        let track = Track::open(&url, &self.agent)?;
+
+        let generation = {
+            let mut playback = self.shared.lock().unwrap();
+            if playback.next_url.as_deref() == Some(url.as_str()) {
+                playback.next_url = None;
+            }
+            playback.generation += 1;
+            playback.start(&track);
+            playback.paused = false;
+            playback.event = None;
+            playback.generation
+        };
+
+        let shared = Arc::downgrade(&self.shared);
+        let agent = self.agent.clone();
+        let speed = self.speed;
+        std::thread::spawn(move || decode_loop(shared, generation, track, agent, speed));
+        Ok(())
+    }
+
+    fn set_next_track(&mut self, url: Option<String>) {
+        self.shared.lock().unwrap().next_url = url;
+    }
+
+    fn poll_event(&mut self) -> Option<PlayerEvent> {
+        self.shared.lock().unwrap().event.take()
+    }
+
+    fn seek(&mut self, time: Duration) {
+        let mut playback = self.shared.lock().unwrap();
+        if playback.time.is_none() {
+            return;
+        }
+
+        let time = match playback.length {
+            Some(length) => time.min(length),
+            None => time,
+        };
+        playback.time = Some(time);
+        playback.seek = Some(time);
+    }
+
+    fn get_samples(&mut self) -> &[f32] {
+        let history: Vec<f32> = {
+            let playback = self.shared.lock().unwrap();
+            match playback.time.is_some() && !playback.paused {
+                true => playback.history.iter().copied().collect(),
+                false => Vec::new(),
+            }
+        };
+
+        self.sample_data.clear();
+        if !history.is_empty() {
+            let volume = self.volume as f32 / 100.0;
+            self.sample_data
+                .extend(spectrum(&history).into_iter().map(|value| value * volume));
+        }
+
+        &self.sample_data
+    }
+
+    fn set_proxy(&mut self, proxy: &ProxyConfig) {
+        self.agent = stream_agent(proxy);
+    }
+
+    fn set_crossfade(&mut self, _crossfade: &CrossfadeConfig) {}
+
+    fn set_pause_fade(&mut self, _enabled: bool) {}
+
+    fn set_equalizer(&mut self, _equalizer: &EqualizerConfig) {}
+
+    fn set_track_gain(&mut self, _url: &str, _gain: Option<f32>) {}
+
+    fn poll_loudness(&mut self) -> Option<(String, f64)> {
+        None
+    }
+
+    fn set_speed(&mut self, _speed: f32) {}
+
+    fn set_pitch(&mut self, _semitones: f32) {}
+
+    fn set_audio_cache(&mut self, _cache: Option<SharedCache>) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::players::decoder::tests::{serve, wav};
+
+    /// One second of track time in 10 ms
+    const SPEED: f64 = 100.0;
+
+    /// Player and base URL of a server with 10 s track `/a` and 5 s track `/b`
+    fn player() -> (NullPlayer, String) {
+        let base = serve(HashMap::from([
+            (String::from("/a"), wav(Duration::from_secs(10))),
+            (String::from("/b"), wav(Duration::from_secs(5))),
+        ]));
+        (NullPlayer::new(SPEED), base)
+    }
+
+    fn wait_until(mut condition: impl FnMut() -> bool) {
+        for _ in 0..1000 {
+            if condition() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        panic!("condition not reached in time");
+    }
+
+    fn wait_event(player: &mut NullPlayer) -> PlayerEvent {
+        let mut event = None;
+        wait_until(|| {
+            event = player.poll_event();
+            event.is_some()
+        });
+        event.unwrap()
+    }
+
+    #[test]
+    fn tracks_position_pause_and_seek() {
+        let (mut player, base) = player();
+        assert_eq!(player.get_time(), None);
+
+        player.switch_track(format!("{}/a", base)).unwrap();
+        wait_until(|| player.get_time().unwrap() >= Duration::from_secs(2));
+        player.set_paused(true);
+
+        let paused_at = player.get_time().unwrap();
+        assert!(paused_at < Duration::from_secs(10));
+        assert!(player.is_paused());
+
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(player.get_time(), Some(paused_at));
+        assert!(player.get_samples().is_empty());
+
+        player.seek(Duration::from_secs(5));
+        assert_eq!(player.get_time(), Some(Duration::from_secs(5)));
+        player.seek(Duration::from_secs(60));
+        assert_eq!(player.get_time(), Some(Duration::from_secs(10)));
+
+        player.seek(Duration::from_secs(5));
+        player.set_paused(false);
+        assert!(player.is_playing());
+        wait_until(|| player.get_time().unwrap() > Duration::from_secs(5));
+
+        player.stop();
+        assert_eq!(player.get_time(), None);
+        assert!(!player.is_playing());
+    }
+
+    #[test]
+    fn advances_to_next_track_and_ends() {
+        let (mut player, base) = player();
+        player.switch_track(format!("{}/a", base)).unwrap();
+        player.set_next_track(Some(format!("{}/b", base)));
+
+        player.seek(Duration::from_secs(9));
+        assert_eq!(wait_event(&mut player), PlayerEvent::Advanced);
+        assert_eq!(player.shared.lock().unwrap().length, Some(Duration::from_secs(5)));
+        assert!(player.get_time().unwrap() < Duration::from_secs(5));
+
+        player.seek(Duration::from_secs(4));
+        assert_eq!(wait_event(&mut player), PlayerEvent::Ended);
+        assert_eq!(player.poll_event(), None);
+        assert_eq!(player.get_time(), None);
+        assert!(!player.is_playing());
+    }
+
+    #[test]
+    fn broken_streams_fail() {
+        let (mut player, base) = player();
+        assert!(player.switch_track(String::new()).is_err());
+        assert!(player.switch_track(format!("{}/missing", base)).is_err());
+        assert_eq!(player.get_time(), None);
+
+        // next track which can't be loaded ends playback
+        player.switch_track(format!("{}/b", base)).unwrap();
+        player.set_next_track(Some(format!("{}/missing", base)));
+        player.seek(Duration::from_secs(4));
+        assert_eq!(wait_event(&mut player), PlayerEvent::Ended);
+    }
+
+    #[test]
+    fn spectrum_of_decoded_audio() {
+        let (mut player, base) = player();
+        player.switch_track(format!("{}/a", base)).unwrap();
+        wait_until(|| player.get_samples().len() == FFT_SIZE / 2);
+
+        let loud = player.get_samples().to_vec();
+        player.set_volume(25);
+        let quiet: f32 = player.get_samples().iter().sum();
+        assert_eq!(player.get_volume(), 25);
+        assert!(quiet < loud.iter().sum::<f32>() / 2.0);
+
+        // 440 Hz tone at 8 kHz lands in bin 440 / (8000 / 4096) ~ 225
+        let peak = loud
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(i, _)| i)
+            .unwrap();
+        assert!((224..=226).contains(&peak), "peak at {}", peak);
+    }
+}
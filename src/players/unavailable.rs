@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+use anyhow::bail;
+
+use crate::{
+    cache::SharedCache,
+    handlers::config::{CrossfadeConfig, ProxyConfig},
+    players::{equalizer::EqualizerConfig, Player, PlayerEvent},
+};
+
+/// Stands in for the audio backend when none could be started, every track fails to load with the startup error
+pub struct UnavailablePlayer {
+    error: String,
+}
+
+impl UnavailablePlayer {
+    pub fn new(error: String) -> Self {
+        Self { error }
+    }
+}
+
+impl Player for UnavailablePlayer {
+    fn is_initialized(&self) -> bool {
+        false
+    }
+
+    fn get_time(&self) -> Option<Duration> {
+        None
+    }
+
+    fn get_devices(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn switch_device(&mut self, _index: usize) -> anyhow::Result<()> {
+        bail!("{}", self.error)
+    }
+
+    fn is_playing(&self) -> bool {
+        false
+    }
+
+    fn is_paused(&self) -> bool {
+        true
+    }
+
+    fn set_paused(&mut self, _paused: bool) {}
+
+    fn set_volume(&mut self, _value: u16) {}
+
+    fn get_volume(&mut self) -> u16 {
+        0
+    }
+
+    fn stop(&mut self) {}
+
+    fn switch_track(&mut self, _url: String) -> anyhow::Result<()> {
+        bail!("{}", self.error)
+    }
+
+    fn set_next_track(&mut self, _url: Option<String>) {}
+
+    fn poll_event(&mut self) -> Option<PlayerEvent> {
+        None
+    }
+
+    fn seek(&mut self, _time: Duration) {}
+
+    fn get_samples(&mut self) -> &[f32] {
+        &[]
+    }
+
+    fn set_proxy(&mut self, _proxy: &ProxyConfig) {}
+
+    fn set_crossfade(&mut self, _crossfade: &CrossfadeConfig) {}
+
+    fn set_pause_fade(&mut self, _enabled: bool) {}
+
+    fn set_equalizer(&mut self, _equalizer: &EqualizerConfig) {}
+
+    fn set_track_gain(&mut self, _url: &str, _gain: Option<f32>) {}
+
+    fn poll_loudness(&mut self) -> Option<(String, f64)> {
+        None
+    }
+
+    fn set_speed(&mut self, _speed: f32) {}
+
+    fn set_pitch(&mut self, _semitones: f32) {}
+
+    fn set_audio_cache(&mut self, _cache: Option<SharedCache>) {}
+}
@@ -0,0 +1,725 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bandcamp::{
+        absolute_url, origin,
+        tralbum::{ItemType, TrackInfo, Tralbum},
+        unescape_html,
+    },
+    queue::QueueTrack,
+};
+
+/// Directory where named playlists are stored, one JSON file per playlist
+pub const PLAYLISTS_DIR: &str = "playlists";
+/// Version of the JSON playlist format
+pub const PLAYLIST_VERSION: u32 = 1;
+/// Timeout of track page requests when resolving imported tracks
+const RESOLVE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Track of a playlist. Stream URLs expire, so only the track page is stored and the stream is resolved from it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlaylistTrack {
+    /// Track page, or album page if the track isn't known
+    pub url: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub title: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub artist: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub album: String,
+    /// Bandcamp track id, 0 if unknown
+    #[serde(default)]
+    pub track_id: u64,
+    /// In seconds, 0 if unknown
+    #[serde(default)]
+    pub duration: f64,
+    #[serde(default)]
+    pub art_id: Option<u64>,
+}
+
+impl From<&QueueTrack> for PlaylistTrack {
+    fn from(track: &QueueTrack) -> Self {
+        Self {
+            url: track.title_link.clone(),
+            title: track.title.clone(),
+            artist: track.artist.clone(),
+            album: track.album.clone(),
+            track_id: track.track_id,
+            duration: track.duration,
+            art_id: track.art_id,
+        }
+    }
+}
+
+impl PlaylistTrack {
+    /// Queue track without stream URL, the player resolves it from the track page before playing
+    pub fn to_queue_track(&self) -> QueueTrack {
+        QueueTrack {
+            track_id: self.track_id,
+            title: self.title.clone(),
+            artist: self.artist.clone(),
+            album: self.album.clone(),
+            album_url: String::new(),
+            art_id: self.art_id,
+            title_link: self.url.clone(),
+            duration: self.duration,
+            file: None,
+        }
+    }
+}
+
+/// Named list of tracks. Stored and exported as JSON:
+///
+/// ```json
+/// {
+///   "version": 1,
+///   "name": "Evening",
+///   "tracks": [
+///     {
+///       "url": "https://thealgorithm.bandcamp.com/track/boot",
+///       "title": "Boot",
+///       "artist": "The Algorithm",
+///       "album": "Brute Force",
+///       "track_id": 1268475939,
+///       "duration": 236.571,
+///       "art_id": 2311186386
+///     }
+///   ]
+/// }
+/// ```
+///
+/// Only `url` of a track is required. Tracks without `track_id` are found on their page by title,
+/// an album page without title adds all tracks of the album
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Playlist {
+    #[serde(default = "default_version")]
+    pub version: u32,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub tracks: Vec<PlaylistTrack>,
+}
+
+fn default_version() -> u32 {
+    PLAYLIST_VERSION
+}
+
+/// File formats playlists are imported from and exported to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlaylistFormat {
+    M3u8,
+    Xspf,
+    Json,
+}
+
+impl PlaylistFormat {
+    /// Format by file extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "m3u8" | "m3u" => Some(PlaylistFormat::M3u8),
+            "xspf" => Some(PlaylistFormat::Xspf),
+            "json" => Some(PlaylistFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// First `<tag>` of an XML fragment, unescaped
+fn xml_element(xml: &str, tag: &str) -> Option<String> {
+    let element = Regex::new(&format!(r"(?s)<{0}>(.*?)</{0}>", tag)).unwrap();
+    element.captures(xml).map(|captures| {
+        unescape_html(&captures[1].replace("&apos;", "'"))
+            .trim()
+            .to_string()
+    })
+}
+
+/// `Artist - Title` of `#EXTINF`, title only if there's no separator
+fn split_extinf_title(title: &str) -> (String, String) {
+    match title.split_once(" - ") {
+        Some((artist, title)) => (artist.trim().to_string(), title.trim().to_string()),
+        None => (String::new(), title.trim().to_string()),
+    }
+}
+
+impl Playlist {
+    pub fn new(name: &str, tracks: Vec<PlaylistTrack>) -> Self {
+        Self {
+            version: PLAYLIST_VERSION,
+            name: name.to_string(),
+            tracks,
+        }
+    }
+
+    pub fn from_queue(name: &str, tracks: &[QueueTrack]) -> Self {
+        Self::new(name, tracks.iter().map(PlaylistTrack::from).collect())
+    }
+
+    pub fn export(&self, format: PlaylistFormat) -> anyhow::Result<String> {
+        Ok(match format {
+            PlaylistFormat::M3u8 => self.to_m3u8(),
+            PlaylistFormat::Xspf => self.to_xspf(),
+            PlaylistFormat::Json => serde_json::to_string_pretty(self)?,
+        })
+    }
+
+    /// Parses playlist file contents, `name` is used if the file doesn't have one
+    pub fn import(data: &str, format: PlaylistFormat, name: &str) -> anyhow::Result<Self> {
+        let mut playlist = match format {
+            PlaylistFormat::M3u8 => Self::from_m3u8(data),
+            PlaylistFormat::Xspf => Self::from_xspf(data),
+            PlaylistFormat::Json => serde_json::from_str(data)?,
+        };
+        if playlist.version > PLAYLIST_VERSION {
+            anyhow::bail!("Playlist version {} is not supported", playlist.version);
+        }
+        if playlist.name.trim().is_empty() {
+            playlist.name = name.to_string();
+        }
+        Ok(playlist)
+    }
+
+    fn to_m3u8(&self) -> String {
+        let mut m3u = format!("#EXTM3U\n#PLAYLIST:{}\n", self.name);
+        for track in &self.tracks {
+            let title = if track.artist.is_empty() {
+                track.title.clone()
+            } else {
+                format!("{} - {}", track.artist, track.title)
+            };
+            let duration = if track.duration > 0.0 {
+                track.duration.round() as i64
+            } else {
+                -1
+            };
+            m3u.push_str(&format!("#EXTINF:{},{}\n{}\n", duration, title, track.url));
+        }
+        m3u
+    }
+
+    fn from_m3u8(data: &str) -> Self {
+        let mut playlist = Self::new("", Vec::new());
+        let mut info: Option<(f64, String, String)> = None;
+
+        for line in data.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            if let Some(name) = line.strip_prefix("#PLAYLIST:") {
+                playlist.name = name.trim().to_string();
+            } else if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+                let (duration, title) = extinf.split_once(',').unwrap_or((extinf, ""));
+                // duration can be followed by attributes
+                let duration = duration
+                    .split_whitespace()
+                    .next()
+                    .and_then(|duration| duration.parse::<f64>().ok())
+                    .filter(|duration| *duration > 0.0)
+                    .unwrap_or_default();
+                let (artist, title) = split_extinf_title(title);
+                info = Some((duration, artist, title));
+            } else if !line.starts_with('#') {
+                let (duration, artist, title) = info.take().unwrap_or_default();
+                playlist.tracks.push(PlaylistTrack {
+                    url: line.to_string(),
+                    title,
+                    artist,
+                    album: String::new(),
+                    track_id: 0,
+                    duration,
+                    art_id: None,
+                });
+            }
+        }
+        playlist
+    }
+
+    fn to_xspf(&self) -> String {
+        let mut xspf = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n",
+        );
+        xspf.push_str(&format!(
+            "  <title>{}</title>\n  <trackList>\n",
+            escape_xml(&self.name)
+        ));
+        for track in &self.tracks {
+            xspf.push_str("    <track>\n");
+            xspf.push_str(&format!(
+                "      <location>{}</location>\n",
+                escape_xml(&track.url)
+            ));
+            for (tag, value) in [
+                ("title", &track.title),
+                ("creator", &track.artist),
+                ("album", &track.album),
+            ] {
+                if !value.is_empty() {
+                    xspf.push_str(&format!("      <{0}>{1}</{0}>\n", tag, escape_xml(value)));
+                }
+            }
+            if track.duration > 0.0 {
+                xspf.push_str(&format!(
+                    "      <duration>{}</duration>\n",
+                    (track.duration * 1000.0).round() as u64
+                ));
+            }
+            if let Some(art_id) = track.art_id {
+                xspf.push_str(&format!(
+                    "      <image>https://f4.bcbits.com/img/a{}_10.jpg</image>\n",
+                    art_id
+                ));
+            }
+            if track.track_id != 0 {
+                xspf.push_str(&format!(
+                    "      <identifier>bandcamp:track:{}</identifier>\n",
+                    track.track_id
+                ));
+            }
+            xspf.push_str("    </track>\n");
+        }
+        xspf.push_str("  </trackList>\n</playlist>\n");
+        xspf
+    }
+
+    fn from_xspf(data: &str) -> Self {
+        let track_list = data.find("<trackList>").unwrap_or(data.len());
+        let name = xml_element(&data[..track_list], "title").unwrap_or_default();
+
+        let track = Regex::new(r"(?s)<track>(.*?)</track>").unwrap();
+        let art = Regex::new(r"/img/a(\d+)_\d+\.jpg").unwrap();
+        let tracks = track
+            .captures_iter(data)
+            .filter_map(|captures| {
+                let track = &captures[1];
+                Some(PlaylistTrack {
+                    url: xml_element(track, "location")?,
+                    title: xml_element(track, "title").unwrap_or_default(),
+                    artist: xml_element(track, "creator").unwrap_or_default(),
+                    album: xml_element(track, "album").unwrap_or_default(),
+                    track_id: xml_element(track, "identifier")
+                        .and_then(|id| id.strip_prefix("bandcamp:track:")?.parse().ok())
+                        .unwrap_or_default(),
+                    duration: xml_element(track, "duration")
+                        .and_then(|duration| duration.parse::<f64>().ok())
+                        .map(|duration| duration / 1000.0)
+                        .unwrap_or_default(),
+                    art_id: xml_element(track, "image").and_then(|image| {
+                        art.captures(&image)
+                            .and_then(|captures| captures[1].parse().ok())
+                    }),
+                })
+            })
+            .collect();
+
+        Self::new(&name, tracks)
+    }
+}
+
+/// Queue track with fresh stream URL from a loaded release page
+fn queue_track(tralbum: &Tralbum, track: &TrackInfo, page_url: &str) -> QueueTrack {
+    QueueTrack {
+        track_id: track.track_id,
+        title: track.title.clone(),
+        artist: track
+            .artist
+            .clone()
+            .unwrap_or_else(|| tralbum.artist.clone()),
+        album: match tralbum.item_type {
+            ItemType::Album => tralbum.current.title.clone(),
+            ItemType::Track => String::new(),
+        },
+        album_url: tralbum.url.clone().unwrap_or_else(|| page_url.to_string()),
+        art_id: tralbum.art_id,
+        title_link: track
+            .title_link
+            .as_deref()
+            .map(|link| absolute_url(origin(page_url), link))
+            .unwrap_or_else(|| page_url.to_string()),
+        duration: track.duration,
+        file: track.file.clone(),
+    }
+}
+
+/// Finds playlist tracks on their loaded page, `None` for tracks which can't be streamed anymore.
+/// A track without id and title matches all tracks of the page
+pub fn match_tracks(
+    tralbum: &Tralbum,
+    page_url: &str,
+    tracks: &[&PlaylistTrack],
+) -> Vec<Option<Vec<QueueTrack>>> {
+    let streamable: Vec<&TrackInfo> = tralbum.streamable_tracks().collect();
+    tracks
+        .iter()
+        .map(|wanted| {
+            let found: Vec<&TrackInfo> = if wanted.track_id != 0 {
+                streamable
+                    .iter()
+                    .copied()
+                    .filter(|track| track.track_id == wanted.track_id)
+                    .collect()
+            } else if !wanted.title.is_empty() {
+                streamable
+                    .iter()
+                    .copied()
+                    .filter(|track| track.title.eq_ignore_ascii_case(&wanted.title))
+                    .take(1)
+                    .collect()
+            } else {
+                streamable.clone()
+            };
+
+            (!found.is_empty()).then(|| {
+                found
+                    .into_iter()
+                    .map(|track| queue_track(tralbum, track, page_url))
+                    .collect()
+            })
+        })
+        .collect()
+}
+
+/// Loads pages of playlist tracks and finds their fresh stream URLs, each page is loaded once.
+/// Returns tracks in playlist order and number of tracks which couldn't be found
+pub fn resolve(agent: &ureq::Agent, tracks: &[PlaylistTrack]) -> (Vec<QueueTrack>, usize) {
+    let mut pages: Vec<&str> = Vec::new();
+    let mut by_page: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (index, track) in tracks.iter().enumerate() {
+        let url = track.url.as_str();
+        if !by_page.contains_key(url) {
+            pages.push(url);
+        }
+        by_page.entry(url).or_default().push(index);
+    }
+
+    let mut resolved: Vec<Option<Vec<QueueTrack>>> = vec![None; tracks.len()];
+    for page_url in pages {
+        let indexes = &by_page[page_url];
+        let page = agent
+            .get(page_url)
+            .timeout(RESOLVE_TIMEOUT)
+            .call()
+            .map_err(anyhow::Error::from)
+            .and_then(|response| Ok(response.into_string()?))
+            .and_then(|html| Ok(Tralbum::from_html(&html)?));
+
+        match page {
+            Ok(tralbum) => {
+                let wanted: Vec<&PlaylistTrack> =
+                    indexes.iter().map(|&index| &tracks[index]).collect();
+                for (&index, found) in indexes
+                    .iter()
+                    .zip(match_tracks(&tralbum, page_url, &wanted))
+                {
+                    resolved[index] = found;
+                }
+            }
+            Err(err) => log::error!("Unable to load playlist track page `{}`: {}", page_url, err),
+        }
+    }
+
+    let missing = resolved.iter().filter(|found| found.is_none()).count();
+    (resolved.into_iter().flatten().flatten().collect(), missing)
+}
+
+/// Playlist name and track count, for listing playlists without their tracks
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlaylistInfo {
+    pub name: String,
+    pub tracks: usize,
+}
+
+/// Named playlists stored as JSON files in a directory
+pub struct PlaylistStore {
+    dir: PathBuf,
+}
+
+impl PlaylistStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// File of the playlist, with characters which can't be in file names replaced
+    fn path(&self, name: &str) -> PathBuf {
+        let file_name: String = name
+            .trim()
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || matches!(c, ' ' | '-' | '_' | '.') {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        self.dir
+            .join(format!("{}.json", file_name.trim_start_matches('.')))
+    }
+
+    /// Stored playlists sorted by name
+    pub fn list(&self) -> Vec<PlaylistInfo> {
+        let mut playlists: Vec<PlaylistInfo> = std::fs::read_dir(&self.dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|file| file.path().extension().is_some_and(|ext| ext == "json"))
+            .filter_map(|file| {
+                let data = std::fs::read_to_string(file.path()).ok()?;
+                let playlist: Playlist = serde_json::from_str(&data)
+                    .map_err(|err| {
+                        log::warn!(
+                            "Skipping invalid playlist `{}`: {}",
+                            file.path().display(),
+                            err
+                        )
+                    })
+                    .ok()?;
+                Some(PlaylistInfo {
+                    name: playlist.name,
+                    tracks: playlist.tracks.len(),
+                })
+            })
+            .collect();
+        playlists.sort_by_key(|playlist| playlist.name.to_lowercase());
+        playlists
+    }
+
+    /// Fails if there is no playlist of that name, even if another one is stored in its file
+    pub fn load(&self, name: &str) -> anyhow::Result<Playlist> {
+        let data = std::fs::read_to_string(self.path(name))?;
+        let playlist: Playlist = serde_json::from_str(&data)?;
+        if playlist.name.trim() != name.trim() {
+            anyhow::bail!("No playlist named `{}`", name);
+        }
+        Ok(playlist)
+    }
+
+    /// `name`, or `name (2)`, `name (3)`... if its file is taken by another playlist
+    pub fn free_name(&self, name: &str) -> String {
+        let name = name.trim();
+        (1..)
+            .map(|n| match n {
+                1 => name.to_string(),
+                n => format!("{} ({})", name, n),
+            })
+            .find(|candidate| !self.path(candidate).exists())
+            .unwrap_or_default()
+    }
+
+    /// Saves the playlist, replacing one with the same name. Names which differ only in characters
+    /// which can't be in file names share the file, so they are rejected
+    pub fn save(&self, playlist: &Playlist) -> anyhow::Result<()> {
+        if playlist.name.trim().is_empty() {
+            anyhow::bail!("Playlist has no name");
+        }
+
+        let stored = std::fs::read_to_string(self.path(&playlist.name))
+            .ok()
+            .and_then(|data| serde_json::from_str::<Playlist>(&data).ok());
+        if let Some(stored) = stored.filter(|stored| stored.name.trim() != playlist.name.trim()) {
+            anyhow::bail!("Name of playlist `{}` is too similar to `{}`", playlist.name, stored.name);
+        }
+
+        std::fs::create_dir_all(&self.dir)?;
+        std::fs::write(
+            self.path(&playlist.name),
+            playlist.export(PlaylistFormat::Json)?,
+        )?;
+        Ok(())
+    }
+
+    pub fn delete(&self, name: &str) -> anyhow::Result<()> {
+        self.load(name)?;
+        std::fs::remove_file(self.path(name))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALBUM_PAGE: &str = include_str!("../tests/fixtures/album.html");
+
+    fn playlist() -> Playlist {
+        Playlist::new(
+            "Evening & <night>",
+            vec![
+                PlaylistTrack {
+                    url: String::from("https://thealgorithm.bandcamp.com/track/boot"),
+                    title: String::from("Boot"),
+                    artist: String::from("The Algorithm"),
+                    album: String::from("Brute Force"),
+                    track_id: 1268475939,
+                    duration: 236.571,
+                    art_id: Some(2311186386),
+                },
+                PlaylistTrack {
+                    url: String::from("https://thealgorithm.bandcamp.com/album/brute-force"),
+                    title: String::new(),
+                    artist: String::new(),
+                    album: String::new(),
+                    track_id: 0,
+                    duration: 0.0,
+                    art_id: None,
+                },
+            ],
+        )
+    }
+
+    #[test]
+    fn m3u8_round_trip() {
+        let m3u = playlist().export(PlaylistFormat::M3u8).unwrap();
+        assert_eq!(
+            m3u,
+            "#EXTM3U\n#PLAYLIST:Evening & <night>\n#EXTINF:237,The Algorithm - Boot\nhttps://thealgorithm.bandcamp.com/track/boot\n#EXTINF:-1,\nhttps://thealgorithm.bandcamp.com/album/brute-force\n"
+        );
+
+        let imported = Playlist::import(&m3u, PlaylistFormat::M3u8, "file").unwrap();
+        assert_eq!(imported.name, "Evening & <night>");
+        assert_eq!(imported.tracks.len(), 2);
+        assert_eq!(imported.tracks[0].artist, "The Algorithm");
+        assert_eq!(imported.tracks[0].title, "Boot");
+        assert_eq!(imported.tracks[0].duration, 237.0);
+        assert_eq!(imported.tracks[1].title, "");
+
+        // plain list of URLs from other players
+        let plain = Playlist::import(
+            "https://thealgorithm.bandcamp.com/track/boot\r\n",
+            PlaylistFormat::M3u8,
+            "file",
+        )
+        .unwrap();
+        assert_eq!(plain.name, "file");
+        assert_eq!(
+            plain.tracks[0].url,
+            "https://thealgorithm.bandcamp.com/track/boot"
+        );
+    }
+
+    #[test]
+    fn xspf_round_trip() {
+        let xspf = playlist().export(PlaylistFormat::Xspf).unwrap();
+        assert!(xspf.contains("<title>Evening &amp; &lt;night&gt;</title>"));
+        assert!(xspf.contains("<duration>236571</duration>"));
+
+        let imported = Playlist::import(&xspf, PlaylistFormat::Xspf, "file").unwrap();
+        assert_eq!(imported, playlist());
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let json = playlist().export(PlaylistFormat::Json).unwrap();
+        assert_eq!(
+            Playlist::import(&json, PlaylistFormat::Json, "file").unwrap(),
+            playlist()
+        );
+
+        // missing or empty name and tracks are defaulted
+        let minimal = r#"{"tracks":[{"url":"https://thealgorithm.bandcamp.com/track/boot"}]}"#;
+        let imported = Playlist::import(minimal, PlaylistFormat::Json, "file").unwrap();
+        assert_eq!(imported.name, "file");
+        assert_eq!(imported.version, PLAYLIST_VERSION);
+        assert_eq!(imported.tracks.len(), 1);
+        assert_eq!(imported.tracks[0].url, "https://thealgorithm.bandcamp.com/track/boot");
+
+        let unnamed = Playlist::import(r#"{"name":"","tracks":[]}"#, PlaylistFormat::Json, "file").unwrap();
+        assert_eq!(unnamed.name, "file");
+        assert!(Playlist::import("{}", PlaylistFormat::Json, "file").unwrap().tracks.is_empty());
+
+        let newer = r#"{"version":2,"name":"a","tracks":[]}"#;
+        assert!(Playlist::import(newer, PlaylistFormat::Json, "file").is_err());
+    }
+
+    #[test]
+    fn matches_tracks_on_page() {
+        let tralbum = Tralbum::from_html(ALBUM_PAGE).unwrap();
+        let page_url = "https://thealgorithm.bandcamp.com/album/brute-force";
+        let by_id = PlaylistTrack {
+            track_id: 3904154364,
+            ..playlist().tracks[1].clone()
+        };
+        let by_title = PlaylistTrack {
+            title: String::from("boot"),
+            ..playlist().tracks[1].clone()
+        };
+        let not_streamable = PlaylistTrack {
+            track_id: 155062815,
+            ..playlist().tracks[1].clone()
+        };
+        let whole_album = playlist().tracks[1].clone();
+
+        let found = match_tracks(
+            &tralbum,
+            page_url,
+            &[&by_id, &by_title, &not_streamable, &whole_album],
+        );
+
+        let track = &found[0].as_ref().unwrap()[0];
+        assert_eq!(track.title, "Floating Point");
+        assert_eq!(track.album, "Brute Force");
+        assert_eq!(
+            track.title_link,
+            "https://thealgorithm.bandcamp.com/track/floating-point"
+        );
+        assert!(track.stream_url().is_some());
+
+        assert_eq!(found[1].as_ref().unwrap()[0].track_id, 1268475939);
+        assert!(found[2].is_none());
+        assert_eq!(found[3].as_ref().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn stores_named_playlists() {
+        let store = PlaylistStore::new(std::env::temp_dir().join("bc_rs_playlists_test"));
+        let _ = std::fs::remove_dir_all(store.dir());
+
+        store.save(&playlist()).unwrap();
+        store
+            .save(&Playlist::new("another/one", Vec::new()))
+            .unwrap();
+        assert!(store.save(&Playlist::new(" ", Vec::new())).is_err());
+        // same file as `another/one`
+        assert!(store.save(&Playlist::new("another_one", Vec::new())).is_err());
+        assert!(store.load("another_one").is_err());
+        assert!(store.delete("another_one").is_err());
+        assert_eq!(store.free_name("another_one"), "another_one (2)");
+        assert_eq!(store.free_name("New"), "New");
+
+        assert_eq!(
+            store.list(),
+            vec![
+                PlaylistInfo {
+                    name: String::from("another/one"),
+                    tracks: 0
+                },
+                PlaylistInfo {
+                    name: String::from("Evening & <night>"),
+                    tracks: 2
+                },
+            ]
+        );
+        assert_eq!(store.load("Evening & <night>").unwrap(), playlist());
+
+        store.delete("another/one").unwrap();
+        assert_eq!(store.list().len(), 1);
+        std::fs::remove_dir_all(store.dir()).unwrap();
+    }
+}
@@ -0,0 +1,574 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bandcamp::tralbum::TrackFile;
+
+/// File where queue is saved between sessions
+pub const QUEUE_FILE: &str = "queue.json";
+/// Maximum of remembered previously played positions
+const HISTORY_LIMIT: usize = 256;
+
+/// Track in the play queue: Bandcamp `trackinfo` entry with album data the frontend needs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueueTrack {
+    #[serde(default)]
+    pub track_id: u64,
+    pub title: String,
+    #[serde(default)]
+    pub artist: String,
+    /// Empty for single tracks
+    #[serde(default)]
+    pub album: String,
+    /// Page the track was added from, album page for album tracks
+    #[serde(default)]
+    pub album_url: String,
+    pub art_id: Option<u64>,
+    /// Absolute URL of the track page
+    #[serde(default)]
+    pub title_link: String,
+    #[serde(default)]
+    pub duration: f64,
+    pub file: Option<TrackFile>,
+}
+
+impl QueueTrack {
+    pub fn stream_url(&self) -> Option<&str> {
+        self.file.as_ref().and_then(|file| file.stream_url())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum RepeatMode {
+    #[default]
+    Off,
+    /// Play current track again when it ends
+    One,
+    /// Start from the beginning after the last track
+    All,
+}
+
+/// Play queue with current position, history and playback modes.
+/// Serialized format is compatible with `queue.json` previously written by the frontend.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Queue {
+    #[serde(rename = "queue")]
+    tracks: Vec<QueueTrack>,
+    position: usize,
+    /// Playback position of the current track in seconds, stored on save
+    #[serde(default)]
+    pub play_position: u64,
+    /// Previously played positions, most recent last
+    #[serde(default)]
+    history: Vec<usize>,
+    #[serde(default)]
+    shuffle: bool,
+    #[serde(default)]
+    repeat: RepeatMode,
+    /// Shuffled play order of track indices, `order[order_position]` is the current track
+    #[serde(default)]
+    order: Vec<usize>,
+    #[serde(default)]
+    order_position: usize,
+    /// Track which plays after the current one ends
+    #[serde(skip)]
+    next: Option<usize>,
+}
+
+impl Queue {
+    /// Loads saved queue, empty queue if there is no valid file
+    pub fn load(path: impl AsRef<Path>) -> Self {
+        let mut queue: Self = std::fs::read_to_string(path)
+            .ok()
+            .and_then(|data| {
+                serde_json::from_str(&data)
+                    .map_err(|err| log::error!("Unable to parse saved queue: {}", err))
+                    .ok()
+            })
+            .unwrap_or_default();
+
+        let len = queue.tracks.len();
+        queue.position = queue.position.min(len.saturating_sub(1));
+        queue.history.retain(|&index| index < len);
+
+        // saved order must be a permutation of all tracks, otherwise start a new one
+        let mut sorted = queue.order.clone();
+        sorted.sort_unstable();
+        if queue.shuffle && !sorted.into_iter().eq(0..len) {
+            queue.reshuffle(queue.position);
+        }
+        queue.sync_order_position();
+        queue.plan_next();
+        queue
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        std::fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn tracks(&self) -> &[QueueTrack] {
+        &self.tracks
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tracks.is_empty()
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn current(&self) -> Option<&QueueTrack> {
+        self.tracks.get(self.position)
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut QueueTrack> {
+        self.tracks.get_mut(index)
+    }
+
+    /// Replaces stream URL of every queued copy of the track, `false` if it's not queued
+    pub fn set_stream_url(&mut self, track_id: u64, url: &str) -> bool {
+        let mut found = false;
+        for track in self.tracks.iter_mut().filter(|track| track.track_id == track_id) {
+            // the old URL could be in another format, which takes precedence
+            track.file = Some(TrackFile {
+                mp3_128: Some(url.to_string()),
+                ..Default::default()
+            });
+            found = true;
+        }
+        found
+    }
+
+    /// Track which plays after the current one ends, if any
+    pub fn next_track(&self) -> Option<&QueueTrack> {
+        self.next.and_then(|index| self.tracks.get(index))
+    }
+
+    pub fn shuffle(&self) -> bool {
+        self.shuffle
+    }
+
+    /// Turning shuffle on starts a new play order from the current track
+    pub fn set_shuffle(&mut self, shuffle: bool) {
+        if shuffle && !self.shuffle {
+            self.reshuffle(self.position);
+        } else if !shuffle {
+            self.order.clear();
+            self.order_position = 0;
+        }
+        self.shuffle = shuffle;
+        self.plan_next();
+    }
+
+    pub fn repeat(&self) -> RepeatMode {
+        self.repeat
+    }
+
+    pub fn set_repeat(&mut self, repeat: RepeatMode) {
+        self.repeat = repeat;
+        self.plan_next();
+    }
+
+    /// Appends tracks to the end of the queue, in shuffle mode they are placed randomly among not yet played ones
+    pub fn add(&mut self, tracks: Vec<QueueTrack>) {
+        let start = self.tracks.len();
+        self.tracks.extend(tracks);
+
+        if self.shuffle {
+            for index in start..self.tracks.len() {
+                let after = (self.order_position + 1).min(self.order.len());
+                self.order.insert(fastrand::usize(after..=self.order.len()), index);
+            }
+            self.sync_order_position();
+        }
+        self.plan_next();
+    }
+
+    /// Inserts tracks right after the current one
+    pub fn insert_next(&mut self, tracks: Vec<QueueTrack>) {
+        if self.tracks.is_empty() {
+            return self.add(tracks);
+        }
+
+        let at = self.position + 1;
+        let count = tracks.len();
+        self.tracks.splice(at..at, tracks);
+        self.remap(|index| Some(if index >= at { index + count } else { index }));
+
+        // inserted tracks play next in shuffle mode too
+        if self.shuffle {
+            let next = self.order_position + 1;
+            self.order.splice(next..next, at..at + count);
+        }
+        self.plan_next();
+    }
+
+    /// Removes track, returns `false` if index is out of range
+    pub fn remove(&mut self, index: usize) -> bool {
+        if index >= self.tracks.len() {
+            return false;
+        }
+
+        // in shuffle mode the track following in play order becomes current
+        if self.shuffle && index == self.position {
+            if let Some(&next) = self.order.get(self.order_position + 1) {
+                self.position = next;
+            }
+        }
+
+        self.tracks.remove(index);
+        if index < self.position {
+            self.position -= 1;
+        }
+        self.position = self.position.min(self.tracks.len().saturating_sub(1));
+        self.remap(|i| match i {
+            i if i == index => None,
+            i if i > index => Some(i - 1),
+            i => Some(i),
+        });
+        true
+    }
+
+    /// Moves track to another place, returns `false` if any index is out of range
+    pub fn move_track(&mut self, from: usize, to: usize) -> bool {
+        if from >= self.tracks.len() || to >= self.tracks.len() {
+            return false;
+        }
+
+        let track = self.tracks.remove(from);
+        self.tracks.insert(to, track);
+
+        let moved = |i: usize| {
+            if i == from {
+                to
+            } else if from < to && i > from && i <= to {
+                i - 1
+            } else if to < from && i >= to && i < from {
+                i + 1
+            } else {
+                i
+            }
+        };
+        self.position = moved(self.position);
+        self.remap(|i| Some(moved(i)));
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.tracks.clear();
+        self.history.clear();
+        self.order.clear();
+        self.order_position = 0;
+        self.position = 0;
+        self.next = None;
+    }
+
+    /// Jumps to track chosen by user, returns `false` if index is out of range
+    pub fn set_position(&mut self, index: usize) -> bool {
+        if index >= self.tracks.len() {
+            return false;
+        }
+
+        if self.shuffle && index != self.position {
+            // play chosen track next in the order, so the rest of it stays the same
+            self.order.retain(|&i| i != index);
+            self.sync_order_position();
+            self.order.insert(self.order_position + 1, index);
+        }
+        self.go_to(index);
+        true
+    }
+
+    /// Moves to the planned next track after the current one ended
+    pub fn advance(&mut self) -> Option<&QueueTrack> {
+        let next = self.next?;
+        self.go_to(next);
+        self.current()
+    }
+
+    /// Moves to the next track by user request, unlike [`Queue::advance`] does not repeat the current track
+    pub fn skip_next(&mut self) -> Option<&QueueTrack> {
+        if self.repeat == RepeatMode::One {
+            let next = self.upcoming(RepeatMode::All)?;
+            self.go_to(next);
+            return self.current();
+        }
+        self.advance()
+    }
+
+    /// Moves to the previously played track
+    pub fn previous(&mut self) -> Option<&QueueTrack> {
+        let previous = match self.history.pop() {
+            Some(index) => index,
+            None if self.shuffle && self.order_position > 0 => self.order[self.order_position - 1],
+            None if !self.shuffle && self.position > 0 => self.position - 1,
+            None => return None,
+        };
+
+        self.position = previous;
+        self.sync_order_position();
+        self.plan_next();
+        self.current()
+    }
+
+    fn go_to(&mut self, index: usize) {
+        if index != self.position {
+            self.push_history();
+        }
+
+        if self.shuffle {
+            if self.order.get(self.order_position + 1) == Some(&index) {
+                self.order_position += 1;
+            } else if self.order.get(self.order_position) != Some(&index) {
+                // wrapped around with repeat all
+                self.reshuffle(index);
+            }
+        }
+
+        self.position = index;
+        self.plan_next();
+    }
+
+    fn push_history(&mut self) {
+        if !self.tracks.is_empty() {
+            self.history.push(self.position);
+        }
+        let excess = self.history.len().saturating_sub(HISTORY_LIMIT);
+        self.history.drain(..excess);
+    }
+
+    /// Updates stored indices after tracks changed, `None` drops the index
+    fn remap(&mut self, map: impl Fn(usize) -> Option<usize>) {
+        self.history = self.history.iter().filter_map(|&i| map(i)).collect();
+        self.history.dedup();
+        self.order = self.order.iter().filter_map(|&i| map(i)).collect();
+        self.sync_order_position();
+        self.plan_next();
+    }
+
+    /// New random play order of all tracks (Fisher-Yates), starting with `first`
+    fn reshuffle(&mut self, first: usize) {
+        let mut order: Vec<usize> = (0..self.tracks.len()).collect();
+        for i in (1..order.len()).rev() {
+            order.swap(i, fastrand::usize(..=i));
+        }
+
+        if let Some(at) = order.iter().position(|&i| i == first) {
+            order.swap(0, at);
+        }
+        self.order = order;
+        self.order_position = 0;
+    }
+
+    fn sync_order_position(&mut self) {
+        if let Some(at) = self.order.iter().position(|&i| i == self.position) {
+            self.order_position = at;
+        }
+    }
+
+    /// Track after the current one with given repeat mode
+    fn upcoming(&self, repeat: RepeatMode) -> Option<usize> {
+        if self.tracks.is_empty() {
+            return None;
+        }
+
+        if self.shuffle && repeat != RepeatMode::One {
+            return match self.order.get(self.order_position + 1) {
+                Some(&next) => Some(next),
+                // any other track starts the next round
+                None if repeat == RepeatMode::All && self.tracks.len() > 1 => {
+                    let index = fastrand::usize(..self.tracks.len() - 1);
+                    Some(if index >= self.position { index + 1 } else { index })
+                }
+                None if repeat == RepeatMode::All => Some(self.position),
+                None => None,
+            };
+        }
+
+        match repeat {
+            RepeatMode::One => Some(self.position),
+            _ if self.position + 1 < self.tracks.len() => Some(self.position + 1),
+            RepeatMode::All => Some(0),
+            RepeatMode::Off => None,
+        }
+    }
+
+    fn plan_next(&mut self) {
+        self.next = self.upcoming(self.repeat);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(title: &str) -> QueueTrack {
+        QueueTrack {
+            track_id: 0,
+            title: title.to_string(),
+            artist: String::from("Artist"),
+            album: String::new(),
+            album_url: String::new(),
+            art_id: None,
+            title_link: String::new(),
+            duration: 0.0,
+            file: None,
+        }
+    }
+
+    fn queue(titles: &[&str]) -> Queue {
+        let mut queue = Queue::default();
+        queue.add(titles.iter().map(|title| track(title)).collect());
+        queue
+    }
+
+    fn titles(queue: &Queue) -> Vec<&str> {
+        queue.tracks().iter().map(|track| track.title.as_str()).collect()
+    }
+
+    #[test]
+    fn advances_and_goes_back() {
+        let mut queue = queue(&["a", "b", "c"]);
+        assert_eq!(queue.next_track().unwrap().title, "b");
+
+        assert_eq!(queue.advance().unwrap().title, "b");
+        assert_eq!(queue.advance().unwrap().title, "c");
+        assert!(queue.advance().is_none());
+        assert_eq!(queue.previous().unwrap().title, "b");
+        assert_eq!(queue.previous().unwrap().title, "a");
+        assert!(queue.previous().is_none());
+    }
+
+    #[test]
+    fn repeat_modes() {
+        let mut queue = queue(&["a", "b"]);
+        queue.set_position(1);
+
+        queue.set_repeat(RepeatMode::All);
+        assert_eq!(queue.next_track().unwrap().title, "a");
+
+        queue.set_repeat(RepeatMode::One);
+        assert_eq!(queue.next_track().unwrap().title, "b");
+        assert_eq!(queue.skip_next().unwrap().title, "a");
+    }
+
+    #[test]
+    fn shuffle_plays_every_track_once() {
+        let mut queue = queue(&["a", "b", "c", "d", "e"]);
+        queue.set_shuffle(true);
+
+        let mut played = vec![queue.position()];
+        while queue.advance().is_some() {
+            played.push(queue.position());
+        }
+
+        played.sort_unstable();
+        assert_eq!(played, [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn shuffle_order_survives_previous() {
+        let mut queue = queue(&["a", "b", "c", "d", "e"]);
+        queue.set_shuffle(true);
+
+        let forward: Vec<usize> = (0..3)
+            .map(|_| {
+                queue.advance().unwrap();
+                queue.position()
+            })
+            .collect();
+        queue.previous();
+        queue.previous();
+        assert_eq!(queue.position(), forward[0]);
+
+        queue.advance();
+        queue.advance();
+        assert_eq!(queue.position(), forward[2]);
+    }
+
+    #[test]
+    fn shuffle_repeat_all_starts_new_round() {
+        let mut queue = queue(&["a", "b", "c"]);
+        queue.set_shuffle(true);
+        queue.set_repeat(RepeatMode::All);
+
+        for _ in 0..2 {
+            queue.advance();
+        }
+        let last = queue.position();
+        assert!(queue.advance().is_some());
+        assert_ne!(queue.position(), last);
+        assert_eq!(queue.order_position, 0);
+    }
+
+    #[test]
+    fn shuffle_chosen_track_keeps_rest_of_order() {
+        let mut queue = queue(&["a", "b", "c", "d", "e"]);
+        queue.set_shuffle(true);
+        let rest: Vec<usize> = queue.order[1..].to_vec();
+
+        queue.set_position(rest[3]);
+        assert_eq!(queue.order[2..], rest[..3]);
+
+        queue.add(vec![track("f")]);
+        queue.remove(0);
+        let mut order = queue.order.clone();
+        order.sort_unstable();
+        assert_eq!(order, [0, 1, 2, 3, 4]);
+        assert_eq!(queue.order[queue.order_position], queue.position());
+    }
+
+    #[test]
+    fn insert_next_and_remove_keep_position() {
+        let mut queue = queue(&["a", "b", "c"]);
+        queue.set_position(1);
+        queue.insert_next(vec![track("x"), track("y")]);
+        assert_eq!(titles(&queue), ["a", "b", "x", "y", "c"]);
+        assert_eq!(queue.next_track().unwrap().title, "x");
+
+        queue.remove(0);
+        assert_eq!(queue.current().unwrap().title, "b");
+        queue.remove(queue.position());
+        assert_eq!(queue.current().unwrap().title, "x");
+        assert!(!queue.remove(10));
+    }
+
+    #[test]
+    fn move_keeps_current_track() {
+        let mut queue = queue(&["a", "b", "c", "d"]);
+        queue.set_position(1);
+
+        queue.move_track(0, 3);
+        assert_eq!(titles(&queue), ["b", "c", "d", "a"]);
+        assert_eq!(queue.current().unwrap().title, "b");
+
+        queue.move_track(0, 2);
+        assert_eq!(titles(&queue), ["c", "d", "b", "a"]);
+        assert_eq!(queue.current().unwrap().title, "b");
+        // history points to the moved track "a"
+        assert_eq!(queue.previous().unwrap().title, "a");
+    }
+
+    #[test]
+    fn loads_frontend_queue_file() {
+        let data = r#"{"queue":[{"title":"a","artist":"Artist","art_id":1,"title_link":"https://x.bandcamp.com/track/a","duration":10.5,"file":{"mp3-128":"https://t4.bcbits.com/a"},"track_id":5,"track_num":1}],"position":0,"play_position":42}"#;
+        let path = std::env::temp_dir().join("bc_rs_queue_test.json");
+        std::fs::write(&path, data).unwrap();
+
+        let mut queue = Queue::load(&path);
+        assert_eq!(queue.play_position, 42);
+        assert_eq!(queue.current().unwrap().stream_url(), Some("https://t4.bcbits.com/a"));
+
+        queue.add(vec![track("b"), track("c")]);
+        queue.set_shuffle(true);
+        queue.save(&path).unwrap();
+        let saved = Queue::load(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(saved.shuffle());
+        assert_eq!(saved.tracks().len(), 3);
+        assert_eq!(saved.order, queue.order);
+    }
+}
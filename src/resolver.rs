@@ -0,0 +1,183 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::mpsc,
+    time::Duration,
+};
+
+use crate::{bandcamp::tralbum::Tralbum, clock::now, handlers::config::ProxyConfig, queue::QueueTrack};
+
+/// Stream URLs are refreshed this many seconds before they expire, so a track doesn't expire while it buffers
+const EXPIRY_MARGIN: u64 = 60;
+/// Timeout of the track page request
+const RESOLVE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Expiry of a bcbits stream URL, unix time in its `ts` parameter
+pub fn url_expiry(url: &str) -> Option<u64> {
+    let (_, query) = url.split_once('?')?;
+    query
+        .split('&')
+        .find_map(|param| param.strip_prefix("ts="))
+        .and_then(|ts| ts.parse().ok())
+}
+
+/// `true` if the URL expires within [`EXPIRY_MARGIN`] of `now`, URLs without expiry never do
+pub fn is_expired(url: &str, now: u64) -> bool {
+    url_expiry(url).is_some_and(|expiry| expiry <= now + EXPIRY_MARGIN)
+}
+
+/// Stream URL of `track_id` from its album or track page
+pub fn find_stream_url(html: &str, track_id: u64) -> anyhow::Result<String> {
+    let tralbum = Tralbum::from_html(html)?;
+    let track = tralbum
+        .trackinfo
+        .iter()
+        .find(|track| track.track_id == track_id)
+        .ok_or_else(|| anyhow::anyhow!("Track {} is not on its page anymore", track_id))?;
+
+    track
+        .file
+        .as_ref()
+        .and_then(|file| file.stream_url())
+        .map(String::from)
+        .ok_or_else(|| anyhow::anyhow!("Track {} can't be streamed", track_id))
+}
+
+fn resolve_track(agent: &ureq::Agent, title_link: &str, track_id: u64) -> anyhow::Result<String> {
+    if title_link.is_empty() {
+        anyhow::bail!("Track {} has no page to refresh its stream URL from", track_id);
+    }
+    let html = agent.get(title_link).call()?.into_string()?;
+    find_stream_url(&html, track_id)
+}
+
+/// Refreshes expired stream URLs of queue tracks from their track pages.
+/// Fresh URLs are remembered until they expire too
+pub struct StreamResolver {
+    agent: ureq::Agent,
+    /// Fresh stream URLs by track id
+    urls: HashMap<u64, String>,
+    /// Tracks being resolved in background
+    pending: HashSet<u64>,
+    tx: mpsc::Sender<(u64, anyhow::Result<String>)>,
+    rx: mpsc::Receiver<(u64, anyhow::Result<String>)>,
+}
+
+impl StreamResolver {
+    pub fn new(proxy: &ProxyConfig) -> Self {
+        let (tx, rx) = mpsc::channel();
+        Self {
+            agent: proxy.agent(RESOLVE_TIMEOUT),
+            urls: HashMap::new(),
+            pending: HashSet::new(),
+            tx,
+            rx,
+        }
+    }
+
+    pub fn set_proxy(&mut self, proxy: &ProxyConfig) {
+        self.agent = proxy.agent(RESOLVE_TIMEOUT);
+    }
+
+    /// Stream URL of the track which is not expired yet, from the queue or resolved before
+    pub fn fresh_url(&self, track: &QueueTrack) -> Option<String> {
+        let now = now();
+        track
+            .stream_url()
+            .into_iter()
+            .chain(self.urls.get(&track.track_id).map(String::as_str))
+            .find(|url| !is_expired(url, now))
+            .map(String::from)
+    }
+
+    /// Loads fresh stream URL of the track in background, it's returned by [`StreamResolver::poll`]
+    pub fn resolve_in_background(&mut self, track: &QueueTrack) {
+        if !self.pending.insert(track.track_id) {
+            return;
+        }
+
+        log::info!("Refreshing stream URL of `{}`", track.title);
+        let agent = self.agent.clone();
+        let tx = self.tx.clone();
+        let (title_link, track_id) = (track.title_link.clone(), track.track_id);
+        std::thread::spawn(move || {
+            let _ = tx.send((track_id, resolve_track(&agent, &title_link, track_id)));
+        });
+    }
+
+    /// Stream URLs resolved in background since the last call, as `(track_id, url)`.
+    /// Tracks which couldn't be resolved are returned without URL
+    pub fn poll(&mut self) -> Vec<(u64, Option<String>)> {
+        let mut resolved = Vec::new();
+        while let Ok((track_id, result)) = self.rx.try_recv() {
+            self.pending.remove(&track_id);
+            match result {
+                Ok(url) => {
+                    self.urls.insert(track_id, url.clone());
+                    resolved.push((track_id, Some(url)));
+                }
+                Err(err) => {
+                    log::error!("Unable to refresh stream URL: {}", err);
+                    resolved.push((track_id, None));
+                }
+            }
+        }
+        resolved
+    }
+
+    /// Forgets resolved URLs, so [`StreamResolver::fresh_url`] returns only URLs of the queue
+    pub fn clear(&mut self) {
+        self.urls.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALBUM_PAGE: &str = include_str!("../tests/fixtures/album.html");
+
+    #[test]
+    fn expiry_from_url() {
+        let url = "https://t4.bcbits.com/stream/aaa/mp3-128/1268475939?p=0&ts=1680000000&t=aaa";
+        assert_eq!(url_expiry(url), Some(1680000000));
+        assert!(is_expired(url, 1680000000));
+        assert!(is_expired(url, 1680000000 - EXPIRY_MARGIN));
+        assert!(!is_expired(url, 1679990000));
+
+        // cached or local files have no expiry
+        assert_eq!(url_expiry("https://t4.bcbits.com/stream/aaa/mp3-128/1"), None);
+        assert!(!is_expired("https://t4.bcbits.com/stream/aaa/mp3-128/1", u64::MAX - EXPIRY_MARGIN));
+    }
+
+    #[test]
+    fn finds_track_by_id() {
+        // not the first track of the album
+        let url = find_stream_url(ALBUM_PAGE, 3904154364).unwrap();
+        assert!(url.contains("/mp3-128/3904154364?"));
+
+        assert!(find_stream_url(ALBUM_PAGE, 1).is_err());
+        // listed, but can't be streamed
+        assert!(find_stream_url(ALBUM_PAGE, 155062815).is_err());
+    }
+
+    #[test]
+    fn fresh_url_from_resolved() {
+        let mut resolver = StreamResolver::new(&ProxyConfig::default());
+        let mut track: QueueTrack = serde_json::from_value(serde_json::json!({
+            "track_id": 7,
+            "title": "Track",
+            "art_id": null,
+            "file": { "mp3-128": "https://t4.bcbits.com/stream/a/mp3-128/7?p=0&ts=1&t=a" },
+        }))
+        .unwrap();
+        assert_eq!(resolver.fresh_url(&track), None);
+
+        let fresh = format!("https://t4.bcbits.com/stream/b/mp3-128/7?p=0&ts={}&t=b", now() + 3600);
+        resolver.urls.insert(7, fresh.clone());
+        assert_eq!(resolver.fresh_url(&track), Some(fresh));
+
+        track.file = None;
+        resolver.clear();
+        assert_eq!(resolver.fresh_url(&track), None);
+    }
+}
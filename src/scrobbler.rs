@@ -0,0 +1,468 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::{now, MAX_LISTEN_STEP},
+    handlers::config::{ProxyConfig, ScrobblerConfig},
+};
+
+/// Audioscrobbler API of Last.fm, used when no other API URL is set
+pub const LASTFM_API_URL: &str = "https://ws.audioscrobbler.com/2.0/";
+/// File where scrobbles are kept until they're submitted
+pub const SCROBBLES_FILE: &str = "scrobbles.json";
+
+/// Tracks shorter than this, in seconds, are never scrobbled
+const MIN_DURATION: f64 = 30.0;
+/// Tracks are scrobbled after half of their duration, or after this time for long tracks
+const MAX_THRESHOLD: Duration = Duration::from_secs(4 * 60);
+/// Delay before failed submissions are retried
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Most scrobbles the API accepts in one request
+const BATCH_SIZE: usize = 50;
+
+/// Played track as the API receives it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Scrobble {
+    pub artist: String,
+    pub track: String,
+    pub album: String,
+    /// In seconds
+    pub duration: u32,
+    /// Unix time when the track started playing
+    pub timestamp: u64,
+}
+
+impl Scrobble {
+    /// Request parameters with `[index]` suffix, as batched `track.scrobble` expects
+    fn params(&self, index: Option<usize>) -> Vec<(String, String)> {
+        let name = |param: &str| match index {
+            Some(index) => format!("{}[{}]", param, index),
+            None => param.to_string(),
+        };
+
+        let mut params = vec![
+            (name("artist"), self.artist.clone()),
+            (name("track"), self.track.clone()),
+        ];
+        if !self.album.is_empty() {
+            params.push((name("album"), self.album.clone()));
+        }
+        if self.duration > 0 {
+            params.push((name("duration"), self.duration.to_string()));
+        }
+        if index.is_some() {
+            params.push((name("timestamp"), self.timestamp.to_string()));
+        }
+        params
+    }
+}
+
+/// `api_sig` of a request: MD5 of parameters sorted by name and concatenated with the API secret
+pub fn api_signature(params: &[(String, String)], secret: &str) -> String {
+    let mut params: Vec<&(String, String)> = params
+        .iter()
+        .filter(|(name, _)| name != "format" && name != "callback")
+        .collect();
+    params.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut data: String = params
+        .iter()
+        .map(|(name, value)| format!("{}{}", name, value))
+        .collect();
+    data.push_str(secret);
+    format!("{:x}", md5::compute(data))
+}
+
+/// Listening progress of the current track
+#[derive(Debug, Clone)]
+struct Listen {
+    scrobble: Scrobble,
+    listened: Duration,
+    scrobbled: bool,
+}
+
+impl Listen {
+    fn new(scrobble: Scrobble) -> Self {
+        Self {
+            scrobble,
+            listened: Duration::ZERO,
+            scrobbled: false,
+        }
+    }
+
+    /// Listening time after which the track is scrobbled, `None` for tracks too short to scrobble
+    fn threshold(&self) -> Option<Duration> {
+        let duration = self.scrobble.duration as f64;
+        (duration >= MIN_DURATION)
+            .then(|| Duration::from_secs_f64(duration / 2.0).min(MAX_THRESHOLD))
+    }
+
+    /// Counts listening time, returns `true` once the track has been listened to long enough
+    fn listen(&mut self, elapsed: Duration) -> bool {
+        self.listened += elapsed.min(MAX_LISTEN_STEP);
+        match self.threshold() {
+            Some(threshold) if !self.scrobbled && self.listened >= threshold => {
+                self.scrobbled = true;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Error of an API request
+enum SubmitError {
+    /// Network or server failure, the request can be retried later
+    Temporary(String),
+    /// Request was rejected and would be rejected again
+    Rejected(String),
+}
+
+/// Scrobbles waiting for submission, kept on disk while offline
+fn load_scrobbles(path: &Path) -> Vec<Scrobble> {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|data| {
+            serde_json::from_str(&data)
+                .map_err(|err| log::error!("Unable to parse saved scrobbles: {}", err))
+                .ok()
+        })
+        .unwrap_or_default()
+}
+
+fn save_scrobbles(path: &Path, scrobbles: &[Scrobble]) {
+    let result = if scrobbles.is_empty() {
+        std::fs::remove_file(path).or_else(|err| match err.kind() {
+            std::io::ErrorKind::NotFound => Ok(()),
+            _ => Err(err),
+        })
+    } else {
+        serde_json::to_string(scrobbles)
+            .map_err(std::io::Error::from)
+            .and_then(|data| std::fs::write(path, data))
+    };
+    if let Err(err) = result {
+        log::error!("Unable to save scrobbles: {}", err);
+    }
+}
+
+/// Signed API call, returns the JSON response
+fn call_api(
+    agent: &ureq::Agent,
+    config: &ScrobblerConfig,
+    mut params: Vec<(String, String)>,
+) -> Result<serde_json::Value, SubmitError> {
+    params.push((String::from("api_key"), config.api_key.clone()));
+    let signature = api_signature(&params, &config.api_secret);
+    params.push((String::from("api_sig"), signature));
+    params.push((String::from("format"), String::from("json")));
+
+    let form: Vec<(&str, &str)> = params
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect();
+    let response = match agent.post(config.api_url()).send_form(&form) {
+        Ok(response) => response,
+        Err(ureq::Error::Status(status, response)) => {
+            let message = response
+                .into_string()
+                .ok()
+                .and_then(|body| serde_json::from_str::<serde_json::Value>(&body).ok())
+                .and_then(|json| json["message"].as_str().map(String::from))
+                .unwrap_or_default();
+            let message = format!("status {} {}", status, message);
+            // invalid session or API key can be fixed in settings, scrobbles are kept until then
+            return Err(match status {
+                401 | 403 | 429 | 500.. => SubmitError::Temporary(message),
+                _ => SubmitError::Rejected(message),
+            });
+        }
+        Err(err) => return Err(SubmitError::Temporary(err.to_string())),
+    };
+
+    let json: serde_json::Value = response
+        .into_string()
+        .map_err(|err| err.to_string())
+        .and_then(|body| serde_json::from_str(&body).map_err(|err| err.to_string()))
+        .map_err(SubmitError::Temporary)?;
+    // Last.fm also reports some errors with status 200
+    match json["error"].as_u64() {
+        // service offline or temporarily unavailable
+        Some(code @ (11 | 16)) => Err(SubmitError::Temporary(format!("error {}", code))),
+        Some(code) => Err(SubmitError::Rejected(format!(
+            "error {} {}",
+            code,
+            json["message"].as_str().unwrap_or_default()
+        ))),
+        None => Ok(json),
+    }
+}
+
+/// Exchanges user credentials for a session key with `auth.getMobileSession`
+pub fn login(
+    config: &ScrobblerConfig,
+    proxy: &ProxyConfig,
+    username: &str,
+    password: &str,
+) -> anyhow::Result<String> {
+    let params = vec![
+        (
+            String::from("method"),
+            String::from("auth.getMobileSession"),
+        ),
+        (String::from("username"), username.to_string()),
+        (String::from("password"), password.to_string()),
+    ];
+    let json =
+        call_api(&proxy.agent(REQUEST_TIMEOUT), config, params).map_err(|err| match err {
+            SubmitError::Temporary(message) | SubmitError::Rejected(message) => {
+                anyhow::anyhow!("Login failed: {}", message)
+            }
+        })?;
+
+    json["session"]["key"]
+        .as_str()
+        .map(String::from)
+        .ok_or_else(|| anyhow::anyhow!("Login failed: no session key in response"))
+}
+
+enum Command {
+    NowPlaying(Scrobble),
+    Scrobble(Scrobble),
+    Configure(ScrobblerConfig, ProxyConfig),
+}
+
+/// Submits in background, keeps scrobbles which couldn't be submitted and retries them
+struct Worker {
+    agent: ureq::Agent,
+    config: ScrobblerConfig,
+    path: PathBuf,
+    scrobbles: Vec<Scrobble>,
+}
+
+impl Worker {
+    fn run(mut self, rx: mpsc::Receiver<Command>) {
+        loop {
+            match rx.recv_timeout(RETRY_INTERVAL) {
+                Ok(Command::NowPlaying(scrobble)) => self.now_playing(&scrobble),
+                Ok(Command::Scrobble(scrobble)) => {
+                    self.scrobbles.push(scrobble);
+                    save_scrobbles(&self.path, &self.scrobbles);
+                    self.submit();
+                }
+                Ok(Command::Configure(config, proxy)) => {
+                    self.agent = proxy.agent(REQUEST_TIMEOUT);
+                    self.config = config;
+                    self.submit();
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => self.submit(),
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    }
+
+    /// Now playing status isn't retried, it's outdated by then
+    fn now_playing(&self, scrobble: &Scrobble) {
+        if !self.config.is_ready() {
+            return;
+        }
+
+        let mut params = scrobble.params(None);
+        params.push((
+            String::from("method"),
+            String::from("track.updateNowPlaying"),
+        ));
+        params.push((String::from("sk"), self.config.session_key.clone()));
+        match call_api(&self.agent, &self.config, params) {
+            Ok(_) => log::info!("Now playing `{}` submitted", scrobble.track),
+            Err(SubmitError::Temporary(err) | SubmitError::Rejected(err)) => {
+                log::warn!("Unable to submit now playing: {}", err)
+            }
+        }
+    }
+
+    /// Submits waiting scrobbles in batches, stops at the first failure to retry later
+    fn submit(&mut self) {
+        if !self.config.is_ready() {
+            return;
+        }
+
+        while !self.scrobbles.is_empty() {
+            let count = self.scrobbles.len().min(BATCH_SIZE);
+            let mut params: Vec<(String, String)> = self.scrobbles[..count]
+                .iter()
+                .enumerate()
+                .flat_map(|(index, scrobble)| scrobble.params(Some(index)))
+                .collect();
+            params.push((String::from("method"), String::from("track.scrobble")));
+            params.push((String::from("sk"), self.config.session_key.clone()));
+
+            match call_api(&self.agent, &self.config, params) {
+                Ok(_) => log::info!("{} scrobbles submitted", count),
+                Err(SubmitError::Rejected(err)) => log::error!("Scrobbles rejected: {}", err),
+                Err(SubmitError::Temporary(err)) => {
+                    log::warn!(
+                        "Unable to submit {} scrobbles, retrying later: {}",
+                        self.scrobbles.len(),
+                        err
+                    );
+                    return;
+                }
+            }
+            self.scrobbles.drain(..count);
+            save_scrobbles(&self.path, &self.scrobbles);
+        }
+    }
+}
+
+/// Follows listening of the current track and scrobbles it once it's listened long enough.
+/// Submissions happen on a background thread
+pub struct Scrobbler {
+    tx: mpsc::Sender<Command>,
+    enabled: bool,
+    current: Option<Listen>,
+    last_update: Instant,
+}
+
+impl Scrobbler {
+    /// Starts submitting, including scrobbles saved in `path` while offline
+    pub fn start(config: ScrobblerConfig, proxy: &ProxyConfig, path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let (tx, rx) = mpsc::channel();
+        let worker = Worker {
+            agent: proxy.agent(REQUEST_TIMEOUT),
+            scrobbles: load_scrobbles(&path),
+            path,
+            config: config.clone(),
+        };
+        std::thread::spawn(move || worker.run(rx));
+
+        Self {
+            tx,
+            enabled: config.enabled,
+            current: None,
+            last_update: Instant::now(),
+        }
+    }
+
+    pub fn configure(&mut self, config: ScrobblerConfig, proxy: ProxyConfig) {
+        self.enabled = config.enabled;
+        let _ = self.tx.send(Command::Configure(config, proxy));
+    }
+
+    /// Starts following the track, repeated calls for the same track are ignored
+    pub fn track_started(&mut self, artist: &str, track: &str, album: &str, duration: f64) {
+        if !self.enabled || artist.is_empty() || track.is_empty() {
+            return;
+        }
+        let playing = self.current.as_ref().is_some_and(|listen| {
+            listen.scrobble.artist == artist && listen.scrobble.track == track
+        });
+        if playing {
+            return;
+        }
+
+        let scrobble = Scrobble {
+            artist: artist.to_string(),
+            track: track.to_string(),
+            album: album.to_string(),
+            duration: duration.max(0.0).round() as u32,
+            timestamp: now(),
+        };
+        let _ = self.tx.send(Command::NowPlaying(scrobble.clone()));
+        self.current = Some(Listen::new(scrobble));
+        self.last_update = Instant::now();
+    }
+
+    /// Stops following current track, the next [`Scrobbler::track_started`] starts a new listen
+    pub fn track_ended(&mut self) {
+        self.current = None;
+    }
+
+    /// Counts time since the last update as listened if `playing`, and scrobbles the track when it's due
+    pub fn update(&mut self, playing: bool) {
+        let elapsed = self.last_update.elapsed();
+        self.last_update = Instant::now();
+
+        let Some(listen) = self.current.as_mut().filter(|_| playing) else {
+            return;
+        };
+        if listen.listen(elapsed) {
+            log::info!("Scrobbling `{}`", listen.scrobble.track);
+            let _ = self.tx.send(Command::Scrobble(listen.scrobble.clone()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scrobble(duration: u32) -> Scrobble {
+        Scrobble {
+            artist: String::from("Artist"),
+            track: String::from("Track"),
+            album: String::new(),
+            duration,
+            timestamp: 1680000000,
+        }
+    }
+
+    #[test]
+    fn signs_sorted_params() {
+        let mut params = scrobble(0).params(Some(0));
+        params.push((String::from("method"), String::from("track.scrobble")));
+        params.push((String::from("sk"), String::from("session")));
+        params.push((String::from("api_key"), String::from("key")));
+        params.push((String::from("format"), String::from("json")));
+
+        assert_eq!(
+            api_signature(&params, "secret"),
+            "7ca1e9c297bbdf1ea45f230a2461fe5b"
+        );
+    }
+
+    #[test]
+    fn scrobbles_after_half_or_four_minutes() {
+        let mut listen = Listen::new(scrobble(100));
+        for _ in 0..9 {
+            assert!(!listen.listen(Duration::from_secs(5)));
+        }
+        assert!(listen.listen(Duration::from_secs(5)));
+        // only once
+        assert!(!listen.listen(Duration::from_secs(5)));
+
+        let long = Listen::new(scrobble(3600));
+        assert_eq!(long.threshold(), Some(MAX_THRESHOLD));
+
+        let mut short = Listen::new(scrobble(20));
+        assert_eq!(short.threshold(), None);
+        assert!(!short.listen(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn long_pause_is_not_listening() {
+        let mut listen = Listen::new(scrobble(100));
+        assert!(!listen.listen(Duration::from_secs(3600)));
+        assert_eq!(listen.listened, MAX_LISTEN_STEP);
+    }
+
+    #[test]
+    fn keeps_scrobbles_on_disk() {
+        let path = std::env::temp_dir().join("bc_rs_scrobbles_test.json");
+        let scrobbles = vec![scrobble(100), scrobble(200)];
+
+        save_scrobbles(&path, &scrobbles);
+        assert_eq!(load_scrobbles(&path), scrobbles);
+
+        save_scrobbles(&path, &[]);
+        assert!(!path.exists());
+        assert!(load_scrobbles(&path).is_empty());
+    }
+}